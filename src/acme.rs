@@ -0,0 +1,284 @@
+use crate::config::{AcmeChallenge, AcmeSettings};
+use anyhow::Result;
+use axum::{extract::Path as UrlPath, routing::get, Router};
+use instant_acme::{
+    Account, AuthorizationStatus, ChallengeType, Identifier, LetsEncrypt, NewAccount, NewOrder,
+    OrderStatus,
+};
+use rcgen::{CertificateParams, DistinguishedName, KeyPair};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Let's Encrypt certificates last 90 days; renew with a month to spare.
+const RENEW_AFTER_SECS: u64 = 60 * 24 * 3600;
+
+/// A certificate chain and its private key, as PEM.
+pub struct Certificate {
+    pub cert_pem: String,
+    pub key_pem: String,
+    /// UNIX timestamp after which it should be renewed.
+    pub renew_at: u64,
+}
+
+fn cache_paths(acme: &AcmeSettings, domain: &str) -> (PathBuf, PathBuf, PathBuf) {
+    let dir = PathBuf::from(shellexpand::tilde(&acme.cache_dir).to_string());
+    (
+        dir.join(format!("{}.crt.pem", domain)),
+        dir.join(format!("{}.key.pem", domain)),
+        dir.join(format!("{}.issued", domain)),
+    )
+}
+
+/// The cached certificate for `domain`, unless it's due for renewal.
+fn cached(acme: &AcmeSettings, domain: &str, now: u64) -> Option<Certificate> {
+    let (cert_path, key_path, issued_path) = cache_paths(acme, domain);
+    let issued_at: u64 = std::fs::read_to_string(issued_path)
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    let renew_at = issued_at + RENEW_AFTER_SECS;
+    if now >= renew_at {
+        return None;
+    }
+    Some(Certificate {
+        cert_pem: std::fs::read_to_string(cert_path).ok()?,
+        key_pem: std::fs::read_to_string(key_path).ok()?,
+        renew_at,
+    })
+}
+
+fn store(acme: &AcmeSettings, domain: &str, certificate: &Certificate, now: u64) -> Result<()> {
+    let (cert_path, key_path, issued_path) = cache_paths(acme, domain);
+    if let Some(dir) = cert_path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&cert_path, &certificate.cert_pem)?;
+    write_private(&key_path, &certificate.key_pem)?;
+    std::fs::write(issued_path, now.to_string())?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    use std::{io::Write, os::unix::fs::OpenOptionsExt};
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn write_private(path: &Path, contents: &str) -> Result<()> {
+    std::fs::write(path, contents)?;
+    Ok(())
+}
+
+/// Serves `/.well-known/acme-challenge/<token>` on `acme.http_listen` until dropped.
+struct Http01Responder {
+    stop: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl Http01Responder {
+    async fn start(addr: SocketAddr, key_authorizations: HashMap<String, String>) -> Result<Self> {
+        let key_authorizations = Arc::new(key_authorizations);
+        let router = Router::new().route(
+            "/.well-known/acme-challenge/:token",
+            get(move |UrlPath(token): UrlPath<String>| {
+                let key_authorizations = key_authorizations.clone();
+                async move { key_authorizations.get(&token).cloned().unwrap_or_default() }
+            }),
+        );
+        let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+        let listener = tokio::net::TcpListener::bind(addr)
+            .await
+            .map_err(|err| anyhow::anyhow!("failed to listen on {} for ACME: {}", addr, err))?;
+        let server = axum::serve(listener, router).with_graceful_shutdown(async {
+            let _ = stopped.await;
+        });
+        tokio::spawn(async move {
+            if let Err(err) = server.await {
+                warn!("ACME challenge server error: {}", err);
+            }
+        });
+        Ok(Http01Responder { stop: Some(stop) })
+    }
+}
+
+impl Drop for Http01Responder {
+    fn drop(&mut self) {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+    }
+}
+
+/// Runs `acme.dns_hook` to set (`set`) or remove (`clear`) the `_acme-challenge` TXT record.
+async fn run_dns_hook(hook: &str, action: &str, domain: &str, value: &str) -> Result<()> {
+    let status = tokio::process::Command::new("sh")
+        .args(["-c", hook])
+        .env("AMIBUSSY_ACME_ACTION", action)
+        .env("AMIBUSSY_ACME_NAME", format!("_acme-challenge.{}", domain))
+        .env("AMIBUSSY_ACME_VALUE", value)
+        .status()
+        .await
+        .map_err(|err| anyhow::anyhow!("failed to run the ACME DNS hook: {}", err))?;
+    if !status.success() {
+        anyhow::bail!(
+            "the ACME DNS hook failed to {} the record: {}",
+            action,
+            status
+        );
+    }
+    Ok(())
+}
+
+/// Orders a certificate for `domain` from Let's Encrypt, answering the challenge over HTTP
+/// on `acme.http_listen` or with a TXT record set by `acme.dns_hook`.
+async fn issue(domain: &str, acme: &AcmeSettings, now: u64) -> Result<Certificate> {
+    let directory = if acme.staging {
+        LetsEncrypt::Staging.url()
+    } else {
+        LetsEncrypt::Production.url()
+    };
+    let contact = acme.email.as_ref().map(|email| format!("mailto:{}", email));
+    let contact: Vec<&str> = contact.iter().map(String::as_str).collect();
+    let (account, _credentials) = Account::create(
+        &NewAccount {
+            contact: &contact,
+            terms_of_service_agreed: true,
+            only_return_existing: false,
+        },
+        directory,
+        None,
+    )
+    .await?;
+
+    let mut order = account
+        .new_order(&NewOrder {
+            identifiers: &[Identifier::Dns(domain.to_string())],
+        })
+        .await?;
+    let challenge_type = match acme.challenge {
+        AcmeChallenge::Http01 => ChallengeType::Http01,
+        AcmeChallenge::Dns01 => ChallengeType::Dns01,
+    };
+
+    let mut ready = Vec::new();
+    let mut key_authorizations = HashMap::new();
+    let mut dns_values = Vec::new();
+    for authorization in order.authorizations().await? {
+        match authorization.status {
+            AuthorizationStatus::Pending => {}
+            AuthorizationStatus::Valid => continue,
+            status => anyhow::bail!("ACME authorization is {:?}", status),
+        }
+        let challenge = authorization
+            .challenges
+            .iter()
+            .find(|challenge| challenge.r#type == challenge_type)
+            .ok_or_else(|| anyhow::anyhow!("no {:?} challenge offered", challenge_type))?;
+        let key_authorization = order.key_authorization(challenge);
+        key_authorizations.insert(
+            challenge.token.clone(),
+            key_authorization.as_str().to_string(),
+        );
+        dns_values.push(key_authorization.dns_value());
+        ready.push(challenge.url.clone());
+    }
+
+    let _responder = match acme.challenge {
+        AcmeChallenge::Http01 => {
+            let addr = acme
+                .http_listen
+                .parse()
+                .map_err(|err| anyhow::anyhow!("invalid acme.http_listen: {}", err))?;
+            Some(Http01Responder::start(addr, key_authorizations).await?)
+        }
+        AcmeChallenge::Dns01 => None,
+    };
+    let dns_hook = match acme.challenge {
+        AcmeChallenge::Dns01 => Some(
+            acme.dns_hook
+                .as_deref()
+                .ok_or_else(|| anyhow::anyhow!("dns-01 needs acme.dns_hook"))?,
+        ),
+        AcmeChallenge::Http01 => None,
+    };
+    if let Some(hook) = dns_hook {
+        for value in &dns_values {
+            run_dns_hook(hook, "set", domain, value).await?;
+        }
+        // Give the record a moment to reach the authoritative servers.
+        tokio::time::sleep(Duration::from_secs(acme.dns_propagation_secs)).await;
+    }
+
+    for url in &ready {
+        order.set_challenge_ready(url).await?;
+    }
+    let mut delay = Duration::from_secs(1);
+    let status = loop {
+        tokio::time::sleep(delay).await;
+        let status = order.refresh().await?.status;
+        if matches!(status, OrderStatus::Ready | OrderStatus::Invalid) || delay.as_secs() > 60 {
+            break status;
+        }
+        delay *= 2;
+    };
+    if let Some(hook) = dns_hook {
+        for value in &dns_values {
+            run_dns_hook(hook, "clear", domain, value).await?;
+        }
+    }
+    if status != OrderStatus::Ready {
+        anyhow::bail!("ACME order for {} ended up {:?}", domain, status);
+    }
+
+    let mut params = CertificateParams::new(vec![domain.to_string()])?;
+    params.distinguished_name = DistinguishedName::new();
+    let key = KeyPair::generate()?;
+    let csr = params.serialize_request(&key)?;
+    order.finalize(csr.der()).await?;
+    let cert_pem = loop {
+        match order.certificate().await? {
+            Some(cert_pem) => break cert_pem,
+            None => tokio::time::sleep(Duration::from_secs(1)).await,
+        }
+    };
+
+    Ok(Certificate {
+        cert_pem,
+        key_pem: key.serialize_pem(),
+        renew_at: now + RENEW_AFTER_SECS,
+    })
+}
+
+/// A certificate for `domain`: the cached one if it's not due for renewal, otherwise a new
+/// one from Let's Encrypt, which is cached in `acme.cache_dir`.
+pub async fn obtain_certificate(
+    domain: &str,
+    acme: &AcmeSettings,
+    now: u64,
+) -> Result<Certificate> {
+    if let Some(certificate) = cached(acme, domain, now) {
+        info!("Using the cached certificate for {}", domain);
+        return Ok(certificate);
+    }
+    info!("Requesting a certificate for {} from Let's Encrypt", domain);
+    let certificate = issue(domain, acme, now)
+        .await
+        .map_err(|err| anyhow::anyhow!("ACME failed for {}: {}", domain, err))?;
+    store(acme, domain, &certificate, now)?;
+    info!("Got a certificate for {}", domain);
+    Ok(certificate)
+}
@@ -0,0 +1,393 @@
+use crate::{
+    clock::local_time,
+    config::{AutoReplySettings, Settings, StatusPanelSettings},
+    control::handle_command,
+    i18n::Text,
+    presence::{emit_and_wait, PresenceEvent},
+    sinks::telegram::{
+        answer_callback_query, edit_message_with_markup, send_message_with_markup, send_reply,
+    },
+    state::{AppState, Status, StatusSource},
+    telegram::{call, CallbackQuery, Message, TelegramError, Update, User},
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{atomic::Ordering, Arc, PoisonError},
+    time::Duration,
+};
+use tracing::{error, info, warn};
+
+/// How long a `getUpdates` call waits for something to happen.
+const POLL_TIMEOUT_SECS: u64 = 30;
+/// Pause after a failed `getUpdates` before trying again.
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Whether any setting needs the bot to read the chat.
+pub fn needs_updates(settings: &Settings) -> bool {
+    settings.auto_reply.is_some()
+        || settings.status_panel.is_some()
+        || !settings.allowed_user_ids.is_empty()
+}
+
+/// What the bot remembers between updates.
+#[derive(Default)]
+struct BotState {
+    me: Option<User>,
+    /// When each user last got an auto-reply.
+    replied_at: HashMap<i64, u64>,
+}
+
+/// Long-polls Telegram for messages to the bot and in the status chat and reacts to them.
+/// Telegram refuses `getUpdates` while a webhook is set for the bot.
+pub async fn bot_updates(state: AppState, shutdown_signal: Arc<tokio::sync::Notify>) {
+    let client = Client::new();
+    let mut bot = BotState::default();
+    let mut offset = 0;
+
+    if state.settings().status_panel.is_some() {
+        if let Err(err) = update_status_panel(&client, &state).await {
+            error!("Failed to set up the status panel: {}", err);
+            state
+                .diagnostics
+                .error("sink.status_panel", err.to_string());
+        }
+    }
+
+    loop {
+        let settings = state.settings();
+        let poll = async {
+            if bot.me.is_none() {
+                bot.me = Some(call(&client, &settings.bot_token, "getMe", &json!({})).await?);
+            }
+            let payload = json!({
+                "offset": offset,
+                "timeout": POLL_TIMEOUT_SECS,
+                "allowed_updates": ["message", "callback_query"]
+            });
+            call::<Vec<Update>>(&client, &settings.bot_token, "getUpdates", &payload).await
+        };
+        let updates = tokio::select! {
+            updates = poll => updates,
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down bot_updates");
+                break;
+            }
+        };
+
+        let updates = match updates {
+            Ok(updates) => updates,
+            Err(err) => {
+                error!("Failed to get Telegram updates: {}", err);
+                state
+                    .diagnostics
+                    .error("source.telegram_updates", err.to_string());
+                if matches!(err, TelegramError::Unauthorized { .. }) {
+                    break;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(RETRY_DELAY) => continue,
+                    _ = shutdown_signal.notified() => break,
+                }
+            }
+        };
+        state
+            .diagnostics
+            .ok("source.telegram_updates", "polling for messages");
+
+        for update in updates {
+            offset = offset.max(update.update_id + 1);
+            if let Some(message) = update.message {
+                handle_message(&client, &state, &mut bot, &message).await;
+            }
+            if let Some(callback) = update.callback_query {
+                handle_panel_press(&client, &state, &callback).await;
+            }
+        }
+    }
+}
+
+async fn handle_message(client: &Client, state: &AppState, bot: &mut BotState, message: &Message) {
+    let settings = state.settings();
+    if let Some(command) = command_line(bot, message) {
+        run_command(client, state, message, &command).await;
+        return;
+    }
+    if let Some(auto_reply) = &settings.auto_reply {
+        auto_reply_to(client, state, bot, auto_reply, message).await;
+    }
+}
+
+/// The control command in a `/command args` message, e.g. `set busy 30m` for `/set busy 30m`
+/// or `/set@amibussy_bot busy 30m`. Commands addressed to other bots are `None`.
+fn command_line(bot: &BotState, message: &Message) -> Option<String> {
+    let text = message.text.as_deref()?.strip_prefix('/')?;
+    let (command, args) = text.split_once(char::is_whitespace).unwrap_or((text, ""));
+    let command = match command.split_once('@') {
+        Some((command, username)) => {
+            let me = bot.me.as_ref()?.username.as_deref()?;
+            if !username.eq_ignore_ascii_case(me) {
+                return None;
+            }
+            command
+        }
+        None => command,
+    };
+    Some(format!("{} {}", command, args).trim().to_string())
+}
+
+/// Runs a bot command as a control command, for the users in `allowed_user_ids`.
+async fn run_command(client: &Client, state: &AppState, message: &Message, command: &str) {
+    let settings = state.settings();
+    let (Some(chat), Some(from)) = (&message.chat, &message.from) else {
+        return;
+    };
+    if settings.allowed_user_ids.is_empty() {
+        return;
+    }
+    let reply = if !settings.is_allowed_user(from.id, false) {
+        warn!(
+            "Refusing bot command {:?} from {}, who isn't in allowed_user_ids",
+            command, from.id
+        );
+        settings.text(Text::Refusal).to_string()
+    } else {
+        info!("[BOT] {} ran {:?}", from.id, command);
+        match handle_command(state, command).await {
+            Ok(reply) => reply,
+            Err(err) => format!("error: {}", err),
+        }
+    };
+    if settings.dry_run {
+        info!("[DRY RUN] Not replying to {}: {:?}", from.id, reply);
+        return;
+    }
+    if let Err(err) = send_reply(client, &settings, chat.id, message.message_id, &reply).await {
+        error!("Failed to reply to a bot command: {}", err);
+    }
+}
+
+/// Replies with `auto_reply.message` while Busy, once per cooldown per person.
+async fn auto_reply_to(
+    client: &Client,
+    state: &AppState,
+    bot: &mut BotState,
+    auto_reply: &AutoReplySettings,
+    message: &Message,
+) {
+    let settings = state.settings();
+    let (Some(chat), Some(from)) = (&message.chat, &message.from) else {
+        return;
+    };
+    if from.is_bot || !matches!(state.decide().status, Status::Busy | Status::ForgotTimer) {
+        return;
+    }
+    let addressed = if chat.kind == "private" {
+        true
+    } else if chat.is(&settings.chat_id) {
+        is_addressed(bot, auto_reply, message)
+    } else {
+        false
+    };
+    if !addressed {
+        return;
+    }
+
+    let now = state.clock.now();
+    let cooldown = auto_reply.cooldown_minutes * 60;
+    if bot
+        .replied_at
+        .get(&from.id)
+        .is_some_and(|&at| now.saturating_sub(at) < cooldown)
+    {
+        return;
+    }
+    bot.replied_at.insert(from.id, now);
+
+    let text = state.render_template(&auto_reply.message);
+    if settings.dry_run {
+        info!("[DRY RUN] Not auto-replying to {}: {:?}", from.id, text);
+        return;
+    }
+    match send_reply(client, &settings, chat.id, message.message_id, &text).await {
+        Ok(()) => {
+            info!("Auto-replied to {} in {}", from.id, chat.id);
+            state.diagnostics.ok("sink.auto_reply", "replied");
+        }
+        Err(err) => {
+            error!("Failed to auto-reply: {}", err);
+            state.diagnostics.error("sink.auto_reply", err.to_string());
+        }
+    }
+}
+
+/// Whether a message in the status chat mentions one of `auto_reply.mentions` or the bot, or
+/// replies to the bot.
+fn is_addressed(bot: &BotState, auto_reply: &AutoReplySettings, message: &Message) -> bool {
+    let me = bot.me.as_ref();
+    let replies_to_bot = message
+        .reply_to_message
+        .as_ref()
+        .and_then(|replied| replied.from.as_ref())
+        .zip(me)
+        .is_some_and(|(author, me)| author.id == me.id);
+    let text = message.text.as_deref().unwrap_or_default().to_lowercase();
+    let bot_mention = me
+        .and_then(|me| me.username.as_ref())
+        .map(|username| format!("@{}", username));
+    replies_to_bot
+        || auto_reply
+            .mentions
+            .iter()
+            .chain(&bot_mention)
+            .any(|mention| text.contains(&mention.to_lowercase()))
+}
+
+/// The panel's buttons: a row of statuses to pin, then extending and dropping the pin.
+fn panel_keyboard(settings: &Settings, panel: &StatusPanelSettings) -> Value {
+    let extend = settings
+        .text(Text::PanelExtend)
+        .replace("{minutes}", &panel.extend_minutes.to_string());
+    json!({
+        "inline_keyboard": [
+            [
+                { "text": settings.text(Text::PanelBusy), "callback_data": "set:busy" },
+                { "text": settings.text(Text::PanelBreak), "callback_data": "set:break" },
+                { "text": settings.text(Text::PanelOff), "callback_data": "set:not_working" }
+            ],
+            [
+                { "text": extend, "callback_data": "extend" },
+                { "text": settings.text(Text::PanelAuto), "callback_data": "clear" }
+            ]
+        ]
+    })
+}
+
+fn panel_text(state: &AppState, panel: &StatusPanelSettings) -> String {
+    let settings = state.settings();
+    let template = panel
+        .template
+        .as_deref()
+        .unwrap_or(settings.text(Text::StatusMessage));
+    let mut text = state.render_template(template);
+    if state.opinion(StatusSource::Override).is_some() {
+        text.push('\n');
+        let until = state.override_until.load(Ordering::Relaxed);
+        if until == 0 {
+            text.push_str(settings.text(Text::Pinned));
+        } else {
+            let until = local_time(settings.timezone, until)
+                .format("%H:%M")
+                .to_string();
+            text.push_str(&settings.text(Text::PinnedUntil).replace("{until}", &until));
+        }
+    }
+    text
+}
+
+/// Edits the status panel to show the current status, sending it first if we don't have it.
+pub async fn update_status_panel(client: &Client, state: &AppState) -> Result<()> {
+    let settings = state.settings();
+    let Some(panel) = &settings.status_panel else {
+        return Ok(());
+    };
+    if settings.dry_run {
+        info!("[DRY RUN] Not updating the status panel");
+        return Ok(());
+    }
+    let chat_id = panel.chat_id.as_deref().unwrap_or(&settings.chat_id);
+    let text = panel_text(state, panel);
+    let keyboard = panel_keyboard(&settings, panel);
+
+    let message_id = *state
+        .status_panel_message_id
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    match message_id {
+        Some(message_id) => {
+            edit_message_with_markup(client, &settings, chat_id, message_id, &text, &keyboard).await
+        }
+        None => {
+            let message_id =
+                send_message_with_markup(client, &settings, chat_id, &text, &keyboard).await?;
+            info!(
+                "Sent a new status panel. Set status_panel.message_id: {} to keep using it after restarts.",
+                message_id
+            );
+            *state
+                .status_panel_message_id
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(message_id);
+            Ok(())
+        }
+    }
+}
+
+/// What pressing the button with `data` pins, if it's one of ours.
+fn panel_event(state: &AppState, panel: &StatusPanelSettings, data: &str) -> Option<PresenceEvent> {
+    let now = state.clock.now();
+    match data.split_once(':') {
+        Some(("set", status)) => Some(PresenceEvent::Override {
+            status: Some(status.parse().ok()?),
+            until: None,
+        }),
+        _ if data == "extend" => {
+            // Extends a pin that runs out, or pins the current status for a while.
+            let status = state
+                .opinion(StatusSource::Override)
+                .unwrap_or(state.decide().status);
+            let until = state.override_until.load(Ordering::Relaxed);
+            Some(PresenceEvent::Override {
+                status: Some(status),
+                until: Some(until.max(now) + panel.extend_minutes * 60),
+            })
+        }
+        _ if data == "clear" => Some(PresenceEvent::Override {
+            status: None,
+            until: None,
+        }),
+        _ => None,
+    }
+}
+
+/// Applies a status panel button press from someone in `allowed_user_ids`.
+async fn handle_panel_press(client: &Client, state: &AppState, callback: &CallbackQuery) {
+    let settings = state.settings();
+    let Some(panel) = &settings.status_panel else {
+        return;
+    };
+    let answer = if !settings.is_allowed_user(callback.from.id, true) {
+        warn!(
+            "Ignoring a status panel press from {}, who isn't in allowed_user_ids",
+            callback.from.id
+        );
+        settings.text(Text::Refusal).to_string()
+    } else {
+        match callback
+            .data
+            .as_deref()
+            .and_then(|data| panel_event(state, panel, data))
+        {
+            Some(event) => {
+                info!("[PANEL] {} pressed {:?}", callback.from.id, callback.data);
+                emit_and_wait(state, event).await;
+                // The title may not change (e.g. extending), but the panel shows the pin.
+                if let Err(err) = update_status_panel(client, state).await {
+                    error!("Failed to update the status panel: {}", err);
+                    state
+                        .diagnostics
+                        .error("sink.status_panel", err.to_string());
+                }
+                settings
+                    .text(Text::PanelDone)
+                    .replace("{title}", &state.effective_title())
+            }
+            None => settings.text(Text::UnknownButton).to_string(),
+        }
+    };
+    if let Err(err) = answer_callback_query(client, &settings, &callback.id, &answer).await {
+        warn!("Failed to answer a status panel press: {}", err);
+    }
+}
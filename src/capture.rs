@@ -0,0 +1,123 @@
+use crate::{
+    config::{DebugSettings, Settings},
+    state::{get_unix_timestamp, AppState},
+};
+use axum::{
+    body::{to_bytes, Body},
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::StatusCode;
+use serde_json::{json, Map, Value};
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::{Mutex, PoisonError},
+};
+use tracing::warn;
+
+/// Headers that carry credentials; `webhook_auth.header` is added to them.
+const SECRET_HEADERS: [&str; 3] = ["authorization", "proxy-authorization", "cookie"];
+
+/// Serializes writes, so that two requests don't both rotate the file.
+static CAPTURE_LOCK: Mutex<()> = Mutex::new(());
+
+/// With `debug.capture_payloads`, appends every webhook that got past `webhook_auth` to
+/// `debug.capture_file`, with its headers and exact body, before the handler parses it.
+pub async fn capture_payload(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let settings = state.settings();
+    if !settings.debug.capture_payloads {
+        return next.run(request).await;
+    }
+    let (parts, body) = request.into_parts();
+    let body = match to_bytes(body, settings.webhook_limits.max_body_bytes).await {
+        Ok(body) => body,
+        Err(err) => {
+            warn!("Failed to read a webhook body to capture: {}", err);
+            return StatusCode::PAYLOAD_TOO_LARGE.into_response();
+        }
+    };
+    let line = json!({
+        "at": get_unix_timestamp().unwrap_or_default(),
+        "method": parts.method.as_str(),
+        "path": redact_path(parts.uri.path()),
+        "headers": redacted_headers(&settings, &parts.headers),
+        "body": String::from_utf8_lossy(&body),
+    });
+    append_capture(&settings.debug, &line);
+    next.run(Request::from_parts(parts, Body::from(body))).await
+}
+
+/// The path without the `webhook_auth.path_token`.
+fn redact_path(path: &str) -> &str {
+    if path.starts_with("/webhook/") {
+        "/webhook/<redacted>"
+    } else {
+        path
+    }
+}
+
+fn redacted_headers(settings: &Settings, headers: &HeaderMap) -> Value {
+    let auth_header = settings
+        .webhook_auth
+        .as_ref()
+        .and_then(|auth| auth.header.as_deref());
+    let mut redacted = Map::new();
+    for (name, value) in headers {
+        let secret = SECRET_HEADERS.contains(&name.as_str())
+            || auth_header.is_some_and(|header| name.as_str().eq_ignore_ascii_case(header));
+        let value = if secret {
+            "<redacted>".to_string()
+        } else {
+            String::from_utf8_lossy(value.as_bytes()).into_owned()
+        };
+        redacted.insert(name.as_str().to_string(), Value::String(value));
+    }
+    Value::Object(redacted)
+}
+
+/// `capture_file.1` is the newest rotated file, `capture_file.<max_files - 1>` the oldest.
+fn rotated(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}", index));
+    PathBuf::from(name)
+}
+
+/// Appends `line`, first rotating the file if it has grown past `capture_max_bytes`.
+fn append_capture(debug: &DebugSettings, line: &Value) {
+    let path = PathBuf::from(shellexpand::tilde(&debug.capture_file).to_string());
+    let _guard = CAPTURE_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+    let full = std::fs::metadata(&path).is_ok_and(|meta| meta.len() >= debug.capture_max_bytes);
+    if full {
+        for index in (1..debug.capture_max_files).rev() {
+            let from = if index == 1 {
+                path.clone()
+            } else {
+                rotated(&path, index - 1)
+            };
+            let _ = std::fs::rename(from, rotated(&path, index));
+        }
+        if debug.capture_max_files <= 1 {
+            let _ = std::fs::remove_file(&path);
+        }
+    }
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(err) = result {
+        warn!(
+            "Failed to capture the webhook to {}: {}",
+            path.display(),
+            err
+        );
+    }
+}
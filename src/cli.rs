@@ -0,0 +1,243 @@
+use crate::{
+    clock::local_time,
+    config::Settings,
+    history::History,
+    out_of_office::{OutOfOffice, OutOfOfficeRequest},
+    report::{build_report, report_days},
+    server::StatusReport,
+    sinks::telegram::send_message,
+    state::get_unix_timestamp,
+    summary::format_duration,
+};
+use anyhow::Result;
+use chrono_tz::Tz;
+use clap::{Parser, Subcommand};
+use reqwest::Client;
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    /// Settings file to use instead of searching for one.
+    #[arg(long, global = true, env = "AMIBUSSY_CONFIG")]
+    pub config: Option<PathBuf>,
+    /// Process events and log transitions, but don't touch Telegram or any other sink.
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Ask the running daemon for its current status.
+    Status {
+        /// Print every subsystem with its details and last error.
+        #[arg(long)]
+        verbose: bool,
+        /// Print the raw report as JSON.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Busy, break and AFK totals for today from the status history.
+    Report {
+        /// The whole current week, from Monday.
+        #[arg(long)]
+        week: bool,
+        /// Post it to Telegram (`weekly_report.chat_id` or `chat_id`) instead of printing it.
+        #[arg(long)]
+        post: bool,
+    },
+    /// Log in to Microsoft Teams (device code flow) for the `teams` sink.
+    TeamsLogin,
+    /// Log in to your own Telegram account for `deep_focus.telegram_user`.
+    TelegramLogin,
+    /// Install and start a user-level systemd unit (Linux) or launchd agent (macOS).
+    InstallService {
+        /// Print the unit instead of installing it.
+        #[arg(long)]
+        print: bool,
+    },
+    /// Go out of office: pin a Not Working status with its own title and ignore Toggl until
+    /// the end of `--until`.
+    Ooo {
+        /// The last day off, as YYYY-MM-DD.
+        #[arg(long, required_unless_present = "end")]
+        until: Option<chrono::NaiveDate>,
+        /// Defaults to `out_of_office.title`.
+        #[arg(long)]
+        title: Option<String>,
+        /// Disable the Toggl subscription meanwhile.
+        #[arg(long)]
+        pause_subscription: bool,
+        /// Come back early.
+        #[arg(long, conflicts_with_all = ["until", "title", "pause_subscription"])]
+        end: bool,
+    },
+    /// Freeze Telegram and the other sinks, e.g. while sharing the screen. The status is still
+    /// tracked, and `resume` brings them up to date.
+    Pause,
+    /// Unfreeze the sinks after `pause`.
+    Resume,
+    /// Feed recorded webhooks (see `record_events`) through the pipeline in dry run.
+    Replay {
+        /// The JSONL file `record_events` wrote.
+        events: PathBuf,
+        /// How many times faster than real time to replay.
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+        speed: u64,
+    },
+    /// Entry point for the Windows service control manager; see `install-service`.
+    #[command(hide = true)]
+    ServiceRun,
+}
+
+fn format_timestamp(timezone: Option<Tz>, timestamp: u64) -> String {
+    local_time(timezone, timestamp)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+pub async fn print_status(settings: &Settings, verbose: bool, as_json: bool) -> Result<()> {
+    let url = format!("http://{}/status", settings.control_addr);
+    let report: StatusReport = Client::new()
+        .get(&url)
+        .send()
+        .await
+        .map_err(|err| anyhow::anyhow!("is amibussy running? ({})", err))?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    if as_json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+        return Ok(());
+    }
+
+    println!(
+        "Status: {:?} ({:?}), title {:?}, for {}",
+        report.status,
+        report.decided_by,
+        report.title,
+        format_duration(get_unix_timestamp()?.saturating_sub(report.since))
+    );
+    if let Some(entry) = &report.entry {
+        println!("Entry: {}", entry);
+    }
+    if report.paused {
+        println!("Sinks: paused, `amibussy resume` to update them");
+    }
+    match report.subsystems.get("tunnel") {
+        Some(tunnel) if tunnel.healthy => println!("Tunnel: ok - {}", tunnel.detail),
+        Some(tunnel) => println!(
+            "Tunnel: FAILING - {}",
+            tunnel
+                .last_error
+                .as_ref()
+                .map_or(tunnel.detail.as_str(), |error| error.message.as_str())
+        ),
+        None => println!("Tunnel: not checked yet"),
+    }
+
+    let unhealthy = report
+        .subsystems
+        .values()
+        .filter(|health| !health.healthy)
+        .count();
+    if !verbose {
+        println!(
+            "Subsystems: {} ok, {} failing",
+            report.subsystems.len() - unhealthy,
+            unhealthy
+        );
+        return Ok(());
+    }
+
+    println!("Subsystems:");
+    for (name, health) in &report.subsystems {
+        let mark = if health.healthy { "ok  " } else { "FAIL" };
+        println!(
+            "  [{}] {} - {} (at {})",
+            mark,
+            name,
+            health.detail,
+            format_timestamp(settings.timezone, health.updated_at)
+        );
+        if let Some(last_error) = &health.last_error {
+            println!(
+                "         last error at {}: {}",
+                format_timestamp(settings.timezone, last_error.at),
+                last_error.message
+            );
+        }
+    }
+    Ok(())
+}
+
+pub async fn print_report(settings: &Settings, week: bool, post: bool) -> Result<()> {
+    let history = History::open(shellexpand::tilde(&settings.history_db).to_string())?;
+    let client = Client::new();
+    let now = get_unix_timestamp()?;
+    let days = report_days(settings, now, week);
+    let text = build_report(&client, settings, &history, &days, now).await?;
+    if !post {
+        println!("{}", text);
+        return Ok(());
+    }
+    let chat_id = settings
+        .weekly_report
+        .as_ref()
+        .and_then(|report| report.chat_id.as_deref())
+        .unwrap_or(&settings.chat_id);
+    send_message(&client, settings, chat_id, &text).await;
+    Ok(())
+}
+
+/// Asks the running daemon to go out of office through `until`, or with `None` to come back.
+pub async fn set_out_of_office(
+    settings: &Settings,
+    request: Option<OutOfOfficeRequest>,
+) -> Result<()> {
+    let url = format!("http://{}/ooo", settings.control_addr);
+    let client = Client::new();
+    let request = match &request {
+        Some(request) => client.post(&url).json(request),
+        None => client.delete(&url),
+    };
+    let response = request
+        .send()
+        .await
+        .map_err(|err| anyhow::anyhow!("is amibussy running? ({})", err))?;
+    if !response.status().is_success() {
+        anyhow::bail!("{}", response.text().await?);
+    }
+    match response.json::<Option<OutOfOffice>>().await? {
+        Some(ooo) => println!(
+            "Out of office until {} as {:?}",
+            format_timestamp(settings.timezone, ooo.until),
+            ooo.title
+        ),
+        None => println!("Back in the office"),
+    }
+    Ok(())
+}
+
+/// Asks the running daemon to freeze the sinks, or with `false` to bring them back.
+pub async fn set_paused(settings: &Settings, paused: bool) -> Result<()> {
+    let action = if paused { "pause" } else { "resume" };
+    let url = format!("http://{}/{}", settings.control_addr, action);
+    let paused: bool = Client::new()
+        .post(&url)
+        .send()
+        .await
+        .map_err(|err| anyhow::anyhow!("is amibussy running? ({})", err))?
+        .error_for_status()?
+        .json()
+        .await?;
+    if paused {
+        println!("Sinks paused");
+    } else {
+        println!("Sinks resumed");
+    }
+    Ok(())
+}
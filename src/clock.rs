@@ -0,0 +1,113 @@
+use crate::error::Error;
+use chrono_tz::Tz;
+use std::{
+    fmt::Debug,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        OnceLock,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing_subscriber::fmt::{format::Writer, time::FormatTime};
+
+/// Source of "now" as UNIX seconds, so time-dependent logic (AFK, long entries, day rollover)
+/// can be driven by a fake clock instead of waiting for the real one.
+pub trait Clock: Debug + Send + Sync {
+    /// Now, or `Error::Clock` if the clock can't tell.
+    fn try_now(&self) -> Result<u64, Error>;
+
+    /// Now, for callers that have to go on either way: while the clock can't tell, the last
+    /// time it could.
+    fn now(&self) -> u64;
+}
+
+/// The real wall clock.
+#[derive(Debug, Default)]
+pub struct SystemClock {
+    /// The latest good reading.
+    last: AtomicU64,
+}
+
+impl Clock for SystemClock {
+    fn try_now(&self) -> Result<u64, Error> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.last.fetch_max(now, Ordering::Relaxed);
+        Ok(now)
+    }
+
+    fn now(&self) -> u64 {
+        self.try_now()
+            .unwrap_or_else(|_| self.last.load(Ordering::Relaxed))
+    }
+}
+
+/// A clock that only moves when told to. Pair it with `tokio::time::pause` to fast-forward
+/// the background tasks' intervals as well.
+#[derive(Debug, Default)]
+pub struct ManualClock {
+    now: AtomicU64,
+}
+
+impl ManualClock {
+    pub fn new(now: u64) -> Self {
+        ManualClock {
+            now: AtomicU64::new(now),
+        }
+    }
+
+    pub fn set(&self, now: u64) {
+        self.now.store(now, Ordering::Relaxed);
+    }
+
+    pub fn advance(&self, secs: u64) {
+        self.now.fetch_add(secs, Ordering::Relaxed);
+    }
+}
+
+impl Clock for ManualClock {
+    fn try_now(&self) -> Result<u64, Error> {
+        Ok(self.now())
+    }
+
+    fn now(&self) -> u64 {
+        self.now.load(Ordering::Relaxed)
+    }
+}
+
+/// Wall-clock time of `timestamp` in `timezone`, or in the system's local time if it's unset.
+pub fn local_time(timezone: Option<Tz>, timestamp: u64) -> chrono::NaiveDateTime {
+    let utc = chrono::DateTime::from_timestamp(timestamp as i64, 0).unwrap_or_default();
+    match timezone {
+        Some(timezone) => utc.with_timezone(&timezone).naive_local(),
+        None => utc.with_timezone(&chrono::Local).naive_local(),
+    }
+}
+
+/// The UNIX timestamp of a wall-clock time in `timezone` (the earlier one across a DST change).
+pub fn timestamp_of(timezone: Option<Tz>, time: chrono::NaiveDateTime) -> Option<u64> {
+    let timestamp = match timezone {
+        Some(timezone) => time.and_local_timezone(timezone).earliest()?.timestamp(),
+        None => time
+            .and_local_timezone(chrono::Local)
+            .earliest()?
+            .timestamp(),
+    };
+    u64::try_from(timestamp).ok()
+}
+
+/// Timezone for log timestamps, set once the settings are loaded. Logs are in UTC until then.
+pub static LOG_TIMEZONE: OnceLock<Tz> = OnceLock::new();
+
+/// Formats log timestamps in `LOG_TIMEZONE`.
+pub struct LogTimer;
+
+impl FormatTime for LogTimer {
+    fn format_time(&self, writer: &mut Writer<'_>) -> std::fmt::Result {
+        const FORMAT: &str = "%Y-%m-%dT%H:%M:%S%.6f%:z";
+        let now = chrono::Utc::now();
+        match LOG_TIMEZONE.get() {
+            Some(timezone) => write!(writer, "{}", now.with_timezone(timezone).format(FORMAT)),
+            None => write!(writer, "{}", now.format("%Y-%m-%dT%H:%M:%S%.6fZ")),
+        }
+    }
+}
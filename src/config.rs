@@ -0,0 +1,2768 @@
+use crate::{
+    error::Error,
+    i18n::{Locale, Text},
+    logging::valid_log_level,
+    state::{default_precedence, Status, StatusSource},
+    telegram::{title_len, MAX_TITLE_LEN},
+    theme::{load_theme, Theme},
+};
+use config::{Config, Environment, File};
+use std::{
+    collections::HashMap,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Settings {
+    /// The file these settings were loaded from, if any.
+    #[serde(skip)]
+    pub config_path: Option<PathBuf>,
+    #[serde(deserialize_with = "secret")]
+    pub bot_token: String,
+    #[serde(default, deserialize_with = "secret")]
+    pub ngrok_authtoken: String,
+    #[serde(default)]
+    pub ngrok_domain: String,
+    /// Who the ngrok edge lets through to the endpoint.
+    #[serde(default)]
+    pub ngrok_endpoint: Option<NgrokEndpointSettings>,
+    /// How Toggl reaches the webhook server.
+    #[serde(default)]
+    pub tunnel: TunnelKind,
+    #[serde(default)]
+    pub tailscale: Option<TailscaleSettings>,
+    #[serde(default)]
+    pub direct: Option<DirectSettings>,
+    pub chat_id: String,
+    /// The status titles; each defaults to the `theme`'s.
+    #[serde(default)]
+    pub busy_chat_status: String,
+    #[serde(default)]
+    pub break_chat_status: String,
+    #[serde(default)]
+    pub not_working_status: String,
+    /// Busy titles by the running entry's description, checked in order; the first match wins
+    /// over `busy_chat_status`.
+    #[serde(default)]
+    pub entry_titles: Vec<EntryTitle>,
+    /// A built-in theme (`traffic-light`, `minimal`, `cats`) or one from `themes_file`.
+    #[serde(default)]
+    pub theme: Option<String>,
+    /// A YAML file of your own themes by name.
+    #[serde(default)]
+    pub themes_file: Option<String>,
+    /// The `theme`, once loaded.
+    #[serde(skip)]
+    pub loaded_theme: Option<Theme>,
+    pub minutes_till_afk: u64,
+    /// How often the break is checked against `minutes_till_afk` and `afk_tiers`.
+    #[serde(default = "default_afk_check_secs")]
+    pub afk_check_secs: u64,
+    /// Titles for the time since the timer stopped, replacing `minutes_till_afk` when set.
+    #[serde(default)]
+    pub afk_tiers: Vec<AfkTierSettings>,
+    /// How often titles with `{elapsed}` or `{back_at}` are refreshed. Telegram rate-limits
+    /// title changes, so no more than once a minute.
+    #[serde(default = "default_title_refresh_minutes")]
+    pub title_refresh_minutes: u64,
+    #[serde(default)]
+    pub idle_detection: bool,
+    /// Chat title to set on shutdown, so the chat doesn't keep showing a stale status.
+    #[serde(default)]
+    pub offline_title: Option<String>,
+    /// Language of the default titles, the summary and report, and the bot's replies.
+    #[serde(default)]
+    pub locale: Locale,
+    /// Own wording for any of those, taking precedence over the locale's.
+    #[serde(default)]
+    pub messages: HashMap<Text, String>,
+    /// `text` or `json` (one object per line, for log collectors).
+    #[serde(default)]
+    pub log_format: LogFormat,
+    /// Log filter in `RUST_LOG` syntax, e.g. `info` or `amibussy=debug,warn`. `RUST_LOG`
+    /// overrides it.
+    #[serde(default = "default_log_level")]
+    pub log_level: String,
+    /// Log to rotating files instead of stdout.
+    #[serde(default)]
+    pub log_file: Option<LogFileSettings>,
+    /// Process events and log transitions without calling Telegram or any other sink.
+    #[serde(default)]
+    pub dry_run: bool,
+    /// `dry_run` was forced with `--dry-run`, whatever the settings say.
+    #[serde(skip)]
+    pub forced_dry_run: bool,
+    #[serde(default)]
+    pub google_calendar: Option<GoogleCalendarSettings>,
+    #[serde(default)]
+    pub caldav: Option<CalDavSettings>,
+    #[serde(default)]
+    pub device_usage: Option<DeviceUsageSettings>,
+    #[serde(default)]
+    pub zoom: Option<ZoomSettings>,
+    #[serde(default)]
+    pub slack_huddle: Option<SlackHuddleSettings>,
+    #[serde(default)]
+    pub tempo: Option<TempoSettings>,
+    #[serde(default)]
+    pub timewarrior: Option<TimewarriorSettings>,
+    #[serde(default)]
+    pub watson: Option<WatsonSettings>,
+    #[serde(default)]
+    pub wakatime: Option<WakaTimeSettings>,
+    #[serde(default)]
+    pub activitywatch: Option<ActivityWatchSettings>,
+    /// Order in which status sources are consulted; the first one with an opinion wins.
+    #[serde(default = "default_precedence")]
+    pub precedence: Vec<StatusSource>,
+    #[serde(default)]
+    pub long_entry: Option<LongEntrySettings>,
+    /// Local hour at which a new "day" starts for daily totals, e.g. 4 for night owls.
+    #[serde(default)]
+    pub day_start_hour: u32,
+    /// IANA name like `Europe/Berlin` for everything done by the clock: day boundaries,
+    /// `work_hours`, the daily summary, `{since}` in templates and log timestamps. Defaults to
+    /// the system's local time.
+    #[serde(default)]
+    pub timezone: Option<chrono_tz::Tz>,
+    /// How long a new status has to last before it's announced.
+    #[serde(default)]
+    pub hysteresis: Option<HysteresisSettings>,
+    /// Outside these hours the status is Not Working, whatever Toggl or the calendars say.
+    #[serde(default)]
+    pub work_hours: Option<WorkHoursSettings>,
+    #[serde(default)]
+    pub end_of_day: Option<EndOfDaySettings>,
+    #[serde(default)]
+    pub out_of_office: Option<OutOfOfficeSettings>,
+    #[serde(default)]
+    pub chat_photos: Option<ChatPhotoSettings>,
+    #[serde(default)]
+    pub chat_description: Option<ChatDescriptionSettings>,
+    #[serde(default)]
+    pub forum_topic: Option<ForumTopicSettings>,
+    #[serde(default)]
+    pub status_message: Option<StatusMessageSettings>,
+    #[serde(default)]
+    pub emoji_status: Option<EmojiStatusSettings>,
+    #[serde(default)]
+    pub matrix: Option<MatrixSettings>,
+    #[serde(default)]
+    pub mattermost: Option<MattermostSettings>,
+    #[serde(default)]
+    pub teams: Option<TeamsSettings>,
+    #[serde(default)]
+    pub slack_dnd: Option<SlackDndSettings>,
+    #[serde(default)]
+    pub github_status: Option<GitHubStatusSettings>,
+    #[serde(default)]
+    pub deep_focus: Option<DeepFocusSettings>,
+    #[serde(default)]
+    pub mastodon: Option<MastodonSettings>,
+    #[serde(default)]
+    pub push: Option<PushSettings>,
+    #[serde(default)]
+    pub status_file: Option<StatusFileSettings>,
+    /// Append every Toggl webhook to this JSONL file, for `amibussy replay`.
+    #[serde(default)]
+    pub record_events: Option<String>,
+    /// Troubleshooting aids.
+    #[serde(default)]
+    pub debug: DebugSettings,
+    /// Your own Toggl user ID, to tell your events from your teammates' with `team`.
+    #[serde(default)]
+    pub toggl_user_id: Option<i64>,
+    /// Your name on the team page; `me` if unset.
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Teammates sharing this deployment, each with their own chat and titles.
+    #[serde(default)]
+    pub team: Vec<TeamMemberSettings>,
+    /// Workspaces whose time entries drive a chat of their own.
+    #[serde(default)]
+    pub workspace_chats: Vec<WorkspaceChatSettings>,
+    /// The workspace these settings are routed to by `workspace_chats`, if they're one's.
+    #[serde(skip)]
+    pub routed_workspace_id: Option<i64>,
+    /// Toggl Track API token, for anything that needs to call Toggl rather than wait for webhooks.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub toggl_api_token: Option<String>,
+    /// Keep the Toggl webhook subscription alive; needs `toggl_api_token`.
+    #[serde(default)]
+    pub toggl_subscription: Option<TogglSubscriptionSettings>,
+    #[serde(default)]
+    pub daily_summary: Option<DailySummarySettings>,
+    #[serde(default)]
+    pub weekly_report: Option<WeeklyReportSettings>,
+    #[serde(default)]
+    pub admin_alerts: Option<AdminAlertSettings>,
+    /// Telegram user IDs allowed to run bot commands and press the status panel's buttons.
+    #[serde(default)]
+    pub allowed_user_ids: Vec<i64>,
+    #[serde(default)]
+    pub auto_reply: Option<AutoReplySettings>,
+    #[serde(default)]
+    pub status_panel: Option<StatusPanelSettings>,
+    #[serde(default)]
+    pub webhook_auth: Option<WebhookAuthSettings>,
+    /// Limits on the public webhook route, against scanners hammering the ngrok domain.
+    #[serde(default)]
+    pub webhook_limits: WebhookLimitSettings,
+    #[serde(default)]
+    pub dashboard: Option<DashboardSettings>,
+    /// How quickly to retry when the tunnel fails.
+    #[serde(default)]
+    pub tunnel_retry: TunnelRetrySettings,
+    /// Arbitrary HTTP endpoints called on every status transition.
+    #[serde(default)]
+    pub webhooks: Vec<WebhookSettings>,
+    #[serde(default)]
+    pub hooks: Option<ShellHookSettings>,
+    #[serde(default)]
+    pub macos_focus: Option<MacosFocusSettings>,
+    #[serde(default)]
+    pub notification_pause: Option<NotificationPauseSettings>,
+    #[serde(default)]
+    pub busylight: Option<BusylightSettings>,
+    #[serde(default)]
+    pub hue: Option<HueSettings>,
+    #[serde(default)]
+    pub elgato: Option<ElgatoSettings>,
+    /// SQLite file that every status transition is recorded to.
+    #[serde(default = "default_history_db")]
+    pub history_db: String,
+    /// Unix socket for plain-text control commands (`status`, `set busy 30m`, ...).
+    #[serde(default = "default_control_socket")]
+    pub control_socket: String,
+    /// Local address of the control API used by `amibussy status`.
+    #[serde(default = "default_control_addr")]
+    pub control_addr: String,
+}
+
+/// A teammate whose Toggl timer drives their own chat title from the same deployment.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TeamMemberSettings {
+    pub toggl_user_id: i64,
+    pub name: String,
+    pub chat_id: String,
+    /// Defaults to the top-level `bot_token`.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub bot_token: Option<String>,
+    /// Their own token, to catch up with their timer on startup and to manage the
+    /// subscription of `workspace_id`.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub toggl_api_token: Option<String>,
+    /// Their workspace, if it isn't yours; it gets its own Toggl subscription.
+    #[serde(default)]
+    pub workspace_id: Option<i64>,
+    #[serde(default)]
+    pub busy_chat_status: Option<String>,
+    #[serde(default)]
+    pub break_chat_status: Option<String>,
+    #[serde(default)]
+    pub not_working_status: Option<String>,
+}
+
+/// What a teammate or a routed workspace changes about the settings its status runs with.
+struct ChatOverrides<'a> {
+    chat_id: &'a str,
+    bot_token: Option<&'a String>,
+    busy_chat_status: Option<&'a String>,
+    break_chat_status: Option<&'a String>,
+    not_working_status: Option<&'a String>,
+}
+
+/// Your time entries in `workspace_id` drive this chat instead of `chat_id`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WorkspaceChatSettings {
+    pub workspace_id: i64,
+    pub chat_id: String,
+    /// Defaults to the top-level `bot_token`.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub bot_token: Option<String>,
+    #[serde(default)]
+    pub busy_chat_status: Option<String>,
+    #[serde(default)]
+    pub break_chat_status: Option<String>,
+    #[serde(default)]
+    pub not_working_status: Option<String>,
+}
+
+fn default_history_db() -> String {
+    "~/.local/share/amibussy/history.sqlite3".to_string()
+}
+
+fn default_control_socket() -> String {
+    "~/.local/run/amibussy.sock".to_string()
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct LogFileSettings {
+    /// Directory for the log files, e.g. `~/Library/Logs/amibussy`.
+    pub dir: String,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    /// How many files to keep; older ones are deleted.
+    #[serde(default = "default_log_max_files")]
+    pub max_files: usize,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+fn default_log_max_files() -> usize {
+    7
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TunnelKind {
+    #[default]
+    Ngrok,
+    /// Tailscale Funnel, through the `tailscale` CLI.
+    Tailscale,
+    /// Listen on a public address ourselves.
+    Direct,
+}
+
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct NgrokEndpointSettings {
+    /// Only these CIDRs may connect, e.g. `["203.0.113.0/24"]`.
+    #[serde(default)]
+    pub allow_cidrs: Vec<String>,
+    #[serde(default)]
+    pub deny_cidrs: Vec<String>,
+    #[serde(default)]
+    pub basic_auth: Option<NgrokBasicAuthSettings>,
+    #[serde(default)]
+    pub oauth: Option<NgrokOauthSettings>,
+    /// Share of 5xx responses (0.0 to 1.0) above which ngrok stops forwarding for a while.
+    #[serde(default)]
+    pub circuit_breaker: Option<f64>,
+}
+
+impl NgrokEndpointSettings {
+    /// Whether the edge turns some requests away before they reach us.
+    pub fn restricts_access(&self) -> bool {
+        !self.allow_cidrs.is_empty()
+            || !self.deny_cidrs.is_empty()
+            || self.basic_auth.is_some()
+            || self.oauth.is_some()
+    }
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NgrokBasicAuthSettings {
+    pub username: String,
+    #[serde(deserialize_with = "secret")]
+    pub password: String,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NgrokOauthSettings {
+    /// `google`, `github`, `microsoft`, ...
+    pub provider: String,
+    #[serde(default)]
+    pub allow_emails: Vec<String>,
+    #[serde(default)]
+    pub allow_domains: Vec<String>,
+}
+
+/// Serve the webhooks on a public address, with our own TLS unless a proxy in front
+/// terminates it.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DirectSettings {
+    /// The domain pointing at this machine.
+    pub domain: String,
+    #[serde(default = "default_direct_listen")]
+    pub listen: String,
+    /// Certificate files to use; mutually exclusive with `acme`.
+    #[serde(default)]
+    pub tls: Option<TlsFileSettings>,
+    #[serde(default)]
+    pub acme: Option<AcmeSettings>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TlsFileSettings {
+    /// PEM certificate chain.
+    pub cert: String,
+    /// PEM private key.
+    pub key: String,
+}
+
+/// Get (and renew) a certificate for `direct.domain` from Let's Encrypt.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AcmeSettings {
+    /// Contact address for expiry notices.
+    #[serde(default)]
+    pub email: Option<String>,
+    #[serde(default)]
+    pub challenge: AcmeChallenge,
+    /// Where to answer HTTP-01 challenges; Let's Encrypt only connects to port 80.
+    #[serde(default = "default_acme_http_listen")]
+    pub http_listen: String,
+    /// Shell command that sets (`AMIBUSSY_ACME_ACTION=set`) or removes (`clear`) the TXT record
+    /// `AMIBUSSY_ACME_NAME` with `AMIBUSSY_ACME_VALUE`, for DNS-01.
+    #[serde(default)]
+    pub dns_hook: Option<String>,
+    #[serde(default = "default_acme_dns_propagation_secs")]
+    pub dns_propagation_secs: u64,
+    /// Use the Let's Encrypt staging environment, for trying things out.
+    #[serde(default)]
+    pub staging: bool,
+    #[serde(default = "default_acme_cache_dir")]
+    pub cache_dir: String,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+pub enum AcmeChallenge {
+    #[default]
+    #[serde(rename = "http-01")]
+    Http01,
+    #[serde(rename = "dns-01")]
+    Dns01,
+}
+
+fn default_direct_listen() -> String {
+    "0.0.0.0:443".to_string()
+}
+
+fn default_acme_http_listen() -> String {
+    "0.0.0.0:80".to_string()
+}
+
+fn default_acme_dns_propagation_secs() -> u64 {
+    60
+}
+
+fn default_acme_cache_dir() -> String {
+    "~/.local/share/amibussy/acme".to_string()
+}
+
+/// Expose the webhook server with Tailscale Funnel instead of ngrok.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TailscaleSettings {
+    /// This machine's Funnel name, like `laptop.tailnet-name.ts.net`.
+    pub domain: String,
+    /// Local port the webhook server listens on and Funnel forwards to.
+    #[serde(default = "default_tailscale_port")]
+    pub port: u16,
+    #[serde(default = "default_tailscale_binary")]
+    pub binary: String,
+}
+
+fn default_tailscale_port() -> u16 {
+    8787
+}
+
+fn default_tailscale_binary() -> String {
+    "tailscale".to_string()
+}
+
+/// A busy title for the entries whose description matches `pattern`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct EntryTitle {
+    /// A regular expression found anywhere in the description, e.g. `(?i)meeting|call`.
+    #[serde(deserialize_with = "regex")]
+    pub pattern: regex::Regex,
+    pub title: String,
+}
+
+/// A stage of being away, from `after_minutes` after the timer stopped until the next one.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AfkTierSettings {
+    pub after_minutes: u64,
+    pub title: String,
+    /// What the other sinks see: `break` or `not_working`. The first `not_working` tier is when
+    /// the break ends.
+    #[serde(default = "default_afk_tier_status")]
+    pub status: Status,
+}
+
+fn default_afk_tier_status() -> Status {
+    Status::NotWorking
+}
+
+fn default_afk_check_secs() -> u64 {
+    15
+}
+
+fn default_log_level() -> String {
+    "info".to_string()
+}
+
+fn default_title_refresh_minutes() -> u64 {
+    5
+}
+
+fn default_control_addr() -> String {
+    "127.0.0.1:7575".to_string()
+}
+
+/// Elgato Key Light / Ring Light, switched on while busy and off otherwise.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ElgatoSettings {
+    /// `ip` or `ip:port` of each light; the port defaults to 9123.
+    pub devices: Vec<String>,
+    /// Percent.
+    #[serde(default = "default_elgato_brightness")]
+    pub brightness: u8,
+    #[serde(default = "default_elgato_temperature_kelvin")]
+    pub temperature_kelvin: u32,
+    /// Only light up when a meeting (calendar, camera, ...) decided the status.
+    #[serde(default)]
+    pub only_in_meetings: bool,
+}
+
+fn default_elgato_brightness() -> u8 {
+    40
+}
+
+fn default_elgato_temperature_kelvin() -> u32 {
+    3200
+}
+
+/// Philips Hue lights driven through the local bridge API.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HueSettings {
+    pub bridge_ip: String,
+    /// The bridge "username" created by pressing its link button.
+    #[serde(deserialize_with = "secret")]
+    pub app_key: String,
+    #[serde(default)]
+    pub lights: Vec<String>,
+    /// A room or zone, e.g. to recall a scene.
+    #[serde(default)]
+    pub group: Option<String>,
+    #[serde(default)]
+    pub busy: Option<HueLightState>,
+    /// Falls back to `busy`.
+    #[serde(default)]
+    pub forgot_timer: Option<HueLightState>,
+    #[serde(default, rename = "break")]
+    pub on_break: Option<HueLightState>,
+    #[serde(default)]
+    pub not_working: Option<HueLightState>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HueLightState {
+    #[serde(default = "default_true")]
+    pub on: bool,
+    /// `#rrggbb`.
+    #[serde(default)]
+    pub color: Option<String>,
+    /// Percent.
+    #[serde(default)]
+    pub brightness: Option<u8>,
+    /// Recall a scene on `group` instead of setting `on`/`color`/`brightness`.
+    #[serde(default)]
+    pub scene: Option<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Colours (`#rrggbb`) for a USB busy-light. Needs the `busylight` cargo feature.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct BusylightSettings {
+    #[serde(default = "default_busylight_busy")]
+    pub busy: String,
+    #[serde(default = "default_busylight_break", rename = "break")]
+    pub on_break: String,
+    /// Black is off.
+    #[serde(default = "default_busylight_not_working")]
+    pub not_working: String,
+}
+
+fn default_busylight_busy() -> String {
+    "#ff0000".to_string()
+}
+
+fn default_busylight_break() -> String {
+    "#00ff00".to_string()
+}
+
+fn default_busylight_not_working() -> String {
+    "#000000".to_string()
+}
+
+/// Pause Linux desktop notifications while Busy. Needs the `dbus` cargo feature.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NotificationPauseSettings {
+    pub backend: NotificationBackend,
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NotificationBackend {
+    Dunst,
+    Gnome,
+    Kde,
+}
+
+/// Shortcuts that turn a macOS Focus on when entering Busy and off on Break/AFK.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MacosFocusSettings {
+    pub on_shortcut: String,
+    pub off_shortcut: String,
+}
+
+/// Local commands run (through `sh -c`, or `cmd /C` on Windows) when entering a status.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ShellHookSettings {
+    #[serde(default)]
+    pub on_busy: Option<String>,
+    /// Falls back to `on_busy`.
+    #[serde(default)]
+    pub on_forgot_timer: Option<String>,
+    #[serde(default)]
+    pub on_break: Option<String>,
+    #[serde(default)]
+    pub on_not_working: Option<String>,
+    #[serde(default = "default_hook_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_hook_timeout_secs() -> u64 {
+    10
+}
+
+/// An outbound webhook (IFTTT, Zapier, n8n, your own scripts, ...).
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WebhookSettings {
+    pub url: String,
+    #[serde(default = "default_webhook_method")]
+    pub method: String,
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// JSON body template; see `sinks::webhook` for placeholders. Defaults to the transition as JSON.
+    #[serde(default)]
+    pub body: Option<String>,
+    /// Sign the body with HMAC-SHA256 under this key, in `signature_header`.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub secret: Option<String>,
+    #[serde(default = "default_signature_header")]
+    pub signature_header: String,
+}
+
+fn default_webhook_method() -> String {
+    "POST".to_string()
+}
+
+fn default_signature_header() -> String {
+    "X-Amibussy-Signature".to_string()
+}
+
+/// Serve today's status timeline at `/dashboard?token=...` on the webhook server.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DashboardSettings {
+    #[serde(deserialize_with = "secret")]
+    pub token: String,
+}
+
+/// Poll a Slack user's profile and treat being in a huddle as a meeting.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SlackHuddleSettings {
+    /// A token with the `users.profile:read` scope.
+    #[serde(deserialize_with = "secret")]
+    pub token: String,
+    pub user_id: String,
+    #[serde(default = "default_slack_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_slack_poll_interval_secs() -> u64 {
+    30
+}
+
+/// Zoom "meeting started/ended" webhooks, received at `/zoom`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ZoomSettings {
+    /// The app's "Secret Token", used for URL validation and signature checks.
+    #[serde(deserialize_with = "secret")]
+    pub secret_token: String,
+    /// Only react to meetings hosted by this Zoom user ID.
+    #[serde(default)]
+    pub user_id: Option<String>,
+}
+
+/// Tempo (or any Jira) timers, started and stopped with webhooks to `/tempo?token=...`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TempoSettings {
+    #[serde(deserialize_with = "secret")]
+    pub token: String,
+}
+
+/// Timewarrior's active interval, read with `timew get dom.active.json`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TimewarriorSettings {
+    #[serde(default = "default_timew_command")]
+    pub command: String,
+    #[serde(default = "default_local_tracker_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_timew_command() -> String {
+    "timew".to_string()
+}
+
+fn default_local_tracker_poll_interval_secs() -> u64 {
+    10
+}
+
+/// Watson's running frame, read from its state file.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WatsonSettings {
+    #[serde(default = "default_watson_state_file")]
+    pub state_file: String,
+    #[serde(default = "default_local_tracker_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_watson_state_file() -> String {
+    "~/.config/watson/state".to_string()
+}
+
+/// WakaTime heartbeats as a sign of coding, from today's durations on the API or sent by the
+/// editor plugins to the relay on the control API.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WakaTimeSettings {
+    /// Poll the API with this key; without it only the relay is used.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub api_key: Option<String>,
+    /// WakaTime's, or a compatible server's like Wakapi.
+    #[serde(default = "default_wakatime_api_url")]
+    pub api_url: String,
+    #[serde(default = "default_wakatime_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// How long after the last heartbeat you still count as coding.
+    #[serde(default = "default_wakatime_grace_minutes")]
+    pub grace_minutes: u64,
+    /// Pass what the plugins send to the relay on to `api_url`.
+    #[serde(default)]
+    pub forward: bool,
+}
+
+fn default_wakatime_api_url() -> String {
+    "https://api.wakatime.com/api/v1".to_string()
+}
+
+fn default_wakatime_poll_interval_secs() -> u64 {
+    120
+}
+
+fn default_wakatime_grace_minutes() -> u64 {
+    10
+}
+
+/// The active window as seen by a local ActivityWatch server, sorted into `categories`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ActivityWatchSettings {
+    #[serde(default = "default_activitywatch_url")]
+    pub url: String,
+    /// The window watcher's bucket; defaults to the first `currentwindow` one.
+    #[serde(default)]
+    pub bucket: Option<String>,
+    #[serde(default = "default_activitywatch_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+    /// Checked in order; the first that matches the window wins.
+    #[serde(default)]
+    pub categories: Vec<ActivityCategory>,
+}
+
+fn default_activitywatch_url() -> String {
+    "http://localhost:5600".to_string()
+}
+
+fn default_activitywatch_poll_interval_secs() -> u64 {
+    30
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ActivityCategory {
+    pub name: String,
+    /// Matched case-insensitively anywhere in the application name.
+    #[serde(default)]
+    pub apps: Vec<String>,
+    /// Matched case-insensitively anywhere in the window title.
+    #[serde(default)]
+    pub titles: Vec<String>,
+    #[serde(default)]
+    pub signal: ActivitySignal,
+}
+
+impl ActivityCategory {
+    pub fn matches(&self, app: &str, title: &str) -> bool {
+        let contains = |haystack: &str, needles: &[String]| {
+            let haystack = haystack.to_lowercase();
+            needles
+                .iter()
+                .any(|needle| haystack.contains(&needle.to_lowercase()))
+        };
+        contains(app, &self.apps) || contains(title, &self.titles)
+    }
+}
+
+/// What a category says about you.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ActivitySignal {
+    /// Working: Busy.
+    Busy,
+    /// At the computer, but not working: On Break.
+    Slacking,
+    /// Logged, but no opinion.
+    #[default]
+    Neutral,
+}
+
+impl ActivitySignal {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ActivitySignal::Busy => "busy",
+            ActivitySignal::Slacking => "slacking",
+            ActivitySignal::Neutral => "neutral",
+        }
+    }
+}
+
+/// Treat an active webcam or microphone as a meeting (Linux).
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DeviceUsageSettings {
+    #[serde(default = "default_true")]
+    pub camera: bool,
+    #[serde(default = "default_true")]
+    pub microphone: bool,
+    #[serde(default = "default_device_poll_interval_secs")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_device_poll_interval_secs() -> u64 {
+    5
+}
+
+/// A file with the current status, for window-manager bars.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct StatusFileSettings {
+    pub path: String,
+    /// `{status}`, `{title}` and `{since}` (UNIX timestamp) are replaced.
+    #[serde(default = "default_status_file_template")]
+    pub template: String,
+}
+
+fn default_status_file_template() -> String {
+    "{title}\n".to_string()
+}
+
+/// Push notifications to your phone on selected transitions.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PushSettings {
+    /// Statuses that trigger a notification when entered.
+    #[serde(default = "default_push_on")]
+    pub on: Vec<Status>,
+    /// `{title}`, `{from}` (the previous title) and `{cause}` are replaced.
+    #[serde(default = "default_push_message")]
+    pub message: String,
+    #[serde(default)]
+    pub ntfy: Option<NtfySettings>,
+    #[serde(default)]
+    pub pushover: Option<PushoverSettings>,
+}
+
+fn default_push_on() -> Vec<Status> {
+    vec![Status::NotWorking]
+}
+
+fn default_push_message() -> String {
+    "Now {title} (was {from})".to_string()
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct NtfySettings {
+    #[serde(default = "default_ntfy_server")]
+    pub server: String,
+    pub topic: String,
+    /// Access token for protected topics.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub token: Option<String>,
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct PushoverSettings {
+    /// The application's API token.
+    #[serde(deserialize_with = "secret")]
+    pub token: String,
+    /// Your user key.
+    #[serde(deserialize_with = "secret")]
+    pub user: String,
+}
+
+/// Show the status in a Mastodon profile field.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MastodonSettings {
+    /// e.g. `https://mastodon.social`.
+    pub instance: String,
+    /// An app token with the `read:accounts` and `write:accounts` scopes.
+    #[serde(deserialize_with = "secret")]
+    pub access_token: String,
+    #[serde(default = "default_mastodon_field_name")]
+    pub field_name: String,
+    #[serde(default = "default_mastodon_busy")]
+    pub busy: String,
+    #[serde(default = "default_mastodon_break", rename = "break")]
+    pub on_break: String,
+    #[serde(default = "default_mastodon_not_working")]
+    pub not_working: String,
+    /// Minimum time between profile updates.
+    #[serde(default = "default_mastodon_min_interval_secs")]
+    pub min_interval_secs: u64,
+}
+
+fn default_mastodon_field_name() -> String {
+    "Status".to_string()
+}
+
+fn default_mastodon_busy() -> String {
+    "🔴 Heads-down".to_string()
+}
+
+fn default_mastodon_break() -> String {
+    "☕ On a break".to_string()
+}
+
+fn default_mastodon_not_working() -> String {
+    "Away".to_string()
+}
+
+fn default_mastodon_min_interval_secs() -> u64 {
+    300
+}
+
+/// Set the GitHub profile status (with the "busy" flag) while a tagged Toggl entry runs.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct GitHubStatusSettings {
+    /// A token with the `user` scope.
+    #[serde(deserialize_with = "secret")]
+    pub token: String,
+    #[serde(default = "default_github_tag")]
+    pub tag: String,
+    #[serde(default = "default_github_message")]
+    pub message: String,
+    #[serde(default = "default_github_emoji")]
+    pub emoji: String,
+}
+
+fn default_github_tag() -> String {
+    "coding".to_string()
+}
+
+fn default_github_message() -> String {
+    "In deep work".to_string()
+}
+
+fn default_github_emoji() -> String {
+    ":red_circle:".to_string()
+}
+
+/// Stricter measures while a Toggl entry tagged `tag` runs: its own title, Slack snoozed and
+/// the chat muted for your own Telegram account.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DeepFocusSettings {
+    #[serde(default = "default_deep_focus_tag")]
+    pub tag: String,
+    /// Defaults to the locale's.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// A user token with the `dnd:write` scope. Unlike `slack_dnd`, only deep focus snoozes.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub slack_token: Option<String>,
+    #[serde(default = "default_snooze_minutes")]
+    pub snooze_minutes: u64,
+    /// Mutes `chat_id` for you; log in once with `amibussy telegram-login`.
+    #[serde(default)]
+    pub telegram_user: Option<TelegramUserSettings>,
+}
+
+fn default_deep_focus_tag() -> String {
+    "deep".to_string()
+}
+
+/// Your own Telegram account, for what the bot can't do.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TelegramUserSettings {
+    /// From https://my.telegram.org.
+    pub api_id: i32,
+    #[serde(deserialize_with = "secret")]
+    pub api_hash: String,
+    #[serde(default = "default_telegram_session_file")]
+    pub session_file: String,
+}
+
+fn default_telegram_session_file() -> String {
+    "~/.local/share/amibussy/telegram.session".to_string()
+}
+
+/// Snooze Slack notifications for the length of a focus block.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct SlackDndSettings {
+    /// A user token with the `dnd:write` scope.
+    #[serde(deserialize_with = "secret")]
+    pub token: String,
+    /// Toggl entries have no planned length, so the snooze runs this long unless a break ends it.
+    #[serde(default = "default_snooze_minutes")]
+    pub snooze_minutes: u64,
+}
+
+fn default_snooze_minutes() -> u64 {
+    60
+}
+
+/// Microsoft Teams preferred presence through the Graph API. Log in once with
+/// `amibussy teams-login`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TeamsSettings {
+    /// An Azure app registration with the delegated `Presence.ReadWrite` permission and
+    /// public client flows enabled.
+    pub client_id: String,
+    #[serde(default = "default_teams_tenant")]
+    pub tenant: String,
+    #[serde(default = "default_teams_token_cache")]
+    pub token_cache: String,
+    #[serde(default = "default_teams_busy")]
+    pub busy: TeamsPresence,
+    #[serde(default = "default_teams_break", rename = "break")]
+    pub on_break: TeamsPresence,
+    #[serde(default = "default_teams_not_working")]
+    pub not_working: TeamsPresence,
+}
+
+/// An `availability`/`activity` pair accepted by `setUserPreferredPresence`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TeamsPresence {
+    pub availability: String,
+    pub activity: String,
+}
+
+fn teams_presence(availability: &str, activity: &str) -> TeamsPresence {
+    TeamsPresence {
+        availability: availability.to_string(),
+        activity: activity.to_string(),
+    }
+}
+
+fn default_teams_tenant() -> String {
+    "common".to_string()
+}
+
+fn default_teams_token_cache() -> String {
+    "~/.local/share/amibussy/teams_token.json".to_string()
+}
+
+fn default_teams_busy() -> TeamsPresence {
+    teams_presence("Busy", "Busy")
+}
+
+fn default_teams_break() -> TeamsPresence {
+    teams_presence("Away", "Away")
+}
+
+fn default_teams_not_working() -> TeamsPresence {
+    teams_presence("Offline", "OffWork")
+}
+
+/// Set a Mattermost custom status per status; one left unset clears it.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MattermostSettings {
+    /// Server URL, e.g. `https://chat.example.com`.
+    pub url: String,
+    /// A personal access token.
+    #[serde(deserialize_with = "secret")]
+    pub token: String,
+    #[serde(default)]
+    pub busy: Option<CustomStatus>,
+    #[serde(default, rename = "break")]
+    pub on_break: Option<CustomStatus>,
+    #[serde(default)]
+    pub not_working: Option<CustomStatus>,
+    /// Let the custom status expire on its own, in case amibussy isn't around to clear it.
+    #[serde(default)]
+    pub expiry_minutes: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct CustomStatus {
+    /// Emoji name without colons, e.g. `red_circle`.
+    pub emoji: String,
+    pub text: String,
+}
+
+/// Mirror the chat title into a Matrix room's name or topic.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct MatrixSettings {
+    /// e.g. `https://matrix.org`.
+    pub homeserver: String,
+    #[serde(deserialize_with = "secret")]
+    pub access_token: String,
+    /// e.g. `!abcdef:matrix.org`.
+    pub room_id: String,
+    #[serde(default)]
+    pub field: MatrixRoomField,
+}
+
+#[derive(Debug, Clone, Copy, Default, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MatrixRoomField {
+    #[default]
+    Name,
+    Topic,
+}
+
+/// Rename a forum topic instead of (or as well as) the whole chat.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ForumTopicSettings {
+    pub message_thread_id: i64,
+    /// Keep renaming the chat itself too.
+    #[serde(default)]
+    pub rename_chat: bool,
+}
+
+/// Keep the status in a pinned message instead of renaming the chat, so the history
+/// isn't spammed with "title changed" service messages.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct StatusMessageSettings {
+    /// Message to edit. If unset, a new one is sent and pinned on the first update.
+    #[serde(default)]
+    pub message_id: Option<i64>,
+    /// `{title}` and `{since}` (local HH:MM) are replaced. Defaults to the locale's.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// Keep renaming the chat itself too.
+    #[serde(default)]
+    pub rename_chat: bool,
+}
+
+/// Set your own Premium emoji status via the Bot API's `setUserEmojiStatus`.
+/// You have to grant the bot access first (Mini App `requestEmojiStatusAccess`).
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct EmojiStatusSettings {
+    pub user_id: i64,
+    /// Custom emoji IDs per status. Statuses without one clear the emoji status.
+    #[serde(default)]
+    pub busy: Option<String>,
+    #[serde(default, rename = "break")]
+    pub on_break: Option<String>,
+    #[serde(default)]
+    pub not_working: Option<String>,
+}
+
+/// Paths to the chat photo to set for each status. Statuses without a photo leave it as is.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ChatPhotoSettings {
+    #[serde(default)]
+    pub busy: Option<String>,
+    #[serde(default, rename = "break")]
+    pub on_break: Option<String>,
+    #[serde(default)]
+    pub not_working: Option<String>,
+}
+
+/// Chat description templates per status, for more context than fits in a title. Statuses
+/// without one clear the description.
+///
+/// `{title}`, `{entry}` (the running Toggl entry's description), `{started}` (local HH:MM the
+/// entry started), `{since}` (local HH:MM the status started), `{elapsed}` and `{back_at}`
+/// are replaced.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ChatDescriptionSettings {
+    #[serde(default)]
+    pub busy: Option<String>,
+    #[serde(default, rename = "break")]
+    pub on_break: Option<String>,
+    #[serde(default)]
+    pub not_working: Option<String>,
+}
+
+/// Minimum seconds in a status before switching to it is announced, per target status.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct HysteresisSettings {
+    #[serde(default)]
+    pub busy_secs: u64,
+    #[serde(default)]
+    pub break_secs: u64,
+    #[serde(default)]
+    pub not_working_secs: u64,
+}
+
+impl HysteresisSettings {
+    pub fn min_secs(&self, status: Status) -> u64 {
+        match status {
+            Status::Busy | Status::ForgotTimer => self.busy_secs,
+            Status::Break => self.break_secs,
+            Status::NotWorking => self.not_working_secs,
+        }
+    }
+}
+
+/// Working hours per weekday as `HH:MM-HH:MM` in local time. Days left out are days off.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WorkHoursSettings {
+    #[serde(default)]
+    pub mon: Option<String>,
+    #[serde(default)]
+    pub tue: Option<String>,
+    #[serde(default)]
+    pub wed: Option<String>,
+    #[serde(default)]
+    pub thu: Option<String>,
+    #[serde(default)]
+    pub fri: Option<String>,
+    #[serde(default)]
+    pub sat: Option<String>,
+    #[serde(default)]
+    pub sun: Option<String>,
+}
+
+impl WorkHoursSettings {
+    fn days(&self) -> [(&'static str, &Option<String>); 7] {
+        [
+            ("mon", &self.mon),
+            ("tue", &self.tue),
+            ("wed", &self.wed),
+            ("thu", &self.thu),
+            ("fri", &self.fri),
+            ("sat", &self.sat),
+            ("sun", &self.sun),
+        ]
+    }
+
+    /// Whether `time` falls inside the working hours of its weekday.
+    pub fn contains(&self, time: chrono::NaiveDateTime) -> bool {
+        use chrono::Datelike;
+        let (_, hours) = self.days()[time.weekday().num_days_from_monday() as usize];
+        hours
+            .as_deref()
+            .and_then(parse_hours_range)
+            .is_some_and(|(start, end)| (start..end).contains(&time.time()))
+    }
+}
+
+/// Parses `09:00-18:00`; the end must come after the start.
+fn parse_hours_range(range: &str) -> Option<(chrono::NaiveTime, chrono::NaiveTime)> {
+    let (start, end) = range.split_once('-')?;
+    let start = chrono::NaiveTime::parse_from_str(start.trim(), "%H:%M").ok()?;
+    let end = chrono::NaiveTime::parse_from_str(end.trim(), "%H:%M").ok()?;
+    (start < end).then_some((start, end))
+}
+
+/// Call it a day at a fixed local time: without a timer running the status turns into "done
+/// for today" until the next day; with one, you get a nudge to stop.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct EndOfDaySettings {
+    /// Local time as `HH:MM`.
+    pub at: String,
+    /// Defaults to the locale's.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Sent if the timer is still running at `at`; `{at}` and `{elapsed}` are replaced.
+    /// Defaults to the locale's.
+    #[serde(default)]
+    pub overwork_message: Option<String>,
+    /// Where to send it; defaults to `admin_alerts.chat_id`.
+    #[serde(default)]
+    pub chat_id: Option<String>,
+}
+
+impl EndOfDaySettings {
+    pub fn time(&self) -> Option<chrono::NaiveTime> {
+        chrono::NaiveTime::parse_from_str(&self.at, "%H:%M").ok()
+    }
+}
+
+/// Out of office from `from` (or now) through `until`, both `YYYY-MM-DD`; see
+/// `out_of_office::OutOfOffice`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct OutOfOfficeSettings {
+    #[serde(default)]
+    pub from: Option<String>,
+    /// The last day off.
+    pub until: String,
+    /// Defaults to the locale's.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// Disable the Toggl subscription meanwhile; needs `toggl_subscription`.
+    #[serde(default)]
+    pub pause_subscription: bool,
+}
+
+impl OutOfOfficeSettings {
+    pub fn from_date(&self) -> Option<chrono::NaiveDate> {
+        self.from.as_deref().and_then(parse_date)
+    }
+
+    pub fn until_date(&self) -> Option<chrono::NaiveDate> {
+        parse_date(&self.until)
+    }
+}
+
+fn parse_date(date: &str) -> Option<chrono::NaiveDate> {
+    chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()
+}
+
+/// Post a summary of the day to a chat at a fixed local time.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DailySummarySettings {
+    /// Local time as `HH:MM`.
+    pub at: String,
+    /// Where to post; defaults to `chat_id`.
+    #[serde(default)]
+    pub chat_id: Option<String>,
+}
+
+/// Shared secrets the webhook callers have to know, on top of the ngrok domain.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WebhookAuthSettings {
+    /// Random path segment: the callback URL becomes `/webhook/<path_token>`.
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub path_token: Option<String>,
+    /// Header every request has to carry with `header_value`.
+    #[serde(default)]
+    pub header: Option<String>,
+    #[serde(default, deserialize_with = "optional_secret")]
+    pub header_value: Option<String>,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WebhookLimitSettings {
+    #[serde(default = "default_webhook_max_body_bytes")]
+    pub max_body_bytes: usize,
+    /// Sustained requests per minute from one address.
+    #[serde(default = "default_webhook_requests_per_minute")]
+    pub requests_per_minute: u32,
+    /// Requests from one address allowed at once.
+    #[serde(default = "default_webhook_burst")]
+    pub burst: u32,
+    /// How long any public request may take before it's answered with a 408.
+    #[serde(default = "default_webhook_timeout_secs")]
+    pub timeout_secs: u64,
+    /// Public requests handled at once; more wait for a slot.
+    #[serde(default = "default_webhook_max_concurrent")]
+    pub max_concurrent: usize,
+}
+
+impl Default for WebhookLimitSettings {
+    fn default() -> Self {
+        WebhookLimitSettings {
+            max_body_bytes: default_webhook_max_body_bytes(),
+            requests_per_minute: default_webhook_requests_per_minute(),
+            burst: default_webhook_burst(),
+            timeout_secs: default_webhook_timeout_secs(),
+            max_concurrent: default_webhook_max_concurrent(),
+        }
+    }
+}
+
+fn default_webhook_max_body_bytes() -> usize {
+    64 * 1024
+}
+
+fn default_webhook_requests_per_minute() -> u32 {
+    60
+}
+
+fn default_webhook_burst() -> u32 {
+    20
+}
+
+fn default_webhook_timeout_secs() -> u64 {
+    30
+}
+
+fn default_webhook_max_concurrent() -> usize {
+    32
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct DebugSettings {
+    /// Append every webhook received, headers included and secrets redacted, to
+    /// `capture_file`, for reporting payloads that fail to parse.
+    #[serde(default)]
+    pub capture_payloads: bool,
+    #[serde(default = "default_capture_file")]
+    pub capture_file: String,
+    /// Size at which the file is rotated to `capture_file.1`.
+    #[serde(default = "default_capture_max_bytes")]
+    pub capture_max_bytes: u64,
+    /// How many files to keep, the current one included.
+    #[serde(default = "default_capture_max_files")]
+    pub capture_max_files: usize,
+}
+
+impl Default for DebugSettings {
+    fn default() -> Self {
+        DebugSettings {
+            capture_payloads: false,
+            capture_file: default_capture_file(),
+            capture_max_bytes: default_capture_max_bytes(),
+            capture_max_files: default_capture_max_files(),
+        }
+    }
+}
+
+fn default_capture_file() -> String {
+    "~/.local/share/amibussy/payloads.jsonl".to_string()
+}
+
+fn default_capture_max_bytes() -> u64 {
+    10 * 1024 * 1024
+}
+
+fn default_capture_max_files() -> usize {
+    3
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TunnelRetrySettings {
+    /// Wait after the first failure; doubles with every further one.
+    #[serde(default = "default_tunnel_retry_initial_secs")]
+    pub initial_secs: u64,
+    #[serde(default = "default_tunnel_retry_max_secs")]
+    pub max_secs: u64,
+    /// Consecutive failures after which `admin_alerts.chat_id` hears about it.
+    #[serde(default = "default_tunnel_retry_alert_after")]
+    pub alert_after: u32,
+}
+
+impl Default for TunnelRetrySettings {
+    fn default() -> Self {
+        TunnelRetrySettings {
+            initial_secs: default_tunnel_retry_initial_secs(),
+            max_secs: default_tunnel_retry_max_secs(),
+            alert_after: default_tunnel_retry_alert_after(),
+        }
+    }
+}
+
+fn default_tunnel_retry_initial_secs() -> u64 {
+    5
+}
+
+fn default_tunnel_retry_max_secs() -> u64 {
+    300
+}
+
+fn default_tunnel_retry_alert_after() -> u32 {
+    10
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TogglSubscriptionSettings {
+    /// Defaults to your default workspace.
+    #[serde(default)]
+    pub workspace_id: Option<i64>,
+    /// Description of a subscription amibussy creates.
+    #[serde(default = "default_toggl_subscription_description")]
+    pub description: String,
+    #[serde(default = "default_toggl_subscription_check_minutes")]
+    pub check_minutes: u64,
+}
+
+fn default_toggl_subscription_description() -> String {
+    "amibussy".to_string()
+}
+
+fn default_toggl_subscription_check_minutes() -> u64 {
+    60
+}
+
+/// DM someone when a subsystem (a sink, a source, the tunnel) keeps failing.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AdminAlertSettings {
+    /// Your own chat with the bot; you have to have sent it a message first.
+    pub chat_id: String,
+    #[serde(default = "default_admin_alert_after_minutes")]
+    pub after_minutes: u64,
+}
+
+fn default_admin_alert_after_minutes() -> u64 {
+    10
+}
+
+/// Reply once to people who write to you while you're Busy.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct AutoReplySettings {
+    /// Filled in like a `chat_description` template.
+    pub message: String,
+    /// In the status chat, only reply to messages mentioning one of these (e.g. `@ivan`), the
+    /// bot, or replying to the bot. DMs to the bot always get a reply.
+    #[serde(default)]
+    pub mentions: Vec<String>,
+    /// How long to stay quiet towards someone after replying to them.
+    #[serde(default = "default_auto_reply_cooldown_minutes")]
+    pub cooldown_minutes: u64,
+}
+
+fn default_auto_reply_cooldown_minutes() -> u64 {
+    60
+}
+
+/// A message with buttons to pin a status (or drop the pin) from Telegram.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct StatusPanelSettings {
+    /// Where to keep it; defaults to `chat_id`.
+    #[serde(default)]
+    pub chat_id: Option<String>,
+    /// Message to edit. If unset, a new one is sent on startup.
+    #[serde(default)]
+    pub message_id: Option<i64>,
+    /// Telegram user IDs allowed to press the buttons, on top of the top-level
+    /// `allowed_user_ids`.
+    #[serde(default)]
+    pub allowed_user_ids: Vec<i64>,
+    /// Filled in like a `chat_description` template. Defaults to the locale's.
+    #[serde(default)]
+    pub template: Option<String>,
+    /// How much the ⏱ button adds to the pinned status.
+    #[serde(default = "default_status_panel_extend_minutes")]
+    pub extend_minutes: u64,
+}
+
+fn default_status_panel_extend_minutes() -> u64 {
+    30
+}
+
+/// Post the week's busy, break and AFK totals once a week.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct WeeklyReportSettings {
+    /// Weekday, e.g. `fri` or `friday`.
+    #[serde(default = "default_weekly_report_day")]
+    pub day: String,
+    /// Local time as `HH:MM`.
+    #[serde(default = "default_weekly_report_at")]
+    pub at: String,
+    /// Where to post; defaults to `chat_id`.
+    #[serde(default)]
+    pub chat_id: Option<String>,
+}
+
+fn default_weekly_report_day() -> String {
+    "fri".to_string()
+}
+
+fn default_weekly_report_at() -> String {
+    "18:00".to_string()
+}
+
+impl WeeklyReportSettings {
+    pub fn weekday(&self) -> Option<chrono::Weekday> {
+        self.day.parse().ok()
+    }
+
+    pub fn time(&self) -> Option<chrono::NaiveTime> {
+        chrono::NaiveTime::parse_from_str(&self.at, "%H:%M").ok()
+    }
+}
+
+/// Sanity check for timers that have been running for implausibly long.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct LongEntrySettings {
+    #[serde(default = "default_long_entry_max_hours")]
+    pub max_hours: u64,
+    /// Chat to send a heads-up to when a timer crosses `max_hours`.
+    #[serde(default)]
+    pub alert_chat_id: Option<String>,
+    /// Title to switch to instead of Busy once the timer looks forgotten.
+    #[serde(default)]
+    pub forgot_timer_status: Option<String>,
+}
+
+fn default_long_entry_max_hours() -> u64 {
+    6
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct GoogleCalendarSettings {
+    pub client_id: String,
+    #[serde(deserialize_with = "secret")]
+    pub client_secret: String,
+    #[serde(deserialize_with = "secret")]
+    pub refresh_token: String,
+    #[serde(default = "default_calendar_id")]
+    pub calendar_id: String,
+    #[serde(default = "default_calendar_poll_secs")]
+    pub poll_interval_secs: u64,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct CalDavSettings {
+    /// URL of the calendar collection, e.g. `https://caldav.fastmail.com/dav/calendars/user/me@fastmail.com/Default/`.
+    pub url: String,
+    pub username: String,
+    #[serde(deserialize_with = "secret")]
+    pub password: String,
+    #[serde(default = "default_calendar_poll_secs")]
+    pub poll_interval_secs: u64,
+}
+
+fn default_calendar_id() -> String {
+    "primary".to_string()
+}
+
+fn default_calendar_poll_secs() -> u64 {
+    60
+}
+
+/// Formats the path of an ignored key like `hue.bridge_ip`, leaving out the `?` serde_ignored
+/// uses for `Option`s.
+fn key_path(path: &serde_ignored::Path) -> String {
+    use serde_ignored::Path;
+    match path {
+        Path::Root => String::new(),
+        Path::Seq { parent, index } => join_key(key_path(parent), &index.to_string()),
+        Path::Map { parent, key } => join_key(key_path(parent), key),
+        Path::Some { parent }
+        | Path::NewtypeStruct { parent }
+        | Path::NewtypeVariant { parent } => key_path(parent),
+    }
+}
+
+fn join_key(parent: String, key: &str) -> String {
+    if parent.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}.{}", parent, key)
+    }
+}
+
+/// A Telegram chat: a (possibly negative) number or a public `@username`.
+fn valid_chat_id(chat_id: &str) -> bool {
+    match chat_id.strip_prefix('@') {
+        Some(username) => {
+            username.len() >= 5
+                && username
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => chat_id.parse::<i64>().is_ok(),
+    }
+}
+
+fn valid_domain(domain: &str) -> bool {
+    domain.contains('.')
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+                && label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        })
+}
+
+/// An IPv4 or IPv6 network like `203.0.113.0/24`.
+fn valid_cidr(cidr: &str) -> bool {
+    match cidr.split_once('/') {
+        Some((addr, bits)) => match (addr.parse::<std::net::IpAddr>(), bits.parse::<u8>()) {
+            (Ok(std::net::IpAddr::V4(_)), Ok(bits)) => bits <= 32,
+            (Ok(std::net::IpAddr::V6(_)), Ok(bits)) => bits <= 128,
+            _ => false,
+        },
+        None => false,
+    }
+}
+
+/// How long the command of a `cmd:` secret may run before it's killed.
+const SECRET_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Resolves a secret: a value starting with `cmd:` is replaced by the trimmed output of the
+/// rest run through the shell, e.g. `cmd:pass show amibussy/bot_token`.
+fn resolve_secret(value: &str) -> anyhow::Result<String> {
+    let Some(command) = value.strip_prefix("cmd:") else {
+        return Ok(value.to_string());
+    };
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell
+        .arg(command)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("failed to run `{}`: {}", command, err))?;
+    // Read while it runs, so a command with a lot to say doesn't block on a full pipe.
+    let read = |pipe: Option<Box<dyn Read + Send>>| {
+        std::thread::spawn(move || {
+            let mut output = Vec::new();
+            if let Some(mut pipe) = pipe {
+                let _ = pipe.read_to_end(&mut output);
+            }
+            output
+        })
+    };
+    let stdout = read(child.stdout.take().map(|pipe| Box::new(pipe) as _));
+    let stderr = read(child.stderr.take().map(|pipe| Box::new(pipe) as _));
+
+    let deadline = Instant::now() + SECRET_COMMAND_TIMEOUT;
+    let status = loop {
+        if let Some(status) = child.try_wait()? {
+            break status;
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+            anyhow::bail!(
+                "`{}` didn't finish within {}s",
+                command,
+                SECRET_COMMAND_TIMEOUT.as_secs()
+            );
+        }
+        std::thread::sleep(Duration::from_millis(20));
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    let stderr = stderr.join().unwrap_or_default();
+    if !status.success() {
+        anyhow::bail!(
+            "`{}` failed with {}: {}",
+            command,
+            status,
+            String::from_utf8_lossy(&stderr).trim()
+        );
+    }
+    Ok(String::from_utf8(stdout)?.trim().to_string())
+}
+
+fn secret<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<String, D::Error> {
+    let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+    resolve_secret(&value).map_err(serde::de::Error::custom)
+}
+
+fn optional_secret<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<String>, D::Error> {
+    let value = <Option<String> as serde::Deserialize>::deserialize(deserializer)?;
+    value
+        .map(|value| resolve_secret(&value))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+fn regex<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<regex::Regex, D::Error> {
+    let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+    regex::Regex::new(&value).map_err(serde::de::Error::custom)
+}
+
+const CONFIG_EXTENSIONS: [&str; 4] = ["yaml", "yml", "toml", "json"];
+
+/// List-valued settings that can be given as comma-separated environment variables.
+const ENV_LIST_KEYS: [&str; 3] = ["precedence", "hue.lights", "push.on"];
+
+/// The `AMIBUSSY_*` variables of `source`, or of the process environment if `None`.
+fn environment(source: Option<HashMap<String, String>>) -> Environment {
+    let mut environment = Environment::with_prefix("AMIBUSSY")
+        .prefix_separator("_")
+        .separator("__")
+        .try_parsing(true)
+        .list_separator(",")
+        .source(source);
+    for key in ENV_LIST_KEYS {
+        environment = environment.with_list_parse_key(key);
+    }
+    environment
+}
+
+/// Directories searched for `settings.{yaml,yml,toml,json}` when no path is given, in order:
+/// the working directory, `$XDG_CONFIG_HOME/amibussy` (`~/.config/amibussy`) and `/etc/amibussy`.
+fn config_search_dirs() -> Vec<PathBuf> {
+    let xdg_config = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(shellexpand::tilde("~/.config").to_string()));
+    vec![
+        PathBuf::from("."),
+        xdg_config.join("amibussy"),
+        PathBuf::from("/etc/amibussy"),
+    ]
+}
+
+impl Settings {
+    /// Resolves the settings file: `explicit` (from `--config` or `AMIBUSSY_CONFIG`) if given,
+    /// otherwise the first match in the search path. `None` means there is no file and the
+    /// settings come from the environment alone.
+    pub fn find_config(explicit: Option<&Path>) -> anyhow::Result<Option<PathBuf>> {
+        if let Some(path) = explicit {
+            let path = PathBuf::from(shellexpand::tilde(&path.to_string_lossy()).to_string());
+            if !path.is_file() {
+                anyhow::bail!("settings file {} does not exist", path.display());
+            }
+            return Ok(Some(path));
+        }
+
+        let candidates: Vec<PathBuf> = config_search_dirs()
+            .iter()
+            .flat_map(|dir| {
+                CONFIG_EXTENSIONS
+                    .iter()
+                    .map(move |extension| dir.join(format!("settings.{}", extension)))
+            })
+            .collect();
+        let found = candidates.iter().find(|path| path.is_file()).cloned();
+        if found.is_none() {
+            info!(
+                "No settings file found, using AMIBUSSY_* environment variables only. Tried:\n{}",
+                candidates
+                    .iter()
+                    .map(|path| format!("  {}", path.display()))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            );
+        }
+        Ok(found)
+    }
+
+    /// Loads the settings from `path` (if any) with `AMIBUSSY_*` environment variables on top.
+    /// Nested keys use `__`, e.g. `AMIBUSSY_HUE__BRIDGE_IP` for `hue.bridge_ip`.
+    pub fn from_config(path: Option<&Path>) -> Result<Self, Error> {
+        let mut builder = Config::builder();
+        if let Some(path) = path {
+            // The format follows the file extension.
+            builder = builder.add_source(File::from(path));
+        }
+        let config = builder.add_source(environment(None)).build()?;
+        Self::from_loaded(config, path)
+    }
+
+    /// Deserializes and checks `config`, loaded from `path` (if any), collecting every problem
+    /// rather than stopping at the first.
+    fn from_loaded(config: Config, path: Option<&Path>) -> Result<Self, Error> {
+        let mut problems = Vec::new();
+        let mut unknown_keys = Vec::new();
+        let mut record_unknown = |key: serde_ignored::Path| {
+            let key = key_path(&key);
+            // `AMIBUSSY_CONFIG` picks the file rather than being a setting.
+            if key != "config" {
+                unknown_keys.push(key);
+            }
+        };
+        let deserializer = serde_ignored::Deserializer::new(config, &mut record_unknown);
+        let mut settings: Option<Self> = match serde_path_to_error::deserialize(deserializer) {
+            Ok(settings) => Some(settings),
+            // Deserializing stops at the first value of the wrong type, before the checks.
+            Err(err) => {
+                problems.push(format!(
+                    "{}: {} (the rest is only checked once this is fixed)",
+                    err.path(),
+                    err.inner()
+                ));
+                None
+            }
+        };
+        problems.extend(
+            unknown_keys
+                .into_iter()
+                .map(|key| format!("{}: unknown key", key)),
+        );
+        if let Some(settings) = &mut settings {
+            if let Err(problem) = settings.apply_theme() {
+                problems.push(format!("theme: {}", problem));
+            }
+            problems.extend(settings.validate());
+        }
+
+        match settings {
+            Some(mut settings) if problems.is_empty() => {
+                settings.config_path = path.map(Path::to_path_buf);
+                settings.warn_long_titles();
+                Ok(settings)
+            }
+            _ => {
+                let location = match path {
+                    Some(path) => path.display().to_string(),
+                    None => "the environment".to_string(),
+                };
+                Err(Error::InvalidSettings { location, problems })
+            }
+        }
+    }
+
+    /// Turns `dry_run` on for good, as `--dry-run` does: settings reloaded into the state keep
+    /// it too.
+    pub fn force_dry_run(&mut self) {
+        self.forced_dry_run = true;
+        self.dry_run = true;
+    }
+
+    /// Loads the `theme` and fills in the status titles that aren't set.
+    fn apply_theme(&mut self) -> Result<(), String> {
+        let Some(name) = &self.theme else {
+            return Ok(());
+        };
+        let theme = load_theme(name, self.themes_file.as_deref())?;
+        for (title, status) in [
+            (&mut self.busy_chat_status, &theme.busy),
+            (&mut self.break_chat_status, &theme.on_break),
+            (&mut self.not_working_status, &theme.not_working),
+        ] {
+            if title.is_empty() {
+                *title = status.full_title();
+            }
+        }
+        self.loaded_theme = Some(theme);
+        Ok(())
+    }
+
+    /// The public domain Toggl and the other webhook senders reach us at.
+    pub fn public_domain(&self) -> &str {
+        match (self.tunnel, &self.tailscale) {
+            (TunnelKind::Tailscale, Some(tailscale)) => &tailscale.domain,
+            (TunnelKind::Direct, _) => self
+                .direct
+                .as_ref()
+                .map_or(&self.ngrok_domain, |direct| &direct.domain),
+            _ => &self.ngrok_domain,
+        }
+    }
+
+    /// Whether the chat title shows the status. It's only left alone if a replacement (forum
+    /// topic, pinned message) is configured that doesn't also ask to rename the chat.
+    pub fn renames_chat(&self) -> bool {
+        let rename_chat = [
+            self.forum_topic.as_ref().map(|topic| topic.rename_chat),
+            self.status_message
+                .as_ref()
+                .map(|message| message.rename_chat),
+        ];
+        rename_chat.iter().all(Option::is_none) || rename_chat.contains(&Some(true))
+    }
+
+    /// The settings a teammate's status runs with: their chat, token and titles, and the
+    /// shared timing rules. Everything else (other sinks and sources, the bot's own features)
+    /// stays with you. Their history goes to a file of its own next to `history_db`.
+    pub fn for_member(&self, member: &TeamMemberSettings) -> Settings {
+        let chat = ChatOverrides {
+            chat_id: &member.chat_id,
+            bot_token: member.bot_token.as_ref(),
+            busy_chat_status: member.busy_chat_status.as_ref(),
+            break_chat_status: member.break_chat_status.as_ref(),
+            not_working_status: member.not_working_status.as_ref(),
+        };
+        Settings {
+            toggl_user_id: Some(member.toggl_user_id),
+            name: Some(member.name.clone()),
+            toggl_api_token: member.toggl_api_token.clone(),
+            ..self.for_chat(chat, &member.toggl_user_id.to_string())
+        }
+    }
+
+    /// The settings the status of your time in a routed workspace runs with, like a
+    /// teammate's: its chat and titles, and only its time entries.
+    pub fn for_workspace(&self, route: &WorkspaceChatSettings) -> Settings {
+        let chat = ChatOverrides {
+            chat_id: &route.chat_id,
+            bot_token: route.bot_token.as_ref(),
+            busy_chat_status: route.busy_chat_status.as_ref(),
+            break_chat_status: route.break_chat_status.as_ref(),
+            not_working_status: route.not_working_status.as_ref(),
+        };
+        Settings {
+            routed_workspace_id: Some(route.workspace_id),
+            ..self.for_chat(chat, &format!("workspace-{}", route.workspace_id))
+        }
+    }
+
+    /// Whether this status is about time entries in `workspace_id`: the routed workspace's
+    /// own, or for yours any workspace that isn't routed elsewhere.
+    pub fn covers_workspace(&self, workspace_id: i64) -> bool {
+        match self.routed_workspace_id {
+            Some(routed) => routed == workspace_id,
+            None => !self
+                .workspace_chats
+                .iter()
+                .any(|route| route.workspace_id == workspace_id),
+        }
+    }
+
+    fn for_chat(&self, chat: ChatOverrides, history_suffix: &str) -> Settings {
+        let history_db = Path::new(&self.history_db);
+        let history_db = history_db
+            .with_file_name(format!(
+                "{}-{}",
+                history_db.file_stem().unwrap_or_default().to_string_lossy(),
+                history_suffix
+            ))
+            .with_extension(history_db.extension().unwrap_or_default());
+        let or_own = |value: Option<&String>, own: &String| value.unwrap_or(own).clone();
+        Settings {
+            bot_token: or_own(chat.bot_token, &self.bot_token),
+            chat_id: chat.chat_id.to_string(),
+            busy_chat_status: or_own(chat.busy_chat_status, &self.busy_chat_status),
+            break_chat_status: or_own(chat.break_chat_status, &self.break_chat_status),
+            not_working_status: or_own(chat.not_working_status, &self.not_working_status),
+            team: Vec::new(),
+            workspace_chats: Vec::new(),
+            history_db: history_db.to_string_lossy().into_owned(),
+            idle_detection: false,
+            out_of_office: None,
+            google_calendar: None,
+            caldav: None,
+            device_usage: None,
+            zoom: None,
+            tempo: None,
+            timewarrior: None,
+            watson: None,
+            wakatime: None,
+            activitywatch: None,
+            slack_huddle: None,
+            chat_photos: None,
+            chat_description: None,
+            forum_topic: None,
+            status_message: None,
+            emoji_status: None,
+            matrix: None,
+            mattermost: None,
+            teams: None,
+            slack_dnd: None,
+            github_status: None,
+            deep_focus: None,
+            mastodon: None,
+            push: None,
+            status_file: None,
+            toggl_subscription: None,
+            daily_summary: None,
+            weekly_report: None,
+            admin_alerts: None,
+            allowed_user_ids: Vec::new(),
+            auto_reply: None,
+            status_panel: None,
+            dashboard: None,
+            webhooks: Vec::new(),
+            hooks: None,
+            macos_focus: None,
+            notification_pause: None,
+            busylight: None,
+            hue: None,
+            elgato: None,
+            ..self.clone()
+        }
+    }
+
+    /// Whether the Telegram user `user_id` may run bot commands and, if `panel`, press the
+    /// status panel's buttons.
+    pub fn is_allowed_user(&self, user_id: i64, panel: bool) -> bool {
+        self.allowed_user_ids.contains(&user_id)
+            || (panel
+                && self
+                    .status_panel
+                    .as_ref()
+                    .is_some_and(|panel| panel.allowed_user_ids.contains(&user_id)))
+    }
+
+    /// `text` as set in `messages`, or in the `locale`.
+    pub fn text(&self, text: Text) -> &str {
+        self.messages
+            .get(&text)
+            .map_or_else(|| text.translated(self.locale), String::as_str)
+    }
+
+    /// How long after the timer stopped the break turns into Not Working: at the first
+    /// `not_working` tier, or after `minutes_till_afk`.
+    pub fn afk_after_secs(&self) -> u64 {
+        self.afk_tiers
+            .iter()
+            .find(|tier| tier.status == Status::NotWorking)
+            .map_or(self.minutes_till_afk, |tier| tier.after_minutes)
+            * 60
+    }
+
+    /// Whether any status title uses `{elapsed}` or `{back_at}` and so changes over time.
+    pub fn has_dynamic_titles(&self) -> bool {
+        let forgot_timer = self
+            .long_entry
+            .as_ref()
+            .and_then(|long_entry| long_entry.forgot_timer_status.as_deref());
+        [
+            Some(self.busy_chat_status.as_str()),
+            Some(self.break_chat_status.as_str()),
+            Some(self.not_working_status.as_str()),
+            forgot_timer,
+        ]
+        .into_iter()
+        .flatten()
+        .chain(self.afk_tiers.iter().map(|tier| tier.title.as_str()))
+        .chain(
+            self.end_of_day
+                .as_ref()
+                .and_then(|end_of_day| end_of_day.title.as_deref()),
+        )
+        .chain(
+            self.deep_focus
+                .as_ref()
+                .and_then(|deep_focus| deep_focus.title.as_deref()),
+        )
+        .chain(self.entry_titles.iter().map(|rule| rule.title.as_str()))
+        .any(|title| title.contains("{elapsed}") || title.contains("{back_at}"))
+    }
+
+    /// Warns about static titles Telegram would reject as too long; they're cut with an
+    /// ellipsis when sent. Templated ones can only be checked once rendered.
+    fn warn_long_titles(&self) {
+        let titles = [
+            ("busy_chat_status", Some(&self.busy_chat_status)),
+            ("break_chat_status", Some(&self.break_chat_status)),
+            ("not_working_status", Some(&self.not_working_status)),
+            (
+                "long_entry.forgot_timer_status",
+                self.long_entry
+                    .as_ref()
+                    .and_then(|long_entry| long_entry.forgot_timer_status.as_ref()),
+            ),
+            ("offline_title", self.offline_title.as_ref()),
+            (
+                "out_of_office.title",
+                self.out_of_office
+                    .as_ref()
+                    .and_then(|ooo| ooo.title.as_ref()),
+            ),
+            (
+                "end_of_day.title",
+                self.end_of_day
+                    .as_ref()
+                    .and_then(|end_of_day| end_of_day.title.as_ref()),
+            ),
+            (
+                "deep_focus.title",
+                self.deep_focus
+                    .as_ref()
+                    .and_then(|deep_focus| deep_focus.title.as_ref()),
+            ),
+        ];
+        let tiers = self
+            .afk_tiers
+            .iter()
+            .map(|tier| ("afk_tiers.title", Some(&tier.title)));
+        let entry_titles = self
+            .entry_titles
+            .iter()
+            .map(|rule| ("entry_titles.title", Some(&rule.title)));
+        for (key, title) in titles.into_iter().chain(tiers).chain(entry_titles) {
+            let Some(title) = title.filter(|title| !title.contains('{')) else {
+                continue;
+            };
+            if title_len(title) > MAX_TITLE_LEN {
+                warn!(
+                    "{}: {} characters, Telegram takes at most {}; it will be cut with an ellipsis",
+                    key,
+                    title_len(title),
+                    MAX_TITLE_LEN
+                );
+            }
+        }
+    }
+
+    /// Checks the values serde can't: formats and ranges. Returns one `path: problem` per issue.
+    fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let mut check = |ok: bool, key: &str, problem: &str| {
+            if !ok {
+                problems.push(format!("{}: {}", key, problem));
+            }
+        };
+
+        const CHAT_ID: &str = "expected a numeric chat ID like -1001234567890 or an @username";
+        check(valid_chat_id(&self.chat_id), "chat_id", CHAT_ID);
+        for (key, title) in [
+            ("busy_chat_status", &self.busy_chat_status),
+            ("break_chat_status", &self.break_chat_status),
+            ("not_working_status", &self.not_working_status),
+        ] {
+            check(!title.is_empty(), key, "required unless `theme` is set");
+        }
+        match self.tunnel {
+            TunnelKind::Ngrok => {
+                check(
+                    !self.ngrok_authtoken.is_empty(),
+                    "ngrok_authtoken",
+                    "required with tunnel: ngrok",
+                );
+                check(
+                    valid_domain(&self.ngrok_domain),
+                    "ngrok_domain",
+                    "expected a bare domain like example.ngrok-free.app, without scheme or path",
+                );
+                if let Some(endpoint) = &self.ngrok_endpoint {
+                    for cidr in endpoint.allow_cidrs.iter().chain(&endpoint.deny_cidrs) {
+                        check(
+                            valid_cidr(cidr),
+                            "ngrok_endpoint",
+                            "expected CIDRs like 203.0.113.0/24",
+                        );
+                    }
+                    if let Some(basic_auth) = &endpoint.basic_auth {
+                        // ngrok's own limits.
+                        check(
+                            !basic_auth.username.is_empty()
+                                && (8..=128).contains(&basic_auth.password.len()),
+                            "ngrok_endpoint.basic_auth",
+                            "needs a username and a password of 8 to 128 characters",
+                        );
+                    }
+                    check(
+                        endpoint
+                            .circuit_breaker
+                            .is_none_or(|ratio| ratio > 0.0 && ratio <= 1.0),
+                        "ngrok_endpoint.circuit_breaker",
+                        "expected a ratio between 0.0 and 1.0",
+                    );
+                }
+            }
+            TunnelKind::Tailscale => match &self.tailscale {
+                Some(tailscale) => check(
+                    valid_domain(&tailscale.domain),
+                    "tailscale.domain",
+                    "expected a bare domain like laptop.tailnet-name.ts.net",
+                ),
+                None => check(false, "tailscale", "required with tunnel: tailscale"),
+            },
+            TunnelKind::Direct => match &self.direct {
+                Some(direct) => {
+                    check(
+                        valid_domain(&direct.domain),
+                        "direct.domain",
+                        "expected a bare domain like amibussy.example.com",
+                    );
+                    check(
+                        direct.listen.parse::<std::net::SocketAddr>().is_ok(),
+                        "direct.listen",
+                        "expected an address like 0.0.0.0:443",
+                    );
+                    check(
+                        !(direct.tls.is_some() && direct.acme.is_some()),
+                        "direct",
+                        "tls and acme are mutually exclusive",
+                    );
+                    if let Some(acme) = &direct.acme {
+                        check(
+                            acme.http_listen.parse::<std::net::SocketAddr>().is_ok(),
+                            "direct.acme.http_listen",
+                            "expected an address like 0.0.0.0:80",
+                        );
+                        check(
+                            acme.challenge != AcmeChallenge::Dns01 || acme.dns_hook.is_some(),
+                            "direct.acme.dns_hook",
+                            "required with challenge: dns-01",
+                        );
+                    }
+                }
+                None => check(false, "direct", "required with tunnel: direct"),
+            },
+        }
+        check(
+            (1..=1440).contains(&self.minutes_till_afk),
+            "minutes_till_afk",
+            "must be between 1 and 1440",
+        );
+        check(
+            (1..=300).contains(&self.afk_check_secs),
+            "afk_check_secs",
+            "must be between 1 and 300",
+        );
+        if !self.afk_tiers.is_empty() {
+            check(
+                self.afk_tiers
+                    .windows(2)
+                    .all(|pair| pair[0].after_minutes < pair[1].after_minutes),
+                "afk_tiers",
+                "after_minutes must increase from one tier to the next",
+            );
+            check(
+                self.afk_tiers
+                    .iter()
+                    .all(|tier| matches!(tier.status, Status::Break | Status::NotWorking)),
+                "afk_tiers.status",
+                "expected break or not_working",
+            );
+            check(
+                self.afk_tiers
+                    .iter()
+                    .skip_while(|tier| tier.status == Status::Break)
+                    .all(|tier| tier.status == Status::NotWorking)
+                    && self
+                        .afk_tiers
+                        .iter()
+                        .any(|tier| tier.status == Status::NotWorking),
+                "afk_tiers",
+                "needs a not_working tier, and no break tiers after it",
+            );
+            check(
+                self.afk_tiers
+                    .iter()
+                    .all(|tier| (1..=1440).contains(&tier.after_minutes)),
+                "afk_tiers.after_minutes",
+                "must be between 1 and 1440",
+            );
+        }
+        check(
+            valid_log_level(&self.log_level),
+            "log_level",
+            "expected a filter like info or amibussy=debug,warn",
+        );
+        if let Some(log_file) = &self.log_file {
+            check(
+                log_file.max_files >= 1,
+                "log_file.max_files",
+                "must be at least 1",
+            );
+        }
+        check(
+            self.title_refresh_minutes >= 1,
+            "title_refresh_minutes",
+            "must be at least 1",
+        );
+        check(
+            self.day_start_hour < 24,
+            "day_start_hour",
+            "must be between 0 and 23",
+        );
+        check(
+            self.control_addr.parse::<std::net::SocketAddr>().is_ok(),
+            "control_addr",
+            "expected an address like 127.0.0.1:7575",
+        );
+        if let Some(long_entry) = &self.long_entry {
+            check(
+                long_entry.max_hours >= 1,
+                "long_entry.max_hours",
+                "must be at least 1",
+            );
+            if let Some(chat_id) = &long_entry.alert_chat_id {
+                check(valid_chat_id(chat_id), "long_entry.alert_chat_id", CHAT_ID);
+            }
+        }
+        if let Some(summary) = &self.daily_summary {
+            check(
+                chrono::NaiveTime::parse_from_str(&summary.at, "%H:%M").is_ok(),
+                "daily_summary.at",
+                "expected a time as HH:MM",
+            );
+            if let Some(chat_id) = &summary.chat_id {
+                check(valid_chat_id(chat_id), "daily_summary.chat_id", CHAT_ID);
+            }
+        }
+        if let Some(end_of_day) = &self.end_of_day {
+            check(
+                end_of_day.time().is_some(),
+                "end_of_day.at",
+                "expected a time as HH:MM",
+            );
+            if let Some(chat_id) = &end_of_day.chat_id {
+                check(valid_chat_id(chat_id), "end_of_day.chat_id", CHAT_ID);
+            }
+        }
+        if let Some(ooo) = &self.out_of_office {
+            check(
+                ooo.from.is_none() || ooo.from_date().is_some(),
+                "out_of_office.from",
+                "expected a date as YYYY-MM-DD",
+            );
+            check(
+                ooo.until_date().is_some(),
+                "out_of_office.until",
+                "expected a date as YYYY-MM-DD",
+            );
+            check(
+                !ooo.pause_subscription
+                    || (self.toggl_subscription.is_some() && self.toggl_api_token.is_some()),
+                "out_of_office.pause_subscription",
+                "needs toggl_subscription and toggl_api_token",
+            );
+        }
+        for (key, poll_interval_secs) in [
+            (
+                "timewarrior.poll_interval_secs",
+                self.timewarrior
+                    .as_ref()
+                    .map(|timew| timew.poll_interval_secs),
+            ),
+            (
+                "watson.poll_interval_secs",
+                self.watson.as_ref().map(|watson| watson.poll_interval_secs),
+            ),
+        ] {
+            check(poll_interval_secs != Some(0), key, "must be at least 1");
+        }
+        if let Some(wakatime) = &self.wakatime {
+            check(
+                wakatime.poll_interval_secs >= 1,
+                "wakatime.poll_interval_secs",
+                "must be at least 1",
+            );
+            check(
+                (1..=1440).contains(&wakatime.grace_minutes),
+                "wakatime.grace_minutes",
+                "must be between 1 and 1440",
+            );
+            check(
+                reqwest::Url::parse(&wakatime.api_url).is_ok(),
+                "wakatime.api_url",
+                "expected a URL like https://api.wakatime.com/api/v1",
+            );
+        }
+        if let Some(activitywatch) = &self.activitywatch {
+            check(
+                activitywatch.poll_interval_secs >= 1,
+                "activitywatch.poll_interval_secs",
+                "must be at least 1",
+            );
+            check(
+                reqwest::Url::parse(&activitywatch.url).is_ok(),
+                "activitywatch.url",
+                "expected a URL like http://localhost:5600",
+            );
+            for category in &activitywatch.categories {
+                check(
+                    !category.apps.is_empty() || !category.titles.is_empty(),
+                    "activitywatch.categories",
+                    "every category needs apps or titles to match",
+                );
+            }
+        }
+        for webhook in &self.webhooks {
+            check(
+                axum::http::HeaderName::from_bytes(webhook.signature_header.as_bytes()).is_ok(),
+                "webhooks.signature_header",
+                "not a valid header name",
+            );
+        }
+        if let Some(auth) = &self.webhook_auth {
+            check(
+                auth.path_token.as_deref().is_none_or(|token| {
+                    !token.is_empty()
+                        && token
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+                }),
+                "webhook_auth.path_token",
+                "expected letters, digits, - and _ only",
+            );
+            check(
+                auth.header.is_some() == auth.header_value.is_some(),
+                "webhook_auth",
+                "header and header_value go together",
+            );
+            check(
+                auth.header.as_deref().is_none_or(|header| {
+                    axum::http::HeaderName::from_bytes(header.as_bytes()).is_ok()
+                }),
+                "webhook_auth.header",
+                "not a valid header name",
+            );
+        }
+        check(
+            self.webhook_limits.requests_per_minute >= 1 && self.webhook_limits.burst >= 1,
+            "webhook_limits",
+            "requests_per_minute and burst must be at least 1",
+        );
+        check(
+            self.webhook_limits.timeout_secs >= 1 && self.webhook_limits.max_concurrent >= 1,
+            "webhook_limits",
+            "timeout_secs and max_concurrent must be at least 1",
+        );
+        check(
+            self.tunnel_retry.initial_secs >= 1
+                && self.tunnel_retry.max_secs >= self.tunnel_retry.initial_secs,
+            "tunnel_retry",
+            "initial_secs must be at least 1 and max_secs at least initial_secs",
+        );
+        check(
+            self.debug.capture_max_bytes >= 1 && self.debug.capture_max_files >= 1,
+            "debug",
+            "capture_max_bytes and capture_max_files must be at least 1",
+        );
+        check(
+            self.tunnel_retry.alert_after >= 1,
+            "tunnel_retry.alert_after",
+            "must be at least 1",
+        );
+        if let Some(subscription) = &self.toggl_subscription {
+            check(
+                self.toggl_api_token.is_some(),
+                "toggl_subscription",
+                "needs toggl_api_token",
+            );
+            check(
+                subscription.check_minutes >= 1,
+                "toggl_subscription.check_minutes",
+                "must be at least 1",
+            );
+        }
+        if let Some(alerts) = &self.admin_alerts {
+            check(
+                valid_chat_id(&alerts.chat_id),
+                "admin_alerts.chat_id",
+                CHAT_ID,
+            );
+        }
+        if let Some(panel) = &self.status_panel {
+            if let Some(chat_id) = &panel.chat_id {
+                check(valid_chat_id(chat_id), "status_panel.chat_id", CHAT_ID);
+            }
+            check(
+                !panel.allowed_user_ids.is_empty() || !self.allowed_user_ids.is_empty(),
+                "status_panel.allowed_user_ids",
+                "add the Telegram user IDs that may press the buttons here or to allowed_user_ids",
+            );
+            check(
+                panel.extend_minutes > 0,
+                "status_panel.extend_minutes",
+                "must be at least 1",
+            );
+        }
+        if !self.team.is_empty() {
+            check(
+                self.toggl_user_id.is_some(),
+                "toggl_user_id",
+                "required with team, to tell your events from your teammates'",
+            );
+        }
+        let mut member_ids = self.toggl_user_id.into_iter().collect::<Vec<_>>();
+        for member in &self.team {
+            check(
+                !member_ids.contains(&member.toggl_user_id),
+                "team.toggl_user_id",
+                "each Toggl user can only be listed once",
+            );
+            member_ids.push(member.toggl_user_id);
+            check(valid_chat_id(&member.chat_id), "team.chat_id", CHAT_ID);
+            check(
+                member.workspace_id.is_none()
+                    || (member.toggl_api_token.is_some() && self.toggl_subscription.is_some()),
+                "team.workspace_id",
+                "needs the member's toggl_api_token and toggl_subscription",
+            );
+        }
+        let mut routed = Vec::new();
+        for route in &self.workspace_chats {
+            check(
+                !routed.contains(&route.workspace_id),
+                "workspace_chats.workspace_id",
+                "each workspace can only be routed once",
+            );
+            routed.push(route.workspace_id);
+            check(
+                valid_chat_id(&route.chat_id),
+                "workspace_chats.chat_id",
+                CHAT_ID,
+            );
+        }
+        if let Some(auto_reply) = &self.auto_reply {
+            check(
+                !auto_reply.message.trim().is_empty(),
+                "auto_reply.message",
+                "must not be empty",
+            );
+        }
+        if let Some(report) = &self.weekly_report {
+            check(
+                report.weekday().is_some(),
+                "weekly_report.day",
+                "expected a weekday like fri or friday",
+            );
+            check(
+                report.time().is_some(),
+                "weekly_report.at",
+                "expected a time as HH:MM",
+            );
+            if let Some(chat_id) = &report.chat_id {
+                check(valid_chat_id(chat_id), "weekly_report.chat_id", CHAT_ID);
+            }
+        }
+        if let Some(work_hours) = &self.work_hours {
+            for (day, hours) in work_hours.days() {
+                if let Some(hours) = hours {
+                    check(
+                        parse_hours_range(hours).is_some(),
+                        &format!("work_hours.{}", day),
+                        "expected a range like 09:00-18:00 that ends after it starts",
+                    );
+                }
+            }
+        }
+        if let Some(deep_focus) = &self.deep_focus {
+            check(
+                (1..=1440).contains(&deep_focus.snooze_minutes),
+                "deep_focus.snooze_minutes",
+                "must be between 1 and 1440",
+            );
+        }
+        if let Some(slack_dnd) = &self.slack_dnd {
+            check(
+                (1..=1440).contains(&slack_dnd.snooze_minutes),
+                "slack_dnd.snooze_minutes",
+                "must be between 1 and 1440",
+            );
+        }
+        if let Some(expiry) = self.mattermost.as_ref().and_then(|m| m.expiry_minutes) {
+            check(
+                expiry >= 1,
+                "mattermost.expiry_minutes",
+                "must be at least 1",
+            );
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+impl Settings {
+    /// Settings for tests: the required keys, then `toml` on top, with the history in memory.
+    pub(crate) fn for_tests(toml: &str) -> Self {
+        let toml = format!(
+            "bot_token = \"test\"\nchat_id = \"-1001\"\nminutes_till_afk = 10\nhistory_db = \":memory:\"\n{}",
+            toml
+        );
+        let mut settings: Settings = Config::builder()
+            .add_source(File::from_str(&toml, config::FileFormat::Toml))
+            .build()
+            .and_then(Config::try_deserialize)
+            .expect("test settings");
+        settings.apply_theme().expect("test theme");
+        settings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Loads `toml` the way `from_config` loads a file, with `variables` as the environment.
+    fn load(toml: &str, variables: &[(&str, &str)]) -> Result<Settings, Error> {
+        let toml = format!(
+            r#"bot_token = "test"
+chat_id = "-1001"
+minutes_till_afk = 10
+history_db = ":memory:"
+busy_chat_status = "Busy"
+break_chat_status = "On a break"
+not_working_status = "Not working"
+ngrok_authtoken = "test"
+ngrok_domain = "test.ngrok-free.app"
+{}"#,
+            toml
+        );
+        let variables = variables
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        let config = Config::builder()
+            .add_source(File::from_str(&toml, config::FileFormat::Toml))
+            .add_source(environment(Some(variables)))
+            .build()?;
+        Settings::from_loaded(config, None)
+    }
+
+    fn problems(toml: &str) -> Vec<String> {
+        match load(toml, &[]) {
+            Err(Error::InvalidSettings { problems, .. }) => problems,
+            other => panic!("expected invalid settings, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_problems() {
+        let mut problems = problems(
+            r#"afk_check_sec = 30
+[hue]
+bridge_ip = "10.0.0.2"
+app_key = "key"
+light = ["1"]"#,
+        );
+        // The keys come in no particular order.
+        problems.sort();
+        assert_eq!(
+            problems,
+            ["afk_check_sec: unknown key", "hue.light: unknown key"]
+        );
+    }
+
+    #[test]
+    fn a_value_of_the_wrong_type_stops_the_check() {
+        // The out-of-range `deep_focus.snooze_minutes` isn't reported until the type is fixed.
+        let problems = problems("afk_check_secs = \"soon\"\n[deep_focus]\nsnooze_minutes = 0");
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        assert!(
+            problems[0].starts_with("afk_check_secs: "),
+            "{}",
+            problems[0]
+        );
+        assert!(
+            problems[0].ends_with("(the rest is only checked once this is fixed)"),
+            "{}",
+            problems[0]
+        );
+    }
+
+    #[test]
+    fn out_of_range_values_are_problems() {
+        assert_eq!(
+            problems("afk_check_secs = 0\n[deep_focus]\nsnooze_minutes = 1441"),
+            [
+                "afk_check_secs: must be between 1 and 300",
+                "deep_focus.snooze_minutes: must be between 1 and 1440"
+            ]
+        );
+    }
+
+    #[test]
+    fn env_list_keys_are_list_settings() {
+        // A value each key's items parse from, and the other keys its section needs.
+        let lists = [
+            ("precedence", "override,timer,idle", ""),
+            (
+                "hue.lights",
+                "1,2",
+                "[hue]\nbridge_ip = \"10.0.0.2\"\napp_key = \"key\"",
+            ),
+            ("push.on", "break,not_working", "[push]"),
+        ];
+        assert_eq!(lists.map(|(key, _, _)| key), ENV_LIST_KEYS);
+        for (key, value, toml) in lists {
+            let variable = format!("AMIBUSSY_{}", key.to_uppercase().replace('.', "__"));
+            let settings =
+                load(toml, &[(&variable, value)]).unwrap_or_else(|err| panic!("{}: {}", key, err));
+            let items = match key {
+                "precedence" => settings.precedence.len(),
+                "hue.lights" => settings.hue.unwrap().lights.len(),
+                _ => settings.push.unwrap().on.len(),
+            };
+            assert_eq!(items, value.split(',').count(), "{}", key);
+        }
+    }
+}
@@ -0,0 +1,269 @@
+use crate::{
+    config::Settings,
+    error,
+    i18n::Text,
+    pause::{pause_sinks, resume_sinks},
+    presence::{emit_and_wait, PresenceEvent},
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use reqwest::Client;
+use std::{path::PathBuf, str::FromStr, time::Duration};
+use tracing::{error, info};
+
+/// Parses durations like `90s`, `30m` or `2h`.
+fn parse_duration(duration: &str) -> Result<u64> {
+    let split = duration
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(duration.len());
+    let (amount, unit) = duration.split_at(split);
+    let amount: u64 = amount
+        .parse()
+        .map_err(|_| anyhow::anyhow!("invalid duration {:?}", duration))?;
+    let multiplier = match unit {
+        "s" => 1,
+        "m" | "" => 60,
+        "h" => 3600,
+        _ => anyhow::bail!("invalid duration unit in {:?}, use s, m or h", duration),
+    };
+    amount
+        .checked_mul(multiplier)
+        .ok_or_else(|| anyhow::anyhow!("invalid duration {:?}, too long", duration))
+}
+
+/// Re-reads the settings file. If it doesn't load, the running settings are kept.
+///
+/// Tasks and the webhook server restart with the new settings; `control_addr`,
+/// `control_socket` and `history_db` only change with a full restart.
+pub async fn reload_settings(state: &AppState) -> error::Result<()> {
+    let path = state.settings().config_path.clone();
+    // `cmd:` secrets run their commands, so the settings load off the async workers.
+    let loaded = tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || Settings::from_config(path.as_deref())
+    })
+    .await
+    .unwrap_or_else(|err| {
+        Err(error::Error::InvalidSettings {
+            location: path.as_ref().map_or_else(
+                || "the environment".to_string(),
+                |path| path.display().to_string(),
+            ),
+            problems: vec![format!("loading failed: {}", err)],
+        })
+    });
+    match loaded {
+        Ok(settings) => {
+            match &path {
+                Some(path) => info!("[RELOAD] Settings reloaded from {}", path.display()),
+                None => info!("[RELOAD] Settings reloaded from the environment"),
+            }
+            state.replace_settings(settings);
+            state.diagnostics.ok("config", "reloaded");
+            Ok(())
+        }
+        Err(err) => {
+            error!("[RELOAD] Keeping the current settings: {}", err);
+            state
+                .diagnostics
+                .error("config", format!("reload failed: {}", err));
+            Err(err)
+        }
+    }
+}
+
+/// Watches for SIGHUP and for changes to the settings file, reloading on either.
+pub async fn watch_settings(state: AppState) {
+    let path = state.settings().config_path.clone();
+    // Without a settings file only SIGHUP triggers a reload.
+    let modified = |path: &Option<PathBuf>| {
+        std::fs::metadata(path.as_ref()?)
+            .and_then(|meta| meta.modified())
+            .ok()
+    };
+    let mut last_modified = modified(&path);
+    let mut poll = tokio::time::interval(Duration::from_secs(5));
+
+    #[cfg(unix)]
+    let mut hangup = match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) {
+        Ok(hangup) => Some(hangup),
+        Err(err) => {
+            error!("Failed to listen for SIGHUP: {}", err);
+            None
+        }
+    };
+
+    loop {
+        #[cfg(unix)]
+        let got_hangup = async {
+            match hangup.as_mut() {
+                Some(hangup) => {
+                    hangup.recv().await;
+                }
+                None => std::future::pending().await,
+            }
+        };
+        #[cfg(not(unix))]
+        let got_hangup = std::future::pending::<()>();
+
+        tokio::select! {
+            _ = got_hangup => {
+                info!("[RELOAD] Received SIGHUP");
+            }
+            _ = poll.tick() => {
+                let current = modified(&path);
+                if current == last_modified {
+                    continue;
+                }
+                last_modified = current;
+                if let Some(path) = &path {
+                    info!("[RELOAD] {} changed", path.display());
+                }
+            }
+        }
+        let _ = reload_settings(&state).await;
+    }
+}
+
+/// Runs one control command and returns the reply. Shared by the control socket and
+/// anything else that wants to drive the daemon with plain-text commands.
+///
+/// - `status`: the current status, what decided it, and the title
+/// - `set <status> [duration]`: pin a status, e.g. `set busy 30m`
+/// - `clear`: drop a pinned status
+/// - `pause`: freeze the sinks, still tracking the status
+/// - `resume`: unfreeze the sinks and bring them up to date
+/// - `reload`: re-read the settings file
+pub async fn handle_command(state: &AppState, line: &str) -> Result<String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    match words.as_slice() {
+        ["status"] => {
+            let decision = state.decide();
+            Ok(format!(
+                "{} {:?} {}",
+                decision.status.as_str(),
+                decision.source,
+                decision.status.title(&state.settings())
+            ))
+        }
+        ["set", status, rest @ ..] => {
+            let status = Status::from_str(status)?;
+            let until = match rest {
+                [] => None,
+                [duration] => Some(
+                    state
+                        .clock
+                        .now()
+                        .checked_add(parse_duration(duration)?)
+                        .ok_or_else(|| {
+                            anyhow::anyhow!("invalid duration {:?}, too long", duration)
+                        })?,
+                ),
+                _ => anyhow::bail!("usage: set <status> [duration]"),
+            };
+            let event = PresenceEvent::Override {
+                status: Some(status),
+                until,
+            };
+            emit_and_wait(state, event).await;
+            Ok(state
+                .settings()
+                .text(Text::CommandDone)
+                .replace("{title}", &state.effective_title()))
+        }
+        ["clear"] => {
+            let event = PresenceEvent::Override {
+                status: None,
+                until: None,
+            };
+            emit_and_wait(state, event).await;
+            Ok(state
+                .settings()
+                .text(Text::CommandDone)
+                .replace("{title}", &state.effective_title()))
+        }
+        ["pause"] => {
+            pause_sinks(state);
+            Ok(state.settings().text(Text::CommandPaused).to_string())
+        }
+        ["resume"] => {
+            resume_sinks(&Client::new(), state).await;
+            Ok(state
+                .settings()
+                .text(Text::CommandResumed)
+                .replace("{title}", &state.effective_title()))
+        }
+        ["reload"] => {
+            reload_settings(state).await?;
+            Ok(state.settings().text(Text::CommandReloaded).to_string())
+        }
+        _ => anyhow::bail!(
+            "unknown command {:?}, try status, set, clear, pause, resume or reload",
+            line.trim()
+        ),
+    }
+}
+
+/// Serves `handle_command` on a Unix socket, one command per line.
+#[cfg(unix)]
+pub async fn run_control_socket(state: AppState) -> Result<()> {
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+        net::UnixListener,
+    };
+
+    let path = shellexpand::tilde(&state.settings().control_socket).to_string();
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // A socket left behind by a previous run would make bind fail.
+    let _ = std::fs::remove_file(&path);
+    let listener = UnixListener::bind(&path)?;
+    info!("Control socket listening on {}", path);
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let state = state.clone();
+        tokio::spawn(async move {
+            let (reader, mut writer) = stream.into_split();
+            let mut lines = BufReader::new(reader).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                let reply = match handle_command(&state, &line).await {
+                    Ok(reply) => reply,
+                    Err(err) => format!("error: {}", err),
+                };
+                if let Err(err) = writer.write_all(format!("{}\n", reply).as_bytes()).await {
+                    error!("Control socket write failed: {}", err);
+                    break;
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90s").unwrap(), 90);
+        assert_eq!(parse_duration("30m").unwrap(), 30 * 60);
+        assert_eq!(parse_duration("30").unwrap(), 30 * 60);
+        assert_eq!(parse_duration("2h").unwrap(), 2 * 3600);
+    }
+
+    #[test]
+    fn rejects_invalid_durations() {
+        for duration in ["", "m", "-5m", "1.5h", "5d", "5 m", "5mm"] {
+            assert!(parse_duration(duration).is_err(), "{:?}", duration);
+        }
+    }
+
+    #[test]
+    fn rejects_durations_that_overflow() {
+        assert!(parse_duration(&format!("{}h", u64::MAX / 3600 + 1)).is_err());
+        assert!(parse_duration(&format!("{}s", u64::MAX)).is_ok());
+        assert!(parse_duration("99999999999999999999999s").is_err());
+    }
+}
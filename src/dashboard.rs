@@ -0,0 +1,264 @@
+use crate::{
+    clock::local_time,
+    limits::constant_time_eq,
+    state::{day_of, day_start_timestamp, AppState, Status},
+};
+use axum::{
+    extract::{Query, State},
+    http::{header, HeaderMap},
+    response::{Html, IntoResponse, Json, Response},
+};
+use chrono_tz::Tz;
+use hyper::StatusCode;
+use std::{fmt::Write, sync::atomic::Ordering};
+use tracing::error;
+
+#[derive(Debug, serde::Deserialize)]
+pub struct DashboardQuery {
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// A stretch of the day spent in one status.
+struct Block {
+    start: u64,
+    end: u64,
+    status: Status,
+}
+
+fn color(status: Status) -> &'static str {
+    match status {
+        Status::Busy => "#d9534f",
+        Status::ForgotTimer => "#f0ad4e",
+        Status::Break => "#5cb85c",
+        Status::NotWorking => "#999999",
+    }
+}
+
+fn format_time(timezone: Option<Tz>, timestamp: u64) -> String {
+    local_time(timezone, timestamp).format("%H:%M").to_string()
+}
+
+/// Builds today's blocks from the history store, starting with whatever status was in
+/// effect when the day began.
+fn today_blocks(state: &AppState) -> anyhow::Result<Vec<Block>> {
+    let Some(history) = &state.history else {
+        anyhow::bail!("status history is not available");
+    };
+    let now = state.clock.now();
+    let day_start_hour = state.settings().day_start_hour;
+    let timezone = state.settings().timezone;
+    let start = day_start_timestamp(
+        day_of(now, day_start_hour, timezone),
+        day_start_hour,
+        timezone,
+    )
+    .ok_or_else(|| anyhow::anyhow!("invalid day start"))?;
+
+    let mut status = history
+        .last_before(start)?
+        .map(|transition| transition.to)
+        .unwrap_or(Status::NotWorking);
+    let mut block_start = start;
+    let mut blocks = Vec::new();
+    for transition in history.between(start, now + 1)? {
+        if transition.at > block_start {
+            blocks.push(Block {
+                start: block_start,
+                end: transition.at,
+                status,
+            });
+        }
+        status = transition.to;
+        block_start = transition.at;
+    }
+    if now > block_start {
+        blocks.push(Block {
+            start: block_start,
+            end: now,
+            status,
+        });
+    }
+    Ok(blocks)
+}
+
+fn render(state: &AppState, blocks: &[Block]) -> String {
+    let total = blocks
+        .iter()
+        .map(|block| block.end - block.start)
+        .sum::<u64>()
+        .max(1);
+
+    let settings = state.settings();
+    let mut timeline = String::new();
+    let mut rows = String::new();
+    for block in blocks {
+        let width = (block.end - block.start) as f64 * 100.0 / total as f64;
+        let title = block.status.title(&settings);
+        let _ = write!(
+            timeline,
+            r#"<div style="width:{:.3}%;background:{}" title="{} {}–{}"></div>"#,
+            width,
+            color(block.status),
+            escape(title),
+            format_time(settings.timezone, block.start),
+            format_time(settings.timezone, block.end),
+        );
+        let _ = write!(
+            rows,
+            "<tr><td>{}</td><td>{}</td><td>{}m</td><td>{}</td></tr>",
+            format_time(settings.timezone, block.start),
+            format_time(settings.timezone, block.end),
+            (block.end - block.start) / 60,
+            escape(title),
+        );
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="60">
+<title>amibussy</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; }}
+.timeline {{ display: flex; height: 3em; border-radius: 4px; overflow: hidden; }}
+table {{ margin-top: 1.5em; border-collapse: collapse; }}
+td {{ padding: 0.2em 1em 0.2em 0; }}
+</style>
+</head>
+<body>
+<h2>Now: {}</h2>
+<div class="timeline">{}</div>
+<table>{}</table>
+</body>
+</html>"#,
+        escape(&state.effective_title()),
+        timeline,
+        rows,
+    )
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// `GET /dashboard?token=...`: today's timeline of statuses from the history store.
+pub async fn dashboard(
+    State(state): State<AppState>,
+    Query(query): Query<DashboardQuery>,
+) -> Response {
+    let Some(settings) = &state.settings().dashboard else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !constant_time_eq(
+        query.token.as_deref().unwrap_or_default().as_bytes(),
+        settings.token.as_bytes(),
+    ) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    match today_blocks(&state) {
+        Ok(blocks) => Html(render(&state, &blocks)).into_response(),
+        Err(err) => {
+            error!("Failed to build dashboard: {}", err);
+            (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response()
+        }
+    }
+}
+
+/// One person's row on the team page.
+#[derive(Debug, serde::Serialize)]
+struct TeamMember {
+    name: String,
+    toggl_user_id: Option<i64>,
+    status: Status,
+    title: String,
+    since: u64,
+    /// On a break, or working without a timer: fine to walk up to.
+    interruptible: bool,
+}
+
+fn team_member(state: &AppState) -> TeamMember {
+    let settings = state.settings();
+    let status = state.decide().status;
+    TeamMember {
+        name: settings.name.clone().unwrap_or_else(|| "me".to_string()),
+        toggl_user_id: settings.toggl_user_id,
+        status,
+        title: state.effective_title(),
+        since: state.status_since.load(Ordering::Relaxed),
+        interruptible: matches!(status, Status::Break | Status::ForgotTimer),
+    }
+}
+
+fn render_team(state: &AppState, members: &[TeamMember]) -> String {
+    let timezone = state.settings().timezone;
+    let mut rows = String::new();
+    for member in members {
+        let _ = write!(
+            rows,
+            r#"<tr><td><span class="dot" style="background:{}"></span>{}</td><td>{}</td><td>since {}</td></tr>"#,
+            color(member.status),
+            escape(&member.name),
+            escape(&member.title),
+            format_time(timezone, member.since),
+        );
+    }
+
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta http-equiv="refresh" content="30">
+<title>amibussy team</title>
+<style>
+body {{ font-family: sans-serif; margin: 2em; font-size: 1.5em; }}
+td {{ padding: 0.3em 1.5em 0.3em 0; }}
+.dot {{ display: inline-block; width: 0.8em; height: 0.8em; border-radius: 50%; margin-right: 0.5em; }}
+</style>
+</head>
+<body>
+<table>{}</table>
+</body>
+</html>"#,
+        rows,
+    )
+}
+
+/// `GET /team?token=...`: everyone's current status, as JSON or, for browsers, a page to put
+/// on an office screen. Uses the `dashboard` token.
+pub async fn team(
+    State(state): State<AppState>,
+    Query(query): Query<DashboardQuery>,
+    headers: HeaderMap,
+) -> Response {
+    let Some(settings) = &state.settings().dashboard else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !constant_time_eq(
+        query.token.as_deref().unwrap_or_default().as_bytes(),
+        settings.token.as_bytes(),
+    ) {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let members = std::iter::once(&state)
+        .chain(state.team.values())
+        .map(team_member)
+        .collect::<Vec<_>>();
+    let wants_html = headers
+        .get(header::ACCEPT)
+        .and_then(|accept| accept.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/html"));
+    if wants_html {
+        Html(render_team(&state, &members)).into_response()
+    } else {
+        Json(members).into_response()
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{
+    config::AdminAlertSettings,
+    sinks::telegram::send_message,
+    state::{get_unix_timestamp, AppState},
+    summary::format_duration,
+};
+use reqwest::Client;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+use tokio::time::interval;
+use tracing::{info, warn};
+
+/// Health of one subsystem (tunnel, a source, a sink), as reported by the subsystem itself.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct SubsystemHealth {
+    pub healthy: bool,
+    pub detail: String,
+    pub updated_at: u64,
+    pub last_error: Option<LastError>,
+    /// When the current run of errors started; `None` while healthy.
+    #[serde(default)]
+    pub failing_since: Option<u64>,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct LastError {
+    pub at: u64,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    subsystems: Mutex<BTreeMap<String, SubsystemHealth>>,
+}
+
+impl Diagnostics {
+    pub fn ok(&self, subsystem: &str, detail: impl Into<String>) {
+        let now = get_unix_timestamp().unwrap_or_default();
+        let mut subsystems = self
+            .subsystems
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let last_error = subsystems
+            .get(subsystem)
+            .and_then(|health| health.last_error.clone());
+        subsystems.insert(
+            subsystem.to_string(),
+            SubsystemHealth {
+                healthy: true,
+                detail: detail.into(),
+                updated_at: now,
+                last_error,
+                failing_since: None,
+            },
+        );
+    }
+
+    pub fn error(&self, subsystem: &str, message: impl Into<String>) {
+        let now = get_unix_timestamp().unwrap_or_default();
+        let message = message.into();
+        let mut subsystems = self
+            .subsystems
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let failing_since = subsystems
+            .get(subsystem)
+            .and_then(|health| health.failing_since)
+            .unwrap_or(now);
+        subsystems.insert(
+            subsystem.to_string(),
+            SubsystemHealth {
+                healthy: false,
+                detail: message.clone(),
+                updated_at: now,
+                last_error: Some(LastError { at: now, message }),
+                failing_since: Some(failing_since),
+            },
+        );
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<String, SubsystemHealth> {
+        self.subsystems
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+/// DMs `alerts.chat_id` when a subsystem has been failing for `alerts.after_minutes`, and
+/// again once it recovers.
+pub async fn admin_alerter(
+    state: AppState,
+    alerts: AdminAlertSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(30));
+    let mut alerted = HashSet::new();
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down admin_alerter");
+                break;
+            }
+        }
+
+        let now = state.clock.now();
+        for (subsystem, health) in state.diagnostics.snapshot() {
+            let text = match health.failing_since {
+                Some(since)
+                    if now.saturating_sub(since) >= alerts.after_minutes * 60
+                        && !alerted.contains(&subsystem) =>
+                {
+                    alerted.insert(subsystem.clone());
+                    format!(
+                        "amibussy: {} has been failing for {}: {}",
+                        subsystem,
+                        format_duration(now.saturating_sub(since)),
+                        health.detail
+                    )
+                }
+                None if alerted.remove(&subsystem) => {
+                    format!("amibussy: {} works again: {}", subsystem, health.detail)
+                }
+                _ => continue,
+            };
+            warn!("[ADMIN ALERT] {}", text);
+            send_message(&client, &state.settings(), &alerts.chat_id, &text).await;
+        }
+    }
+}
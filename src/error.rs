@@ -0,0 +1,24 @@
+use std::time::SystemTimeError;
+
+/// Errors the daemon can run into and has to handle rather than panic on. Most code still
+/// returns `anyhow::Result`, which these convert into with `?`.
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// The settings file or environment couldn't be read.
+    #[error("failed to load the settings: {0}")]
+    LoadSettings(#[from] config::ConfigError),
+    /// The settings loaded but have problems, one `path: problem` each.
+    #[error(
+        "invalid settings in {location}:\n{}",
+        problems.iter().map(|problem| format!("  - {}", problem)).collect::<Vec<_>>().join("\n")
+    )]
+    InvalidSettings {
+        location: String,
+        problems: Vec<String>,
+    },
+    /// The system clock reads before 1970, e.g. on a board without an RTC before NTP syncs.
+    #[error("the system clock is set before the UNIX epoch: {0}")]
+    Clock(#[from] SystemTimeError),
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
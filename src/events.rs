@@ -0,0 +1,152 @@
+use crate::{history::Transition, state::AppState};
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Query, State,
+    },
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        Response,
+    },
+};
+use serde_json::{json, Value};
+use std::{convert::Infallible, time::Duration};
+use tokio::sync::broadcast::error::RecvError;
+use tokio_stream::{
+    once,
+    wrappers::{errors::BroadcastStreamRecvError, BroadcastStream},
+    Stream, StreamExt,
+};
+use tracing::{error, warn};
+
+fn transition_json(state: &AppState, transition: &Transition) -> Value {
+    json!({
+        "type": "transition",
+        "at": transition.at,
+        "from": transition.from,
+        "to": transition.to,
+        "title": transition.to.title(&state.settings()),
+        "cause": transition.cause,
+        "payload_id": transition.payload_id,
+    })
+}
+
+fn status_json(state: &AppState) -> Value {
+    let decision = state.decide();
+    json!({
+        "type": "status",
+        "status": decision.status,
+        "decided_by": decision.source,
+        "title": decision.status.title(&state.settings()),
+    })
+}
+
+fn transition_event(state: &AppState, transition: &Transition) -> Event {
+    Event::default()
+        .event("transition")
+        .id(transition.at.to_string())
+        .data(transition_json(state, transition).to_string())
+}
+
+/// `GET /events`: the current status once, then a `transition` event on every change.
+pub async fn sse_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let current = Event::default()
+        .event("status")
+        .data(status_json(&state).to_string());
+
+    let transitions =
+        BroadcastStream::new(state.transitions.subscribe()).filter_map(move |transition| {
+            match transition {
+                Ok(transition) => Some(Ok(transition_event(&state, &transition))),
+                Err(BroadcastStreamRecvError::Lagged(skipped)) => {
+                    warn!("SSE subscriber lagged, skipped {} transitions", skipped);
+                    None
+                }
+            }
+        });
+
+    Sse::new(once(Ok(current)).chain(transitions)).keep_alive(KeepAlive::default())
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct WsQuery {
+    /// Replay transitions recorded at or after this UNIX timestamp, e.g. the `at` of the
+    /// last event a reconnecting client saw plus one.
+    #[serde(default)]
+    since: Option<u64>,
+}
+
+/// `GET /ws`: like `/events`, but over a WebSocket, with `?since=` replay from the history.
+pub async fn ws_events(
+    State(state): State<AppState>,
+    Query(query): Query<WsQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| ws_session(state, query.since, socket))
+}
+
+async fn ws_session(state: AppState, since: Option<u64>, mut socket: WebSocket) {
+    // Subscribe before replaying so nothing falls between the two.
+    let mut transitions = state.transitions.subscribe();
+    let mut last_sent = 0;
+
+    if let (Some(since), Some(history)) = (since, &state.history) {
+        match history.between(since, state.clock.now() + 1) {
+            Ok(replay) => {
+                for transition in replay {
+                    last_sent = transition.at;
+                    let message = transition_json(&state, &transition).to_string();
+                    if socket.send(Message::Text(message)).await.is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(err) => error!("Failed to replay transitions since {}: {}", since, err),
+        }
+    }
+    if socket
+        .send(Message::Text(status_json(&state).to_string()))
+        .await
+        .is_err()
+    {
+        return;
+    }
+
+    let mut ping = tokio::time::interval(Duration::from_secs(30));
+    loop {
+        tokio::select! {
+            _ = ping.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    return;
+                }
+            }
+            transition = transitions.recv() => {
+                let transition = match transition {
+                    Ok(transition) => transition,
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("WebSocket subscriber lagged, skipped {} transitions", skipped);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                // Already sent as part of the replay.
+                if transition.at < last_sent {
+                    continue;
+                }
+                let message = transition_json(&state, &transition).to_string();
+                if socket.send(Message::Text(message)).await.is_err() {
+                    return;
+                }
+            }
+            message = socket.recv() => {
+                // Pongs are answered by the library; we only care about the client leaving.
+                match message {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                    Some(Ok(_)) => {}
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crate::{config::ActivitySignal, state::Status};
+use anyhow::Result;
+use rusqlite::{params, Connection};
+use std::{
+    path::Path,
+    str::FromStr,
+    sync::{Mutex, PoisonError},
+};
+
+/// One change of the effective status.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Transition {
+    pub at: u64,
+    pub from: Status,
+    pub to: Status,
+    /// What triggered it, e.g. `toggl_stop` or `afk_timeout`.
+    pub cause: String,
+    /// ID of the source event (Toggl `event_id`, ...), if there was one.
+    pub payload_id: Option<String>,
+}
+
+/// Every status transition, plus the ActivityWatch categories of the active window, persisted
+/// in SQLite for reports and debugging.
+#[derive(Debug)]
+pub struct History {
+    conn: Mutex<Connection>,
+}
+
+impl History {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        if let Some(parent) = path.as_ref().parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS transitions (
+                id INTEGER PRIMARY KEY,
+                at INTEGER NOT NULL,
+                from_status TEXT NOT NULL,
+                to_status TEXT NOT NULL,
+                cause TEXT NOT NULL,
+                payload_id TEXT
+            );
+            CREATE INDEX IF NOT EXISTS transitions_at ON transitions (at);
+            CREATE TABLE IF NOT EXISTS activity (
+                id INTEGER PRIMARY KEY,
+                at INTEGER NOT NULL,
+                app TEXT NOT NULL,
+                category TEXT,
+                signal TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS activity_at ON activity (at);",
+        )?;
+        Ok(History {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn record(&self, transition: &Transition) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .execute(
+                "INSERT INTO transitions (at, from_status, to_status, cause, payload_id)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    transition.at as i64,
+                    transition.from.as_str(),
+                    transition.to.as_str(),
+                    transition.cause,
+                    transition.payload_id,
+                ],
+            )?;
+        Ok(())
+    }
+
+    /// Notes that the active window changed to `app`, in `category` if one matched it.
+    pub fn record_activity(
+        &self,
+        at: u64,
+        app: &str,
+        category: Option<&str>,
+        signal: ActivitySignal,
+    ) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .execute(
+                "INSERT INTO activity (at, app, category, signal) VALUES (?1, ?2, ?3, ?4)",
+                params![at as i64, app, category, signal.as_str()],
+            )?;
+        Ok(())
+    }
+
+    /// Transitions in `[from, to)`, oldest first.
+    pub fn between(&self, from: u64, to: u64) -> Result<Vec<Transition>> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut statement = conn.prepare(
+            "SELECT at, from_status, to_status, cause, payload_id FROM transitions
+             WHERE at >= ?1 AND at < ?2 ORDER BY at, id",
+        )?;
+        let rows = statement.query_map(params![from as i64, to as i64], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+            ))
+        })?;
+
+        let mut transitions = Vec::new();
+        for row in rows {
+            let (at, from_status, to_status, cause, payload_id) = row?;
+            transitions.push(Transition {
+                at: at as u64,
+                from: Status::from_str(&from_status)?,
+                to: Status::from_str(&to_status)?,
+                cause,
+                payload_id,
+            });
+        }
+        Ok(transitions)
+    }
+
+    /// The last transition before `at`, i.e. the status that was in effect at that moment.
+    pub fn last_before(&self, at: u64) -> Result<Option<Transition>> {
+        let conn = self.conn.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut statement = conn.prepare(
+            "SELECT at, from_status, to_status, cause, payload_id FROM transitions
+             WHERE at < ?1 ORDER BY at DESC, id DESC LIMIT 1",
+        )?;
+        let mut rows = statement.query(params![at as i64])?;
+        let Some(row) = rows.next()? else {
+            return Ok(None);
+        };
+        Ok(Some(Transition {
+            at: row.get::<_, i64>(0)? as u64,
+            from: Status::from_str(&row.get::<_, String>(1)?)?,
+            to: Status::from_str(&row.get::<_, String>(2)?)?,
+            cause: row.get(3)?,
+            payload_id: row.get(4)?,
+        }))
+    }
+}
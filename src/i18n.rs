@@ -0,0 +1,187 @@
+/// The language of the texts amibussy writes on its own: default titles, the summary and
+/// report, and the bot's replies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Locale {
+    #[default]
+    En,
+    Ru,
+    De,
+}
+
+/// A text with a translation, named as in `messages`. Placeholders in braces are filled in
+/// where it's used.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Text {
+    /// `end_of_day.title`.
+    EndOfDayTitle,
+    /// `deep_focus.title`.
+    DeepFocusTitle,
+    /// `end_of_day.overwork_message`: `{at}`, `{elapsed}`.
+    OverworkMessage,
+    /// To `long_entry.alert_chat_id`: `{hours}`.
+    LongEntryAlert,
+    /// `out_of_office.title`.
+    OutOfOfficeTitle,
+    /// `status_message.template` and `status_panel.template`: `{title}`, `{since}`.
+    StatusMessage,
+    /// `{day}`, `{tracked}`, `{entries}`, `{longest}`, `{breaks}`, `{afk}`, `{changes}`.
+    Summary,
+    /// The summary's `{day}` before the first event of the day.
+    Today,
+    /// `{day}`.
+    ReportDay,
+    /// `{day}`: the Monday.
+    ReportWeek,
+    /// `{day}`, `{busy}`, `{breaks}`, `{afk}`.
+    ReportLine,
+    /// `{busy}`, `{breaks}`, `{afk}`.
+    ReportTotal,
+    /// Monday to Sunday, separated by spaces.
+    Weekdays,
+    /// To someone not in `allowed_user_ids`.
+    Refusal,
+    /// A command pinned or unpinned a status: `{title}`.
+    CommandDone,
+    CommandPaused,
+    /// `{title}`.
+    CommandResumed,
+    CommandReloaded,
+    /// A status panel button was pressed: `{title}`.
+    PanelDone,
+    UnknownButton,
+    PanelBusy,
+    PanelBreak,
+    PanelOff,
+    /// `{minutes}`.
+    PanelExtend,
+    PanelAuto,
+    Pinned,
+    /// `{until}`.
+    PinnedUntil,
+}
+
+impl Text {
+    pub fn translated(self, locale: Locale) -> &'static str {
+        match locale {
+            Locale::En => self.en(),
+            Locale::Ru => self.ru(),
+            Locale::De => self.de(),
+        }
+    }
+
+    fn en(self) -> &'static str {
+        match self {
+            Text::EndOfDayTitle => "🌙 Done for today",
+            Text::DeepFocusTitle => "⛔ Deep focus, do not disturb",
+            Text::OverworkMessage => {
+                "It's past {at} and your timer has been running for {elapsed}. Time to call it a day?"
+            }
+            Text::LongEntryAlert => {
+                "Your Toggl timer has been running for more than {hours} hours. Did you forget to stop it?"
+            }
+            Text::OutOfOfficeTitle => "🌴 Out of office",
+            Text::StatusMessage => "Status: {title} since {since}",
+            Text::Summary => {
+                "Summary for {day}\nTracked: {tracked} in {entries} entries\nLongest focus block: {longest}\nBreaks: {breaks}, AFK: {afk}\nStatus changes: {changes}"
+            }
+            Text::Today => "today",
+            Text::ReportDay => "Report for {day}",
+            Text::ReportWeek => "Week of {day}",
+            Text::ReportLine => "{day}: busy {busy}, breaks {breaks}, AFK {afk}",
+            Text::ReportTotal => "Total: busy {busy}, breaks {breaks}, AFK {afk}",
+            Text::Weekdays => "Mon Tue Wed Thu Fri Sat Sun",
+            Text::Refusal => "Sorry, only the owner of this status can change it.",
+            Text::CommandDone => "ok, now {title}",
+            Text::CommandPaused => "ok, sinks paused",
+            Text::CommandResumed => "ok, sinks resumed at {title}",
+            Text::CommandReloaded => "ok, settings reloaded",
+            Text::PanelDone => "Now {title}",
+            Text::UnknownButton => "Unknown button",
+            Text::PanelBusy => "🔴 Busy",
+            Text::PanelBreak => "☕ Break",
+            Text::PanelOff => "💤 Off",
+            Text::PanelExtend => "⏱ +{minutes}m",
+            Text::PanelAuto => "🔄 Auto",
+            Text::Pinned => "📌 Pinned by hand",
+            Text::PinnedUntil => "📌 Pinned by hand until {until}",
+        }
+    }
+
+    fn ru(self) -> &'static str {
+        match self {
+            Text::EndOfDayTitle => "🌙 На сегодня всё",
+            Text::DeepFocusTitle => "⛔ Глубокий фокус, не беспокоить",
+            Text::OverworkMessage => {
+                "Уже больше {at}, а таймер идёт {elapsed}. Может, пора заканчивать?"
+            }
+            Text::LongEntryAlert => {
+                "Таймер Toggl идёт уже больше {hours} ч. Может, забыли его остановить?"
+            }
+            Text::OutOfOfficeTitle => "🌴 Не на работе",
+            Text::StatusMessage => "Статус: {title} с {since}",
+            Text::Summary => {
+                "Итоги за {day}\nЗатрекано: {tracked}, записей: {entries}\nСамый долгий фокус: {longest}\nПерерывы: {breaks}, AFK: {afk}\nСмен статуса: {changes}"
+            }
+            Text::Today => "сегодня",
+            Text::ReportDay => "Отчёт за {day}",
+            Text::ReportWeek => "Неделя с {day}",
+            Text::ReportLine => "{day}: работа {busy}, перерывы {breaks}, AFK {afk}",
+            Text::ReportTotal => "Итого: работа {busy}, перерывы {breaks}, AFK {afk}",
+            Text::Weekdays => "Пн Вт Ср Чт Пт Сб Вс",
+            Text::Refusal => "Извините, менять этот статус может только его владелец.",
+            Text::CommandDone => "ок, теперь {title}",
+            Text::CommandPaused => "ок, обновления приостановлены",
+            Text::CommandResumed => "ок, обновления возобновлены: {title}",
+            Text::CommandReloaded => "ок, настройки перечитаны",
+            Text::PanelDone => "Теперь {title}",
+            Text::UnknownButton => "Неизвестная кнопка",
+            Text::PanelBusy => "🔴 Занят",
+            Text::PanelBreak => "☕ Перерыв",
+            Text::PanelOff => "💤 Не работаю",
+            Text::PanelExtend => "⏱ +{minutes} мин",
+            Text::PanelAuto => "🔄 Авто",
+            Text::Pinned => "📌 Закреплено вручную",
+            Text::PinnedUntil => "📌 Закреплено вручную до {until}",
+        }
+    }
+
+    fn de(self) -> &'static str {
+        match self {
+            Text::EndOfDayTitle => "🌙 Feierabend",
+            Text::DeepFocusTitle => "⛔ Tiefe Konzentration, bitte nicht stören",
+            Text::OverworkMessage => {
+                "Es ist nach {at} und dein Timer läuft seit {elapsed}. Zeit für den Feierabend?"
+            }
+            Text::LongEntryAlert => {
+                "Dein Toggl-Timer läuft seit mehr als {hours} Stunden. Vergessen, ihn zu stoppen?"
+            }
+            Text::OutOfOfficeTitle => "🌴 Nicht im Büro",
+            Text::StatusMessage => "Status: {title} seit {since}",
+            Text::Summary => {
+                "Zusammenfassung für {day}\nErfasst: {tracked} in {entries} Einträgen\nLängster Fokusblock: {longest}\nPausen: {breaks}, AFK: {afk}\nStatuswechsel: {changes}"
+            }
+            Text::Today => "heute",
+            Text::ReportDay => "Bericht für {day}",
+            Text::ReportWeek => "Woche ab {day}",
+            Text::ReportLine => "{day}: beschäftigt {busy}, Pausen {breaks}, AFK {afk}",
+            Text::ReportTotal => "Gesamt: beschäftigt {busy}, Pausen {breaks}, AFK {afk}",
+            Text::Weekdays => "Mo Di Mi Do Fr Sa So",
+            Text::Refusal => "Sorry, nur der Inhaber dieses Status kann ihn ändern.",
+            Text::CommandDone => "ok, jetzt {title}",
+            Text::CommandPaused => "ok, Aktualisierungen pausiert",
+            Text::CommandResumed => "ok, Aktualisierungen fortgesetzt: {title}",
+            Text::CommandReloaded => "ok, Einstellungen neu geladen",
+            Text::PanelDone => "Jetzt {title}",
+            Text::UnknownButton => "Unbekannte Schaltfläche",
+            Text::PanelBusy => "🔴 Beschäftigt",
+            Text::PanelBreak => "☕ Pause",
+            Text::PanelOff => "💤 Frei",
+            Text::PanelExtend => "⏱ +{minutes} Min.",
+            Text::PanelAuto => "🔄 Auto",
+            Text::Pinned => "📌 Von Hand festgelegt",
+            Text::PinnedUntil => "📌 Von Hand festgelegt bis {until}",
+        }
+    }
+}
@@ -0,0 +1,31 @@
+pub mod acme;
+pub mod bot;
+pub mod capture;
+pub mod cli;
+pub mod clock;
+pub mod config;
+pub mod control;
+pub mod dashboard;
+pub mod diagnostics;
+pub mod error;
+pub mod events;
+pub mod history;
+pub mod i18n;
+pub mod limits;
+pub mod logging;
+pub mod out_of_office;
+pub mod pause;
+pub mod presence;
+pub mod replay;
+pub mod report;
+pub mod server;
+pub mod service;
+pub mod sinks;
+pub mod sources;
+pub mod state;
+pub mod summary;
+pub mod supervisor;
+pub mod telegram;
+pub mod theme;
+pub mod tls;
+pub mod tunnel;
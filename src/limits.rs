@@ -0,0 +1,215 @@
+use crate::{config::TunnelKind, state::AppState};
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::header,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use hyper::StatusCode;
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::{Mutex, PoisonError},
+    time::Instant,
+};
+use tracing::warn;
+
+/// Above this many tracked clients, those with a full bucket again are forgotten.
+const MAX_TRACKED_CLIENTS: usize = 1024;
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+/// A token bucket per client IP: `burst` requests at once, refilled at `per_minute`.
+#[derive(Default)]
+pub struct RateLimiter {
+    buckets: Mutex<HashMap<Option<IpAddr>, Bucket>>,
+}
+
+impl RateLimiter {
+    /// Takes a token from `client`'s bucket, or returns false if it's empty. Clients whose
+    /// address is unknown share one bucket.
+    pub fn allow(&self, client: Option<IpAddr>, per_minute: u32, burst: u32) -> bool {
+        let now = Instant::now();
+        let burst = f64::from(burst);
+        let refill = |bucket: &mut Bucket| {
+            let elapsed = now.duration_since(bucket.refilled_at).as_secs_f64();
+            bucket.tokens = (bucket.tokens + elapsed * f64::from(per_minute) / 60.0).min(burst);
+            bucket.refilled_at = now;
+        };
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+        if buckets.len() > MAX_TRACKED_CLIENTS {
+            buckets.retain(|_, bucket| {
+                refill(bucket);
+                bucket.tokens < burst
+            });
+        }
+        let bucket = buckets.entry(client).or_insert(Bucket {
+            tokens: burst,
+            refilled_at: now,
+        });
+        refill(bucket);
+        if bucket.tokens < 1.0 {
+            return false;
+        }
+        bucket.tokens -= 1.0;
+        true
+    }
+}
+
+/// The address the request's connection comes from, as `serve_connections` notes it.
+fn peer_ip(request: &Request) -> Option<IpAddr> {
+    request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip())
+}
+
+/// The address Tailscale Funnel appended to `X-Forwarded-For`: the rightmost one, as anything
+/// before it is whatever the caller chose to send.
+fn forwarded_ip(request: &Request) -> Option<IpAddr> {
+    request
+        .headers()
+        .get("x-forwarded-for")?
+        .to_str()
+        .ok()?
+        .rsplit(',')
+        .next()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Who the request is from, for rate limiting and logs. ngrok hands over connections with the
+/// caller's address as their peer, and so does a direct listener; Funnel proxies through the
+/// local `tailscaled`, so there the one hop it adds to `X-Forwarded-For` is trusted instead.
+fn client_ip(tunnel: &TunnelKind, request: &Request) -> Option<IpAddr> {
+    match tunnel {
+        TunnelKind::Tailscale => forwarded_ip(request).or_else(|| peer_ip(request)),
+        TunnelKind::Ngrok | TunnelKind::Direct => peer_ip(request),
+    }
+}
+
+/// Compares without bailing out at the first difference, so timing doesn't leak the secret.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+/// With `webhook_auth`, only `/webhook/<path_token>` exists (anything else is a 404, so the
+/// route doesn't give itself away) and requests need the configured header.
+pub async fn check_webhook_auth(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let settings = state.settings();
+    let Some(auth) = settings.webhook_auth.clone() else {
+        return next.run(request).await;
+    };
+    let expected_path = match &auth.path_token {
+        Some(token) => format!("/webhook/{}", token),
+        None => "/webhook".to_string(),
+    };
+    if !constant_time_eq(request.uri().path().as_bytes(), expected_path.as_bytes()) {
+        warn!(
+            "Webhook request to an unknown path from {:?}",
+            client_ip(&settings.tunnel, &request)
+        );
+        return StatusCode::NOT_FOUND.into_response();
+    }
+    if let (Some(name), Some(value)) = (&auth.header, &auth.header_value) {
+        let sent = request
+            .headers()
+            .get(name.as_str())
+            .map(|sent| sent.as_bytes())
+            .unwrap_or_default();
+        if !constant_time_eq(sent, value.as_bytes()) {
+            warn!(
+                "Webhook request without a valid {} header from {:?}",
+                name,
+                client_ip(&settings.tunnel, &request)
+            );
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+/// Rejects what Toggl would never send, before the handler sees it: more than
+/// `webhook_limits.requests_per_minute` from one address and POSTs that aren't JSON. The body
+/// size is capped separately with `DefaultBodyLimit`. Also layered on the routes that take a
+/// `?token=` (`/dashboard`, `/team`, `/tempo`), which share the buckets.
+pub async fn limit_webhook(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let settings = state.settings();
+    let limits = settings.webhook_limits.clone();
+    let client = client_ip(&settings.tunnel, &request);
+    if !state
+        .webhook_limiter
+        .allow(client, limits.requests_per_minute, limits.burst)
+    {
+        warn!("Rate limited a webhook request from {:?}", client);
+        return StatusCode::TOO_MANY_REQUESTS.into_response();
+    }
+
+    if request.method() == axum::http::Method::POST {
+        let is_json = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/json"));
+        if !is_json {
+            warn!("Rejected a non-JSON webhook request from {:?}", client);
+            return StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response();
+        }
+    }
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    fn request_from(peer: &str, forwarded_for: &str) -> Request {
+        let mut request = Request::builder()
+            .header("x-forwarded-for", forwarded_for)
+            .body(Body::empty())
+            .unwrap();
+        request
+            .extensions_mut()
+            .insert(ConnectInfo(peer.parse::<SocketAddr>().unwrap()));
+        request
+    }
+
+    #[test]
+    fn spoofed_forwarded_for_doesnt_reset_the_bucket() {
+        let limiter = RateLimiter::default();
+        for tunnel in [TunnelKind::Ngrok, TunnelKind::Direct] {
+            let allowed = (1..=3)
+                .map(|i| {
+                    let request = request_from("203.0.113.7:443", &format!("198.51.100.{}", i));
+                    limiter.allow(client_ip(&tunnel, &request), 60, 2)
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(allowed, [true, true, false], "{:?}", tunnel);
+            limiter.buckets.lock().unwrap().clear();
+        }
+    }
+
+    #[test]
+    fn funnel_trusts_only_the_hop_it_added() {
+        let request = request_from("127.0.0.1:40000", "198.51.100.1, 203.0.113.7");
+        assert_eq!(
+            client_ip(&TunnelKind::Tailscale, &request),
+            Some("203.0.113.7".parse().unwrap())
+        );
+    }
+}
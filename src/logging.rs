@@ -0,0 +1,51 @@
+use crate::{
+    clock::LogTimer,
+    config::{LogFileSettings, LogFormat, LogRotation, Settings},
+};
+use anyhow::Result;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::{fmt::writer::BoxMakeWriter, EnvFilter};
+
+/// Whether `level` parses as a `log_level` (`RUST_LOG` syntax).
+pub fn valid_log_level(level: &str) -> bool {
+    EnvFilter::try_new(level).is_ok()
+}
+
+/// `amibussy.<date>.log` files in `log_file.dir`, of which the oldest are deleted.
+fn file_appender(log_file: &LogFileSettings) -> Result<RollingFileAppender> {
+    let dir = shellexpand::tilde(&log_file.dir).to_string();
+    let rotation = match log_file.rotation {
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    };
+    RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix("amibussy")
+        .filename_suffix("log")
+        .max_log_files(log_file.max_files)
+        .build(&dir)
+        .map_err(|err| anyhow::anyhow!("can't log to {}: {}", dir, err))
+}
+
+/// Installs the global subscriber, filtered by `RUST_LOG` if it's set and `log_level`
+/// otherwise. With `log_format: json` every event is one JSON object per line; with
+/// `log_file` it goes to rotating files instead of stdout.
+pub fn init_logging(settings: &Settings) -> Result<()> {
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(&settings.log_level));
+    let writer = match &settings.log_file {
+        Some(log_file) => BoxMakeWriter::new(file_appender(log_file)?),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+    let builder = tracing_subscriber::fmt()
+        .with_timer(LogTimer)
+        .with_env_filter(filter)
+        .with_ansi(settings.log_file.is_none())
+        .with_writer(writer);
+    match settings.log_format {
+        LogFormat::Text => builder.init(),
+        LogFormat::Json => builder.json().init(),
+    }
+    Ok(())
+}
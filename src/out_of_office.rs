@@ -0,0 +1,156 @@
+use crate::{
+    config::Settings,
+    i18n::Text,
+    sources::{
+        toggl::reconcile_on_boot,
+        toggl_subscription::{ensure_subscription, pause_subscription},
+    },
+    state::{day_start_timestamp, publish_change, publish_title, AppState, Status},
+};
+use axum::{
+    extract::{Json, State},
+    response::{IntoResponse, Response},
+};
+use hyper::StatusCode;
+use reqwest::Client;
+use std::sync::{atomic::Ordering, PoisonError};
+use tracing::{error, info};
+
+/// Out of office: Not Working with its own title whatever the sources say, and Toggl events
+/// ignored, until `until`.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct OutOfOffice {
+    /// UNIX seconds.
+    pub until: u64,
+    pub title: String,
+    /// Disable the Toggl subscription meanwhile.
+    pub pause_subscription: bool,
+}
+
+/// UNIX timestamp at which `day` is over, with days starting at `day_start_hour`.
+pub fn end_of(day: chrono::NaiveDate, settings: &Settings) -> Option<u64> {
+    day_start_timestamp(day.succ_opt()?, settings.day_start_hour, settings.timezone)
+}
+
+/// The `out_of_office` settings, if it's between their `from` and `until` now.
+pub fn from_settings(settings: &Settings, now: u64) -> Option<OutOfOffice> {
+    let ooo = settings.out_of_office.as_ref()?;
+    let from = match ooo.from_date() {
+        Some(from) => day_start_timestamp(from, settings.day_start_hour, settings.timezone)?,
+        None => 0,
+    };
+    let until = end_of(ooo.until_date()?, settings)?;
+    (from..until).contains(&now).then(|| OutOfOffice {
+        until,
+        title: ooo
+            .title
+            .clone()
+            .unwrap_or_else(|| settings.text(Text::OutOfOfficeTitle).to_string()),
+        pause_subscription: ooo.pause_subscription,
+    })
+}
+
+/// Acts on going out of or coming back to the office: pauses or restores the Toggl
+/// subscription, publishes the status and, on return, catches up with Toggl. Returns whether
+/// anything changed since the last call.
+pub async fn sync_out_of_office(client: &Client, state: &AppState) -> bool {
+    let current = state.out_of_office();
+    let active = current.is_some();
+    if state.out_of_office_active.swap(active, Ordering::Relaxed) == active {
+        return false;
+    }
+    let settings = state.settings();
+    let subscription = settings
+        .toggl_subscription
+        .as_ref()
+        .zip(settings.toggl_api_token.as_ref())
+        .filter(|_| !settings.dry_run);
+
+    match current {
+        Some(ooo) => {
+            info!("[OUT OF OFFICE] Until {}: {:?}", ooo.until, ooo.title);
+            if let Some((subscription, api_token)) = subscription.filter(|_| ooo.pause_subscription)
+            {
+                match pause_subscription(client, state, api_token, subscription).await {
+                    Ok(()) => info!("[OUT OF OFFICE] Toggl subscription paused"),
+                    Err(err) => error!("Failed to pause the Toggl subscription: {}", err),
+                }
+            }
+            let previous = state.decide_in_office().status;
+            publish_change(client, state, previous, "out_of_office").await;
+        }
+        None => {
+            info!("[OUT OF OFFICE] Back in the office");
+            *state
+                .runtime_out_of_office
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = None;
+            if let Some((subscription, api_token)) = subscription {
+                if let Err(err) = ensure_subscription(client, state, api_token, subscription).await
+                {
+                    error!("Failed to restore the Toggl subscription: {}", err);
+                }
+            }
+            publish_change(client, state, Status::NotWorking, "out_of_office_ended").await;
+            reconcile_on_boot(state.clone()).await;
+        }
+    }
+    true
+}
+
+/// Body of `POST /ooo`.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct OutOfOfficeRequest {
+    /// The last day off, as `YYYY-MM-DD`.
+    pub until: String,
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub pause_subscription: bool,
+}
+
+/// `POST /ooo` on the control API: out of office through `until`.
+pub async fn start_out_of_office(
+    State(state): State<AppState>,
+    Json(request): Json<OutOfOfficeRequest>,
+) -> Response {
+    let settings = state.settings();
+    let Ok(until) = chrono::NaiveDate::parse_from_str(&request.until, "%Y-%m-%d") else {
+        return (
+            StatusCode::BAD_REQUEST,
+            "until has to be a date like 2024-12-31",
+        )
+            .into_response();
+    };
+    let Some(until) = end_of(until, &settings).filter(|&until| until > state.clock.now()) else {
+        return (StatusCode::BAD_REQUEST, "until has to be today or later").into_response();
+    };
+    let title = request
+        .title
+        .or_else(|| settings.out_of_office.as_ref()?.title.clone())
+        .unwrap_or_else(|| settings.text(Text::OutOfOfficeTitle).to_string());
+    *state
+        .runtime_out_of_office
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(OutOfOffice {
+        until,
+        title,
+        pause_subscription: request.pause_subscription,
+    });
+    let client = Client::new();
+    if !sync_out_of_office(&client, &state).await {
+        // Already out of office; only the title may have changed.
+        publish_title(&client, &state).await;
+    }
+    Json(state.out_of_office()).into_response()
+}
+
+/// `DELETE /ooo` on the control API: back in the office, unless the settings say otherwise.
+pub async fn end_out_of_office(State(state): State<AppState>) -> Response {
+    *state
+        .runtime_out_of_office
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = None;
+    sync_out_of_office(&Client::new(), &state).await;
+    Json(state.out_of_office()).into_response()
+}
@@ -0,0 +1,66 @@
+use crate::{
+    history::Transition,
+    presence::publish_transition,
+    state::{publish_title, AppState},
+};
+use axum::extract::{Json, State};
+use reqwest::Client;
+use std::sync::PoisonError;
+use tracing::info;
+
+/// Freezes the sinks where they are, e.g. while sharing the screen. Events are still applied
+/// and transitions recorded; only publishing them waits for `resume_sinks`. Returns whether
+/// they weren't paused already.
+pub fn pause_sinks(state: &AppState) -> bool {
+    let showing = state.announced_status();
+    let mut paused = state
+        .sinks_frozen_at
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if paused.is_some() {
+        return false;
+    }
+    info!("[PAUSED] Sinks frozen at {:?}", showing);
+    *paused = Some(showing);
+    true
+}
+
+/// Unfreezes the sinks and brings them up to date: the title sinks get the current title, and
+/// the transition sinks one transition from the status they were left showing, if it changed
+/// meanwhile. Returns whether they were paused.
+pub async fn resume_sinks(client: &Client, state: &AppState) -> bool {
+    let showing = state
+        .sinks_frozen_at
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .take();
+    let Some(showing) = showing else {
+        return false;
+    };
+    let status = state.announced_status();
+    info!("[RESUMED] Sinks were at {:?}, now {:?}", showing, status);
+    publish_title(client, state).await;
+    if status != showing && !state.settings().dry_run {
+        let transition = Transition {
+            at: state.clock.now(),
+            from: showing,
+            to: status,
+            cause: "resume".to_string(),
+            payload_id: None,
+        };
+        publish_transition(client, state, &transition).await;
+    }
+    true
+}
+
+/// `POST /pause` on the control API. Replies whether the sinks are paused now.
+pub async fn pause(State(state): State<AppState>) -> Json<bool> {
+    pause_sinks(&state);
+    Json(state.sinks_paused())
+}
+
+/// `POST /resume` on the control API. Replies whether the sinks are paused now.
+pub async fn resume(State(state): State<AppState>) -> Json<bool> {
+    resume_sinks(&Client::new(), &state).await;
+    Json(state.sinks_paused())
+}
@@ -0,0 +1,668 @@
+use crate::{
+    config::ActivitySignal,
+    history::Transition,
+    sinks::{
+        github::update_github_status, hue::update_hue, macos_focus::update_macos_focus,
+        mattermost::update_mattermost, notifications::update_notification_pause, push::send_push,
+        shell::run_shell_hooks, slack::update_slack_dnd, status_file::write_status_file,
+        teams::update_teams, webhook::call_webhooks,
+    },
+    sources::toggl::parse_timestamp,
+    state::{publish_if_changed, AppState, CurrentEntry, IntervalKind, Status},
+};
+use reqwest::Client;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    PoisonError,
+};
+use tokio::sync::{broadcast, oneshot};
+use tracing::{error, info, warn, Instrument};
+
+/// What a source saw, normalized. Sources only emit these; `presence_loop` is the one place
+/// that applies them to the state.
+#[derive(Debug, Clone)]
+pub enum PresenceEvent {
+    /// A Toggl webhook about a time entry.
+    TogglEntry(TogglEntryEvent),
+    /// Another time tracker's timer started or stopped; handled like Toggl's.
+    TrackerTimer(TrackerTimerEvent),
+    /// A meeting source's busy flag, as of its latest poll or webhook.
+    Meeting { source: MeetingSource, busy: bool },
+    /// Whether WakaTime heartbeats are still coming in.
+    Coding { busy: bool },
+    /// What the active window's ActivityWatch category says.
+    Activity { signal: ActivitySignal },
+    /// A status pinned (or unpinned with `None`) by hand, until the UNIX timestamp.
+    Override {
+        status: Option<Status>,
+        until: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct TogglEntryEvent {
+    /// `created`, `updated` or `deleted`.
+    pub action: String,
+    pub id: Option<i64>,
+    pub start: Option<String>,
+    pub stop: Option<String>,
+    /// Whether the entry stopped, even if there's no `stop` to tell when.
+    pub stopped: bool,
+    pub entry: CurrentEntry,
+}
+
+#[derive(Debug, Clone)]
+pub struct TrackerTimerEvent {
+    pub tracker: Tracker,
+    /// When the timer started, or `None` if it stopped.
+    pub started_at: Option<u64>,
+    pub entry: CurrentEntry,
+}
+
+/// Time trackers other than Toggl.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Tracker {
+    Tempo,
+    Timewarrior,
+    Watson,
+}
+
+impl Tracker {
+    pub fn label(self) -> &'static str {
+        match self {
+            Tracker::Tempo => "Tempo",
+            Tracker::Timewarrior => "Timewarrior",
+            Tracker::Watson => "Watson",
+        }
+    }
+
+    fn cause(self, running: bool) -> &'static str {
+        match (self, running) {
+            (Tracker::Tempo, true) => "tempo_start",
+            (Tracker::Tempo, false) => "tempo_stop",
+            (Tracker::Timewarrior, true) => "timewarrior_start",
+            (Tracker::Timewarrior, false) => "timewarrior_stop",
+            (Tracker::Watson, true) => "watson_start",
+            (Tracker::Watson, false) => "watson_stop",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MeetingSource {
+    GoogleCalendar,
+    Caldav,
+    Devices,
+    Zoom,
+    SlackHuddle,
+}
+
+impl MeetingSource {
+    fn flag(self, state: &AppState) -> &AtomicBool {
+        match self {
+            MeetingSource::GoogleCalendar => &state.google_calendar_busy,
+            MeetingSource::Caldav => &state.caldav_busy,
+            MeetingSource::Devices => &state.devices_busy,
+            MeetingSource::Zoom => &state.zoom_busy,
+            MeetingSource::SlackHuddle => &state.slack_huddle_busy,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            MeetingSource::GoogleCalendar => "Google Calendar event",
+            MeetingSource::Caldav => "CalDAV event",
+            MeetingSource::Devices => "Camera/microphone use",
+            MeetingSource::Zoom => "Zoom meeting",
+            MeetingSource::SlackHuddle => "Slack huddle",
+        }
+    }
+
+    fn cause(self) -> &'static str {
+        match self {
+            MeetingSource::GoogleCalendar => "google_calendar",
+            MeetingSource::Caldav => "caldav_calendar",
+            MeetingSource::Devices => "camera_mic",
+            MeetingSource::Zoom => "zoom",
+            MeetingSource::SlackHuddle => "slack_huddle",
+        }
+    }
+}
+
+/// An emitted event on its way to `presence_loop`.
+pub(crate) struct Envelope {
+    event: PresenceEvent,
+    payload_id: Option<String>,
+    /// The emitter's span, so the logs of a webhook keep its request ID.
+    span: tracing::Span,
+    applied: Option<oneshot::Sender<()>>,
+}
+
+/// Queues `event` for `presence_loop` and returns right away, so a slow sink doesn't hold
+/// up e.g. the webhook response (and make Toggl retry).
+pub fn emit(state: &AppState, event: PresenceEvent, payload_id: Option<&str>) {
+    send(state, event, payload_id, None);
+}
+
+/// Like `emit`, but waits until the state reflects the event (not for the sinks), e.g. to
+/// reply with the new status.
+pub async fn emit_and_wait(state: &AppState, event: PresenceEvent) {
+    let (applied, applied_receiver) = oneshot::channel();
+    send(state, event, None, Some(applied));
+    let _ = applied_receiver.await;
+}
+
+fn send(
+    state: &AppState,
+    event: PresenceEvent,
+    payload_id: Option<&str>,
+    applied: Option<oneshot::Sender<()>>,
+) {
+    let envelope = Envelope {
+        event,
+        payload_id: payload_id.map(str::to_string),
+        span: tracing::Span::current(),
+        applied,
+    };
+    if let Err(err) = state.presence_events.send(envelope) {
+        error!("presence_loop is gone, dropping {:?}", err.0.event);
+    }
+}
+
+/// Applies the emitted events to the state one after the other, in the order they were
+/// emitted, and publishes the resulting status changes. Runs for the daemon's lifetime.
+pub async fn presence_loop(state: AppState) {
+    let Ok(mut events) = state.presence_events_receiver.try_lock() else {
+        error!("presence_loop is already running");
+        return;
+    };
+    let client = Client::new();
+    while let Some(envelope) = events.recv().await {
+        let previous = state.decide().status;
+        let cause = envelope.span.in_scope(|| apply(&state, envelope.event));
+        if let Some(applied) = envelope.applied {
+            let _ = applied.send(());
+        }
+        if let Some(cause) = cause {
+            publish_if_changed(
+                &client,
+                &state,
+                previous,
+                cause,
+                envelope.payload_id.as_deref(),
+            )
+            .instrument(envelope.span)
+            .await;
+        }
+    }
+}
+
+/// Updates the state for `event`. Returns the cause to publish the change under, or `None`
+/// if nothing changed.
+fn apply(state: &AppState, event: PresenceEvent) -> Option<&'static str> {
+    match event {
+        PresenceEvent::TogglEntry(_) | PresenceEvent::TrackerTimer(_)
+            if state.out_of_office().is_some() =>
+        {
+            info!("[OUT OF OFFICE] Ignoring the timer event");
+            None
+        }
+        PresenceEvent::TogglEntry(event) => apply_toggl_entry(state, event),
+        PresenceEvent::TrackerTimer(event) => apply_tracker_timer(state, event),
+        PresenceEvent::Meeting { source, busy } => {
+            if source.flag(state).swap(busy, Ordering::Relaxed) == busy {
+                return None;
+            }
+            info!(
+                "[MEETING] {} ongoing: {}. Effective status: {}",
+                source.label(),
+                busy,
+                state.effective_title()
+            );
+            Some(source.cause())
+        }
+        PresenceEvent::Coding { busy } => {
+            if state.coding.swap(busy, Ordering::Relaxed) == busy {
+                return None;
+            }
+            info!(
+                "[CODING] WakaTime heartbeats coming in: {}. Effective status: {}",
+                busy,
+                state.effective_title()
+            );
+            Some("wakatime")
+        }
+        PresenceEvent::Activity { signal } => {
+            let previous = std::mem::replace(
+                &mut *state
+                    .activity
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner),
+                signal,
+            );
+            if previous == signal {
+                return None;
+            }
+            info!(
+                "[ACTIVITY] Active window says {}. Effective status: {}",
+                signal.as_str(),
+                state.effective_title()
+            );
+            Some("activitywatch")
+        }
+        PresenceEvent::Override { status, until } => {
+            state.set_override(status, until);
+            Some(if status.is_some() {
+                "override"
+            } else {
+                "override_cleared"
+            })
+        }
+    }
+}
+
+fn apply_toggl_entry(state: &AppState, event: TogglEntryEvent) -> Option<&'static str> {
+    let TogglEntryEvent {
+        action,
+        id,
+        start,
+        stop,
+        stopped,
+        entry,
+    } = event;
+    let running = state.timer_started_at.load(Ordering::Relaxed) != 0;
+    // Without an ID to compare (e.g. seeded before IDs were kept), assume it's the one.
+    let is_running_entry = running
+        && state
+            .current_entry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .and_then(|current| current.id)
+            .zip(id)
+            .is_none_or(|(current, id)| current == id);
+
+    if action == "deleted" {
+        if !is_running_entry {
+            info!("Ignoring the deletion of an entry that isn't running");
+            return None;
+        }
+        info!("[SETTING BREAK]. Reason: The running entry was deleted");
+        stop_timer(state, state.clock.now());
+        return Some("toggl_deleted");
+    }
+
+    match start {
+        Some(start_time) if stopped => {
+            if !is_running_entry {
+                // A past entry was added or edited; the timer state stays as it is.
+                info!(
+                    "Ignoring {} entry {:?} that isn't running ({} - {:?})",
+                    action, id, start_time, stop
+                );
+                return None;
+            }
+            info!(
+                "[SETTING BREAK]. Reason: Stop event received with payload. start_time: {}, stop_time: {:?}",
+                start_time, stop
+            );
+            let stopped_at = match &stop {
+                Some(stop_time) => toggl_stopped_at(state, stop_time),
+                None => {
+                    warn!("No stop time for the stopped entry, the break starts now");
+                    state.clock.now()
+                }
+            };
+            stop_timer(state, stopped_at);
+            Some("toggl_stop")
+        }
+        Some(start_time) => {
+            let started_at = parse_timestamp(&start_time).unwrap_or_else(|| state.clock.now());
+            if is_running_entry && action == "updated" {
+                info!(
+                    "[UPDATING BUSY]. Reason: The running entry was edited, start: {}",
+                    start_time
+                );
+                state.timer_started_at.store(started_at, Ordering::Relaxed);
+                *state
+                    .current_entry
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(entry);
+                state.long_entry_flagged.store(false, Ordering::Relaxed);
+                return Some("toggl_update");
+            }
+            info!(
+                "[SETTING BUSY]. Reason: Start event received with payload: {}",
+                start_time
+            );
+            start_timer(state, started_at, entry);
+            Some("toggl_start")
+        }
+        None => None,
+    }
+}
+
+fn apply_tracker_timer(state: &AppState, event: TrackerTimerEvent) -> Option<&'static str> {
+    let TrackerTimerEvent {
+        tracker,
+        started_at,
+        entry,
+    } = event;
+    let running_since = state.timer_started_at.load(Ordering::Relaxed);
+    match started_at {
+        Some(started_at) => {
+            if running_since == started_at {
+                return None;
+            }
+            info!(
+                "[SETTING BUSY]. Reason: {} timer started: {:?}",
+                tracker.label(),
+                entry.description
+            );
+            if running_since != 0 {
+                // Switching timers; count the one that was running.
+                stop_timer(state, state.clock.now());
+            }
+            start_timer(state, started_at, entry);
+        }
+        None => {
+            if running_since == 0 {
+                return None;
+            }
+            info!("[SETTING BREAK]. Reason: {} timer stopped", tracker.label());
+            stop_timer(state, state.clock.now());
+        }
+    }
+    Some(tracker.cause(started_at.is_some()))
+}
+
+/// How far Toggl's `stop` may be from our clock before it's not trusted.
+const CLOCK_SKEW_TOLERANCE_SECS: u64 = 120;
+
+/// When the Toggl entry stopped, by its `stop`, rather than when the webhook arrived. A `stop`
+/// further than `CLOCK_SKEW_TOLERANCE_SECS` ahead of now means one of the clocks is off, so the
+/// break starts now; otherwise it's kept between the timer's start and now.
+fn toggl_stopped_at(state: &AppState, stop_time: &str) -> u64 {
+    let now = state.clock.now();
+    let Some(stopped_at) = parse_timestamp(stop_time) else {
+        warn!("Unreadable stop time {:?}, the break starts now", stop_time);
+        return now;
+    };
+    if stopped_at > now + CLOCK_SKEW_TOLERANCE_SECS {
+        warn!(
+            "The entry stopped at {}, {}s from now, is the clock off? The break starts now",
+            stopped_at,
+            stopped_at - now
+        );
+        return now;
+    }
+    stopped_at
+        .min(now)
+        .max(state.timer_started_at.load(Ordering::Relaxed))
+}
+
+/// Closes the busy interval and starts a break at `stopped_at`.
+fn stop_timer(state: &AppState, stopped_at: u64) {
+    let started_at = state.timer_started_at.load(Ordering::Relaxed);
+    if started_at != 0 {
+        state
+            .daily_totals
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(
+                IntervalKind::Busy,
+                started_at,
+                stopped_at,
+                state.settings().day_start_hour,
+                state.settings().timezone,
+            );
+    }
+    state.timer_started_at.store(0, Ordering::Relaxed);
+    state.long_entry_flagged.store(false, Ordering::Relaxed);
+    *state
+        .current_entry
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = None;
+    state.last_break_start.store(stopped_at, Ordering::Relaxed);
+    state.timer_stopped_at.store(stopped_at, Ordering::Relaxed);
+}
+
+/// Closes the break or AFK interval and marks the timer running since `started_at`.
+fn start_timer(state: &AppState, started_at: u64, entry: CurrentEntry) {
+    let last_break = state.last_break_start.load(Ordering::Relaxed);
+    if last_break != 0 {
+        state
+            .daily_totals
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(
+                IntervalKind::Break,
+                last_break,
+                state.clock.now(),
+                state.settings().day_start_hour,
+                state.settings().timezone,
+            );
+    }
+    let afk_since = state.afk_since.swap(0, Ordering::Relaxed);
+    if afk_since != 0 {
+        state
+            .daily_totals
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .record(
+                IntervalKind::Afk,
+                afk_since,
+                state.clock.now(),
+                state.settings().day_start_hour,
+                state.settings().timezone,
+            );
+    }
+    state.timer_started_at.store(started_at, Ordering::Relaxed);
+    *state
+        .current_entry
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(entry);
+    state.long_entry_flagged.store(false, Ordering::Relaxed);
+    state.last_break_start.store(0, Ordering::Relaxed);
+    state.timer_stopped_at.store(0, Ordering::Relaxed);
+    state.done_for_today.store(false, Ordering::Relaxed);
+}
+
+/// Passes every transition on to the sinks that react to transitions rather than show the
+/// title (webhooks, shell hooks, Focus, Hue, ...). Runs for the daemon's lifetime.
+pub async fn transition_sinks(state: AppState) {
+    let client = Client::new();
+    let mut transitions = state.transitions.subscribe();
+    loop {
+        let transition = match transitions.recv().await {
+            Ok(transition) => transition,
+            Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                warn!("The sinks fell behind, skipped {} transitions", skipped);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
+        };
+        let settings = state.settings();
+        if settings.dry_run {
+            info!(
+                "[DRY RUN] Not publishing {:?} -> {:?} to the sinks",
+                transition.from, transition.to
+            );
+            continue;
+        }
+        if state.sinks_paused() {
+            info!(
+                "[PAUSED] Not publishing {:?} -> {:?} to the sinks",
+                transition.from, transition.to
+            );
+            continue;
+        }
+        publish_transition(&client, &state, &transition).await;
+    }
+}
+
+/// Tells the webhooks, shell hooks, Slack and the other transition sinks about `transition`.
+pub async fn publish_transition(client: &Client, state: &AppState, transition: &Transition) {
+    let settings = state.settings();
+    call_webhooks(client, state, transition).await;
+    run_shell_hooks(state, transition).await;
+    if let Some(focus) = &settings.macos_focus {
+        update_macos_focus(state, focus, transition).await;
+    }
+    if let Some(pause) = &settings.notification_pause {
+        update_notification_pause(state, pause, transition).await;
+    }
+    if let Some(hue) = &settings.hue {
+        update_hue(client, state, hue, transition).await;
+    }
+    if let Some(mattermost) = &settings.mattermost {
+        update_mattermost(client, state, mattermost, transition).await;
+    }
+    if let Some(teams) = &settings.teams {
+        update_teams(client, state, teams, transition).await;
+    }
+    if let Some(dnd) = &settings.slack_dnd {
+        update_slack_dnd(client, state, dnd, transition).await;
+    }
+    if let Some(github) = &settings.github_status {
+        update_github_status(client, state, github, transition).await;
+    }
+    if let Some(push) = &settings.push {
+        send_push(client, state, push, transition).await;
+    }
+    if let Some(file) = &settings.status_file {
+        write_status_file(state, file, transition.at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::ManualClock, config::Settings};
+    use std::sync::Arc;
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn rfc3339(timestamp: u64) -> String {
+        chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .unwrap()
+            .to_rfc3339()
+    }
+
+    fn running_since(started_at: u64) -> AppState {
+        let state = AppState::with_clock(Settings::for_tests(""), Arc::new(ManualClock::new(NOW)));
+        state.timer_started_at.store(started_at, Ordering::Relaxed);
+        state
+    }
+
+    #[test]
+    fn stop_time_within_the_tolerance_is_kept() {
+        let state = running_since(NOW - 3600);
+        assert_eq!(toggl_stopped_at(&state, &rfc3339(NOW - 60)), NOW - 60);
+        // A little ahead of our clock counts as now.
+        assert_eq!(toggl_stopped_at(&state, &rfc3339(NOW + 60)), NOW);
+    }
+
+    #[test]
+    fn stop_time_in_the_past_is_kept() {
+        let state = running_since(NOW - 3600);
+        assert_eq!(toggl_stopped_at(&state, &rfc3339(NOW - 600)), NOW - 600);
+    }
+
+    #[test]
+    fn stop_time_beyond_the_tolerance_is_now() {
+        let state = running_since(NOW - 3600);
+        assert_eq!(toggl_stopped_at(&state, &rfc3339(NOW + 600)), NOW);
+        assert_eq!(toggl_stopped_at(&state, "yesterday"), NOW);
+    }
+
+    #[test]
+    fn stop_time_is_not_before_the_start() {
+        let state = running_since(NOW - 30);
+        assert_eq!(toggl_stopped_at(&state, &rfc3339(NOW - 90)), NOW - 30);
+    }
+
+    #[test]
+    fn stopped_entry_without_a_stop_time_starts_a_break_now() {
+        let state = running_since(NOW - 3600);
+        let event = TogglEntryEvent {
+            action: "updated".to_string(),
+            id: None,
+            start: Some(rfc3339(NOW - 3600)),
+            stop: None,
+            stopped: true,
+            entry: CurrentEntry::default(),
+        };
+        assert_eq!(apply_toggl_entry(&state, event), Some("toggl_stop"));
+        assert_eq!(state.timer_started_at.load(Ordering::Relaxed), 0);
+        assert_eq!(state.last_break_start.load(Ordering::Relaxed), NOW);
+    }
+
+    /// A Toggl event about entry `id`, stopped if it has a `stop`.
+    fn toggl_event(action: &str, id: i64, start: u64, stop: Option<u64>) -> TogglEntryEvent {
+        TogglEntryEvent {
+            action: action.to_string(),
+            id: Some(id),
+            start: Some(rfc3339(start)),
+            stop: stop.map(rfc3339),
+            stopped: stop.is_some(),
+            entry: CurrentEntry {
+                id: Some(id),
+                ..CurrentEntry::default()
+            },
+        }
+    }
+
+    /// Entry 1 running since `started_at`.
+    fn running_entry_since(started_at: u64) -> AppState {
+        let state = running_since(started_at);
+        *state
+            .current_entry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(CurrentEntry {
+            id: Some(1),
+            ..CurrentEntry::default()
+        });
+        state
+    }
+
+    #[test]
+    fn deleting_the_running_entry_starts_a_break() {
+        let state = running_entry_since(NOW - 3600);
+        let event = toggl_event("deleted", 2, NOW - 7200, Some(NOW - 3600));
+        assert_eq!(apply_toggl_entry(&state, event), None);
+        assert_eq!(state.timer_started_at.load(Ordering::Relaxed), NOW - 3600);
+
+        let event = toggl_event("deleted", 1, NOW - 3600, None);
+        assert_eq!(apply_toggl_entry(&state, event), Some("toggl_deleted"));
+        assert_eq!(state.timer_started_at.load(Ordering::Relaxed), 0);
+        assert_eq!(state.last_break_start.load(Ordering::Relaxed), NOW);
+    }
+
+    #[test]
+    fn editing_the_running_entrys_start_keeps_it_running() {
+        let state = running_entry_since(NOW - 3600);
+        let event = toggl_event("updated", 1, NOW - 5400, None);
+        assert_eq!(apply_toggl_entry(&state, event), Some("toggl_update"));
+        assert_eq!(state.timer_started_at.load(Ordering::Relaxed), NOW - 5400);
+        assert_eq!(state.last_break_start.load(Ordering::Relaxed), 0);
+    }
+
+    #[test]
+    fn editing_the_running_entrys_stop_starts_the_break_then() {
+        let state = running_entry_since(NOW - 3600);
+        let event = toggl_event("updated", 1, NOW - 3600, Some(NOW - 900));
+        assert_eq!(apply_toggl_entry(&state, event), Some("toggl_stop"));
+        assert_eq!(state.timer_started_at.load(Ordering::Relaxed), 0);
+        assert_eq!(state.last_break_start.load(Ordering::Relaxed), NOW - 900);
+    }
+
+    #[test]
+    fn editing_a_past_entry_leaves_the_timer_alone() {
+        let state = running_entry_since(NOW - 3600);
+        let event = toggl_event("updated", 2, NOW - 7200, Some(NOW - 5400));
+        assert_eq!(apply_toggl_entry(&state, event), None);
+        assert_eq!(state.timer_started_at.load(Ordering::Relaxed), NOW - 3600);
+        assert_eq!(state.last_break_start.load(Ordering::Relaxed), 0);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    clock::{Clock, ManualClock},
+    config::Settings,
+    presence::{presence_loop, transition_sinks},
+    sources::toggl::webhook_post,
+    state::{afk_status_updater, get_unix_timestamp, AppState},
+    supervisor::Supervisor,
+};
+use anyhow::Result;
+use axum::{body::Bytes, extract::State};
+use serde_json::{json, Value};
+use std::{
+    fs::OpenOptions,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::Arc,
+    time::Duration,
+};
+use tracing::{info, warn};
+
+/// Appends a webhook `body` to `path` as one line of `{"at": <UNIX seconds>, "body": ...}`,
+/// for `amibussy replay`.
+pub fn record_event(path: &str, body: &Value) {
+    let path = shellexpand::tilde(path).to_string();
+    let line = json!({
+        "at": get_unix_timestamp().unwrap_or_default(),
+        "body": body,
+    });
+    let result = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(err) = result {
+        warn!("Failed to record the webhook to {}: {}", path, err);
+    }
+}
+
+/// A recorded webhook: when it arrived, if known, and its body.
+struct Recorded {
+    at: Option<u64>,
+    body: Value,
+}
+
+/// Reads the lines `record_event` writes. A line without `body` is taken as a bare webhook
+/// body, replayed right after the one before it.
+fn read_recording(path: &Path) -> Result<Vec<Recorded>> {
+    let file = std::fs::File::open(path)
+        .map_err(|err| anyhow::anyhow!("failed to open {}: {}", path.display(), err))?;
+    let mut recorded = Vec::new();
+    for (number, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let value: Value = serde_json::from_str(&line).map_err(|err| {
+            anyhow::anyhow!("{}:{}: not JSON: {}", path.display(), number + 1, err)
+        })?;
+        recorded.push(match value.get("body") {
+            Some(body) => Recorded {
+                at: value.get("at").and_then(Value::as_u64),
+                body: body.clone(),
+            },
+            None => Recorded {
+                at: None,
+                body: value,
+            },
+        });
+    }
+    Ok(recorded)
+}
+
+/// Feeds the webhooks recorded in `path` through the whole pipeline in dry run, with a clock
+/// that starts when the first one arrived (or now) and runs `speed` times as fast as the real one, so
+/// AFK and the other timing rules kick in like they did. The history goes to a throwaway
+/// file, not `history_db`.
+pub async fn replay(settings: Settings, path: &Path, speed: u64) -> Result<()> {
+    let recorded = read_recording(path)?;
+    let start = match recorded.iter().find_map(|recorded| recorded.at) {
+        Some(start) => start,
+        None => get_unix_timestamp()?,
+    };
+    let scratch = std::env::temp_dir().join(format!("amibussy-replay-{}", std::process::id()));
+    let settings = Settings {
+        dry_run: true,
+        record_events: None,
+        history_db: scratch.join("history.db").to_string_lossy().into_owned(),
+        ..settings
+    };
+    let clock = Arc::new(ManualClock::new(start));
+    let state = AppState::with_clock(settings, clock.clone());
+    let shutdown_signal = Arc::new(tokio::sync::Notify::new());
+    for scoped_state in std::iter::once(&state)
+        .chain(state.team.values())
+        .chain(state.workspaces.values())
+    {
+        let supervisor = Supervisor::new(scoped_state.clone(), shutdown_signal.clone());
+        supervisor.spawn("presence_loop", |state, _| presence_loop(state));
+        supervisor.spawn("transition_sinks", |state, _| transition_sinks(state));
+        supervisor.spawn("afk_status_updater", afk_status_updater);
+    }
+
+    info!(
+        "[REPLAY] {} webhooks from {} at {}x",
+        recorded.len(),
+        path.display(),
+        speed
+    );
+    for recorded in recorded {
+        let at = recorded.at.unwrap_or_else(|| clock.now());
+        while clock.now() < at {
+            tokio::time::sleep(Duration::from_secs(1)).await;
+            clock.set((clock.now() + speed).min(at));
+        }
+        let body = Bytes::from(recorded.body.to_string());
+        let response = webhook_post(State(state.clone()), body).await;
+        info!("[REPLAY] Webhook at {} answered {}", at, response.status());
+    }
+    // The events are applied in the background; give them a moment before reporting.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+    shutdown_signal.notify_waiters();
+    info!(
+        "[REPLAY] Done, ending {:?} with title {:?}",
+        state.decide().status,
+        state.effective_title()
+    );
+    if let Err(err) = std::fs::remove_dir_all(&scratch) {
+        warn!("Failed to remove {}: {}", scratch.display(), err);
+    }
+    Ok(())
+}
@@ -0,0 +1,279 @@
+use crate::{
+    clock::local_time,
+    config::{Settings, WeeklyReportSettings},
+    history::History,
+    i18n::Text,
+    sinks::telegram::send_message,
+    sources::toggl::fetch_time_entries,
+    state::{day_of, day_start_timestamp, split_at_day_start, AppState, Status},
+    summary::format_duration,
+};
+use anyhow::Result;
+use chrono::{Datelike, NaiveDate};
+use reqwest::Client;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// Busy, break and AFK time on one day.
+#[derive(Debug, Clone)]
+pub struct DayReport {
+    pub day: NaiveDate,
+    pub busy_secs: u64,
+    pub break_secs: u64,
+    pub afk_secs: u64,
+}
+
+/// Today, or the days of the current week (from Monday) up to today.
+pub fn report_days(settings: &Settings, now: u64, week: bool) -> Vec<NaiveDate> {
+    let today = day_of(now, settings.day_start_hour, settings.timezone);
+    if !week {
+        return vec![today];
+    }
+    let monday = today - chrono::Duration::days(i64::from(today.weekday().num_days_from_monday()));
+    monday.iter_days().take_while(|day| *day <= today).collect()
+}
+
+/// `[start, end)` of `day`, cut off at `now`.
+fn day_bounds(settings: &Settings, day: NaiveDate, now: u64) -> Result<(u64, u64)> {
+    let (day_start_hour, timezone) = (settings.day_start_hour, settings.timezone);
+    let start = day_start_timestamp(day, day_start_hour, timezone)
+        .ok_or_else(|| anyhow::anyhow!("invalid day start for {}", day))?;
+    let end = day
+        .succ_opt()
+        .and_then(|next| day_start_timestamp(next, day_start_hour, timezone))
+        .ok_or_else(|| anyhow::anyhow!("invalid day end for {}", day))?;
+    Ok((start, end.min(now).max(start)))
+}
+
+/// Totals per day from the status history. Not Working only counts as AFK between the first
+/// and the last busy or break stretch of a day; before and after that it's just not a workday.
+pub fn day_reports(
+    history: &History,
+    settings: &Settings,
+    days: &[NaiveDate],
+    now: u64,
+) -> Result<Vec<DayReport>> {
+    let mut reports = Vec::new();
+    for &day in days {
+        let (start, end) = day_bounds(settings, day, now)?;
+        let mut report = DayReport {
+            day,
+            busy_secs: 0,
+            break_secs: 0,
+            afk_secs: 0,
+        };
+
+        let mut status = history
+            .last_before(start)?
+            .map(|transition| transition.to)
+            .unwrap_or(Status::NotWorking);
+        let mut stretches = Vec::new();
+        let mut stretch_start = start;
+        for transition in history.between(start, end)? {
+            stretches.push((status, transition.at - stretch_start));
+            status = transition.to;
+            stretch_start = transition.at;
+        }
+        stretches.push((status, end - stretch_start));
+
+        let mut worked = false;
+        let mut not_working_secs = 0;
+        for (status, secs) in stretches {
+            match status {
+                Status::NotWorking => not_working_secs += secs,
+                Status::Busy | Status::ForgotTimer | Status::Break => {
+                    if worked {
+                        report.afk_secs += not_working_secs;
+                    }
+                    not_working_secs = 0;
+                    worked = true;
+                    if status == Status::Break {
+                        report.break_secs += secs;
+                    } else {
+                        report.busy_secs += secs;
+                    }
+                }
+            }
+        }
+        reports.push(report);
+    }
+    Ok(reports)
+}
+
+/// Replaces the busy figures with the time entries Toggl has for those days, which also
+/// covers entries whose webhooks we missed.
+async fn busy_from_toggl(
+    client: &Client,
+    settings: &Settings,
+    api_token: &str,
+    reports: &mut [DayReport],
+    now: u64,
+) -> Result<()> {
+    let (Some(first), Some(last)) = (reports.first(), reports.last()) else {
+        return Ok(());
+    };
+    let start = day_bounds(settings, first.day, now)?.0;
+    let end = day_bounds(settings, last.day, now)?.1;
+    let to_rfc3339 = |timestamp: u64| {
+        chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .unwrap_or_default()
+            .to_rfc3339()
+    };
+
+    let entries =
+        fetch_time_entries(client, api_token, &to_rfc3339(start), &to_rfc3339(end + 1)).await?;
+    for report in reports.iter_mut() {
+        report.busy_secs = 0;
+    }
+    for entry in entries {
+        let Some(started_at) = chrono::DateTime::parse_from_rfc3339(&entry.start)
+            .ok()
+            .and_then(|time| u64::try_from(time.timestamp()).ok())
+        else {
+            continue;
+        };
+        // Running entries report -start_timestamp as their duration.
+        let stopped_at = if entry.duration >= 0 {
+            started_at + entry.duration as u64
+        } else {
+            now
+        };
+        let pieces = split_at_day_start(
+            started_at,
+            stopped_at,
+            settings.day_start_hour,
+            settings.timezone,
+        );
+        for (day, from, to) in pieces {
+            if let Some(report) = reports.iter_mut().find(|report| report.day == day) {
+                report.busy_secs += to - from;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The report for `days` as a message: one line per day and the totals.
+pub async fn build_report(
+    client: &Client,
+    settings: &Settings,
+    history: &History,
+    days: &[NaiveDate],
+    now: u64,
+) -> Result<String> {
+    let mut reports = day_reports(history, settings, days, now)?;
+    if let Some(api_token) = &settings.toggl_api_token {
+        if let Err(err) = busy_from_toggl(client, settings, api_token, &mut reports, now).await {
+            error!(
+                "Failed to fetch the entries from Toggl, using the status history: {}",
+                err
+            );
+        }
+    }
+
+    let mut text = match days {
+        [day] => settings
+            .text(Text::ReportDay)
+            .replace("{day}", &day.to_string()),
+        _ => settings.text(Text::ReportWeek).replace(
+            "{day}",
+            &days.first().map(|day| day.to_string()).unwrap_or_default(),
+        ),
+    };
+    text.push('\n');
+    let weekdays: Vec<&str> = settings.text(Text::Weekdays).split_whitespace().collect();
+    let line = |template: Text, busy_secs: u64, break_secs: u64, afk_secs: u64| {
+        settings
+            .text(template)
+            .replace("{busy}", &format_duration(busy_secs))
+            .replace("{breaks}", &format_duration(break_secs))
+            .replace("{afk}", &format_duration(afk_secs))
+            + "\n"
+    };
+    for report in &reports {
+        let weekday = report.day.weekday().num_days_from_monday() as usize;
+        let day = format!(
+            "{} {}",
+            weekdays.get(weekday).copied().unwrap_or_default(),
+            report.day.format("%d.%m")
+        );
+        text.push_str(
+            &line(
+                Text::ReportLine,
+                report.busy_secs,
+                report.break_secs,
+                report.afk_secs,
+            )
+            .replace("{day}", day.trim()),
+        );
+    }
+    if reports.len() > 1 {
+        let total = |secs: fn(&DayReport) -> u64| reports.iter().map(secs).sum::<u64>();
+        text.push_str(&line(
+            Text::ReportTotal,
+            total(|report| report.busy_secs),
+            total(|report| report.break_secs),
+            total(|report| report.afk_secs),
+        ));
+    }
+    Ok(text.trim_end().to_string())
+}
+
+/// Posts the week's report once a week, as soon as the local time passes `report.at` on
+/// `report.day`.
+pub async fn weekly_report_poster(
+    state: AppState,
+    report: WeeklyReportSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let (day, at) = match (report.weekday(), report.time()) {
+        (Some(day), Some(at)) => (day, at),
+        _ => {
+            error!(
+                "Invalid weekly_report day {:?} or time {:?}",
+                report.day, report.at
+            );
+            return;
+        }
+    };
+    let Some(history) = state.history.clone() else {
+        error!("The weekly report needs the status history, which is not available");
+        return;
+    };
+    let client = Client::new();
+    let chat_id = report
+        .chat_id
+        .clone()
+        .unwrap_or_else(|| state.settings().chat_id.clone());
+    let mut interval = interval(Duration::from_secs(30));
+    let mut last_posted = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down weekly_report_poster");
+                break;
+            }
+        }
+
+        let now = state.clock.now();
+        let settings = state.settings();
+        let local_now = local_time(settings.timezone, now);
+        let today = day_of(now, settings.day_start_hour, settings.timezone);
+        if local_now.weekday() != day || local_now.time() < at || last_posted == Some(today) {
+            continue;
+        }
+
+        let days = report_days(&settings, now, true);
+        match build_report(&client, &settings, &history, &days, now).await {
+            Ok(text) => {
+                info!("[WEEKLY REPORT] {}", text);
+                send_message(&client, &settings, &chat_id, &text).await;
+            }
+            Err(err) => error!("Failed to build the weekly report: {}", err),
+        }
+        last_posted = Some(today);
+    }
+}
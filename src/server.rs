@@ -0,0 +1,786 @@
+#[cfg(unix)]
+use crate::control::run_control_socket;
+use crate::{
+    bot::{bot_updates, needs_updates},
+    capture::capture_payload,
+    config::{Settings, TogglSubscriptionSettings, TunnelKind},
+    control::watch_settings,
+    dashboard::{dashboard, team},
+    diagnostics::{admin_alerter, SubsystemHealth},
+    events::{sse_events, ws_events},
+    limits::{check_webhook_auth, limit_webhook},
+    out_of_office::{end_out_of_office, start_out_of_office},
+    pause::{pause, resume},
+    presence::{presence_loop, transition_sinks},
+    report::weekly_report_poster,
+    sinks::{
+        busylight::busylight_updater,
+        elgato::elgato_updater,
+        mastodon::mastodon_updater,
+        status_file::write_status_file,
+        telegram::{send_message, set_chat_title, verify_bot_rights},
+    },
+    sources::{
+        activitywatch::activitywatch_poller,
+        caldav::caldav_poller,
+        devices::device_usage_poller,
+        google_calendar::google_calendar_poller,
+        local_trackers::{timewarrior_poller, watson_poller},
+        slack::slack_huddle_poller,
+        tempo::tempo_webhook,
+        toggl::{reconcile_on_boot, webhook_get, webhook_post},
+        toggl_subscription::subscription_watchdog,
+        wakatime::{wakatime_poller, wakatime_relay},
+        zoom::zoom_webhook,
+    },
+    state::{
+        afk_status_updater, get_unix_timestamp, title_refresher, AppState, Status, StatusSource,
+    },
+    summary::daily_summary_poster,
+    supervisor::Supervisor,
+    tunnel::{
+        ngrok_healthcheck, start_direct_listener, start_ngrok_listener, start_tailscale_listener,
+        Backoff, DirectListener, NgrokSessions,
+    },
+};
+use anyhow::Result;
+use axum::{
+    extract::{ConnectInfo, DefaultBodyLimit, Json, State},
+    http::{HeaderValue, Request},
+    middleware,
+    routing::{any, get, post},
+    Extension, Router,
+};
+use hyper_util::{
+    rt::{TokioExecutor, TokioIo},
+    server::{conn::auto, graceful::GracefulShutdown},
+    service::TowerToHyperService,
+};
+use reqwest::Client;
+use std::{
+    collections::{BTreeMap, HashSet},
+    future::Future,
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, PoisonError,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::TcpStream,
+    signal,
+    task::JoinHandle,
+};
+use tokio_rustls::server::TlsStream;
+use tokio_stream::{Stream, StreamExt};
+use tower::{limit::ConcurrencyLimitLayer, ServiceBuilder};
+use tower_http::{
+    request_id::{MakeRequestId, PropagateRequestIdLayer, RequestId, SetRequestIdLayer},
+    timeout::TimeoutLayer,
+    trace::TraceLayer,
+};
+use tracing::{debug, error, info, warn};
+
+/// How long a listener has to stay up for the tunnel to count as working again.
+const TUNNEL_STABLE_AFTER: Duration = Duration::from_secs(60);
+
+/// What `GET /status` on the control API returns.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct StatusReport {
+    pub status: Status,
+    pub decided_by: StatusSource,
+    pub title: String,
+    /// When the current status was entered.
+    #[serde(default)]
+    pub since: u64,
+    /// Description of the running Toggl entry.
+    #[serde(default)]
+    pub entry: Option<String>,
+    /// The sinks are frozen by `amibussy pause`.
+    #[serde(default)]
+    pub paused: bool,
+    pub subsystems: BTreeMap<String, SubsystemHealth>,
+}
+
+/// Serves the public routes on connections from `listener` (the ngrok tunnel, a local port
+/// that Tailscale Funnel forwards to, or our own public one) until the tunnel looks dead, the
+/// settings are reloaded or it's `restart_at` (UNIX seconds), e.g. to renew the certificate.
+pub async fn run_server<L, C, E>(
+    app_state: AppState,
+    listener: L,
+    restart_at: Option<u64>,
+) -> Result<()>
+where
+    L: Stream<Item = Result<C, E>> + Unpin,
+    C: AsyncRead + AsyncWrite + PeerAddr + Unpin + Send + 'static,
+    E: std::fmt::Display,
+{
+    let settings = app_state.settings();
+    let shutdown_signal = Arc::new(tokio::sync::Notify::new());
+
+    let webhook = post(webhook_post)
+        .get(webhook_get)
+        .layer(DefaultBodyLimit::max(
+            settings.webhook_limits.max_body_bytes,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            capture_payload,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            check_webhook_auth,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_state.clone(),
+            limit_webhook,
+        ));
+    let router = Router::new()
+        .route("/webhook", webhook.clone())
+        .route("/webhook/:token", webhook)
+        .route(
+            "/dashboard",
+            get(dashboard).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                limit_webhook,
+            )),
+        )
+        .route(
+            "/team",
+            get(team).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                limit_webhook,
+            )),
+        )
+        .route(
+            "/zoom",
+            post(zoom_webhook).layer(middleware::from_fn_with_state(
+                app_state.clone(),
+                capture_payload,
+            )),
+        )
+        .route(
+            "/tempo",
+            post(tempo_webhook)
+                .layer(middleware::from_fn_with_state(
+                    app_state.clone(),
+                    capture_payload,
+                ))
+                .layer(middleware::from_fn_with_state(
+                    app_state.clone(),
+                    limit_webhook,
+                )),
+        )
+        .with_state(app_state.clone())
+        .layer(
+            ServiceBuilder::new()
+                .layer(SetRequestIdLayer::x_request_id(RequestIds::default()))
+                .layer(TraceLayer::new_for_http().make_span_with(request_span))
+                .layer(PropagateRequestIdLayer::x_request_id())
+                .layer(TimeoutLayer::new(Duration::from_secs(
+                    settings.webhook_limits.timeout_secs,
+                )))
+                .layer(ConcurrencyLimitLayer::new(
+                    settings.webhook_limits.max_concurrent,
+                )),
+        );
+
+    let shutdown_signal_clone = shutdown_signal.clone();
+    let settings_reloaded = app_state.settings_reloaded.clone();
+    let restart_in = restart_at.map(|at| at.saturating_sub(app_state.clock.now()));
+    // A reload restarts the server (and the tunnel and tasks below) with the new settings.
+    let shutdown_future = async move {
+        let restart = async {
+            match restart_in {
+                Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            _ = shutdown_signal_clone.notified() => {}
+            _ = settings_reloaded.notified() => info!("Restarting with the reloaded settings"),
+            _ = restart => info!("Restarting to renew the certificate"),
+        }
+    };
+    let server = serve_connections(listener, router, shutdown_future);
+
+    let supervisor = Supervisor::new(app_state.clone(), shutdown_signal.clone());
+    let (team_supervisors, team_handles) = spawn_team_tasks(&app_state, &shutdown_signal);
+
+    let ngrok_healthcheck_handler = supervisor.spawn("ngrok_healthcheck", ngrok_healthcheck);
+    let afk_status_updater_handle = supervisor.spawn("afk_status_updater", afk_status_updater);
+    let subscription_handle = settings.toggl_subscription.clone().map(|subscription| {
+        supervisor.spawn("subscription_watchdog", move |state, signal| {
+            subscription_watchdog(state, subscription.clone(), signal)
+        })
+    });
+    let calendar_poller_handle = settings.google_calendar.clone().map(|calendar| {
+        supervisor.spawn("google_calendar_poller", move |state, signal| {
+            google_calendar_poller(state, calendar.clone(), signal)
+        })
+    });
+    let caldav_poller_handle = settings.caldav.clone().map(|caldav| {
+        supervisor.spawn("caldav_poller", move |state, signal| {
+            caldav_poller(state, caldav.clone(), signal)
+        })
+    });
+
+    // In a dry run nothing gets published, so the sinks that poll the state stay off.
+    let sinks = (!settings.dry_run).then_some(&settings);
+    let title_refresher_handle = sinks
+        .filter(|s| s.has_dynamic_titles())
+        .map(|_| supervisor.spawn("title_refresher", title_refresher));
+    let busylight_handle = sinks.and_then(|s| s.busylight.clone()).map(|busylight| {
+        supervisor.spawn("busylight_updater", move |state, signal| {
+            busylight_updater(state, busylight.clone(), signal)
+        })
+    });
+
+    let elgato_handle = sinks.and_then(|s| s.elgato.clone()).map(|elgato| {
+        supervisor.spawn("elgato_updater", move |state, signal| {
+            elgato_updater(state, elgato.clone(), signal)
+        })
+    });
+
+    let device_usage_handle = settings.device_usage.clone().map(|devices| {
+        supervisor.spawn("device_usage_poller", move |state, signal| {
+            device_usage_poller(state, devices.clone(), signal)
+        })
+    });
+
+    let slack_huddle_handle = settings.slack_huddle.clone().map(|slack| {
+        supervisor.spawn("slack_huddle_poller", move |state, signal| {
+            slack_huddle_poller(state, slack.clone(), signal)
+        })
+    });
+
+    let timewarrior_handle = settings.timewarrior.clone().map(|timew| {
+        supervisor.spawn("timewarrior_poller", move |state, signal| {
+            timewarrior_poller(state, timew.clone(), signal)
+        })
+    });
+
+    let watson_handle = settings.watson.clone().map(|watson| {
+        supervisor.spawn("watson_poller", move |state, signal| {
+            watson_poller(state, watson.clone(), signal)
+        })
+    });
+
+    let wakatime_handle = settings.wakatime.clone().map(|wakatime| {
+        supervisor.spawn("wakatime_poller", move |state, signal| {
+            wakatime_poller(state, wakatime.clone(), signal)
+        })
+    });
+
+    let activitywatch_handle = settings.activitywatch.clone().map(|activitywatch| {
+        supervisor.spawn("activitywatch_poller", move |state, signal| {
+            activitywatch_poller(state, activitywatch.clone(), signal)
+        })
+    });
+
+    let mastodon_handle = sinks.and_then(|s| s.mastodon.clone()).map(|mastodon| {
+        supervisor.spawn("mastodon_updater", move |state, signal| {
+            mastodon_updater(state, mastodon.clone(), signal)
+        })
+    });
+
+    let daily_summary_handle = sinks.and_then(|s| s.daily_summary.clone()).map(|summary| {
+        supervisor.spawn("daily_summary_poster", move |state, signal| {
+            daily_summary_poster(state, summary.clone(), signal)
+        })
+    });
+
+    let admin_alerts_handle = sinks.and_then(|s| s.admin_alerts.clone()).map(|alerts| {
+        supervisor.spawn("admin_alerter", move |state, signal| {
+            admin_alerter(state, alerts.clone(), signal)
+        })
+    });
+    let bot_updates_handle =
+        needs_updates(&settings).then(|| supervisor.spawn("bot_updates", bot_updates));
+    let weekly_report_handle = sinks.and_then(|s| s.weekly_report.clone()).map(|report| {
+        supervisor.spawn("weekly_report_poster", move |state, signal| {
+            weekly_report_poster(state, report.clone(), signal)
+        })
+    });
+
+    server.await;
+
+    supervisor.stop();
+    for supervisor in &team_supervisors {
+        supervisor.stop();
+    }
+
+    let _ = ngrok_healthcheck_handler.await;
+    let _ = afk_status_updater_handle.await;
+    if let Some(handle) = subscription_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = calendar_poller_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = caldav_poller_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = title_refresher_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = busylight_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = elgato_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = device_usage_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = slack_huddle_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = timewarrior_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = watson_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = wakatime_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = activitywatch_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = mastodon_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = daily_summary_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = weekly_report_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = admin_alerts_handle {
+        let _ = handle.await;
+    }
+    if let Some(handle) = bot_updates_handle {
+        let _ = handle.await;
+    }
+    for handle in team_handles {
+        let _ = handle.await;
+    }
+
+    Ok(())
+}
+
+async fn control_status(State(state): State<AppState>) -> Json<StatusReport> {
+    let decision = state.decide();
+    Json(StatusReport {
+        status: decision.status,
+        decided_by: decision.source,
+        title: state.effective_title(),
+        since: state.status_since.load(Ordering::Relaxed),
+        entry: state
+            .current_entry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .and_then(|entry| entry.description.clone()),
+        paused: state.sinks_paused(),
+        subsystems: state.diagnostics.snapshot(),
+    })
+}
+
+/// Request IDs for correlating the logs of one request: the daemon's start time and a
+/// counter. An `X-Request-Id` the caller sent is kept instead.
+#[derive(Clone)]
+struct RequestIds {
+    prefix: u64,
+    next: Arc<AtomicU64>,
+}
+
+impl Default for RequestIds {
+    fn default() -> Self {
+        RequestIds {
+            prefix: get_unix_timestamp().unwrap_or_default(),
+            next: Arc::new(AtomicU64::new(1)),
+        }
+    }
+}
+
+impl MakeRequestId for RequestIds {
+    fn make_request_id<B>(&mut self, _request: &Request<B>) -> Option<RequestId> {
+        let id = format!(
+            "{:x}-{}",
+            self.prefix,
+            self.next.fetch_add(1, Ordering::Relaxed)
+        );
+        HeaderValue::from_str(&id).ok().map(RequestId::new)
+    }
+}
+
+/// The span every log line of a public request is in. The webhook path token is left out.
+fn request_span<B>(request: &Request<B>) -> tracing::Span {
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|id| id.to_str().ok())
+        .unwrap_or_default();
+    let path = request.uri().path();
+    let path = if path.starts_with("/webhook/") {
+        "/webhook/…"
+    } else {
+        path
+    };
+    tracing::info_span!(
+        "request",
+        id = %request_id,
+        method = %request.method(),
+        path = %path,
+    )
+}
+
+/// A connection that can tell where it comes from.
+pub trait PeerAddr {
+    fn peer_addr(&self) -> Option<SocketAddr>;
+}
+
+impl PeerAddr for TcpStream {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        TcpStream::peer_addr(self).ok()
+    }
+}
+
+impl PeerAddr for TlsStream<TcpStream> {
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.get_ref().0.peer_addr().ok()
+    }
+}
+
+impl PeerAddr for ngrok::tunnel::Conn {
+    /// The client that connected to the ngrok edge.
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        Some(self.remote_addr())
+    }
+}
+
+/// Serves `router` on the connections from `listener` until `shutdown` resolves, then waits
+/// for the requests in flight. `axum::serve` only takes a `TcpListener`, and ours is a tunnel.
+/// Each request gets the connection's peer as `ConnectInfo<SocketAddr>`, as with `axum::serve`.
+async fn serve_connections<L, C, E>(
+    mut listener: L,
+    router: Router,
+    shutdown: impl Future<Output = ()>,
+) where
+    L: Stream<Item = Result<C, E>> + Unpin,
+    C: AsyncRead + AsyncWrite + PeerAddr + Unpin + Send + 'static,
+    E: std::fmt::Display,
+{
+    let builder = auto::Builder::new(TokioExecutor::new());
+    let graceful = GracefulShutdown::new();
+    tokio::pin!(shutdown);
+
+    loop {
+        let conn = tokio::select! {
+            conn = listener.next() => match conn {
+                Some(Ok(conn)) => conn,
+                Some(Err(err)) => {
+                    warn!("Failed to accept a connection: {}", err);
+                    continue;
+                }
+                None => break,
+            },
+            _ = &mut shutdown => break,
+        };
+        let mut router = router.clone();
+        if let Some(peer) = conn.peer_addr() {
+            router = router.layer(Extension(ConnectInfo(peer)));
+        }
+        let service = TowerToHyperService::new(router);
+        let connection = graceful.watch(
+            builder
+                .serve_connection_with_upgrades(TokioIo::new(conn), service)
+                .into_owned(),
+        );
+        tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!("Connection error: {}", err);
+            }
+        });
+    }
+    graceful.shutdown().await;
+}
+
+/// Serves the local control API. Unlike the webhook server it is not restarted with the tunnel.
+async fn run_control_server(state: AppState) -> Result<()> {
+    let addr: SocketAddr = state.settings().control_addr.parse()?;
+    let router = Router::new()
+        .route("/status", get(control_status))
+        .route("/events", get(sse_events))
+        .route("/ws", get(ws_events))
+        .route("/ooo", post(start_out_of_office).delete(end_out_of_office))
+        .route("/pause", post(pause))
+        .route("/resume", post(resume))
+        .route("/wakatime/*path", any(wakatime_relay))
+        .with_state(state);
+
+    info!("Control API listening on http://{}", addr);
+    let listener = tokio::net::TcpListener::bind(addr).await?;
+    axum::serve(listener, router).await?;
+    Ok(())
+}
+
+/// Runs the daemon until Ctrl+C or SIGTERM.
+pub async fn run(settings: Settings) -> Result<()> {
+    run_until(settings, shutdown_requested()).await
+}
+
+/// Starts the timing tasks of every teammate's and routed workspace's status, which stop with
+/// `shutdown_signal` like yours, plus one Toggl subscription watchdog per workspace that isn't
+/// yours.
+fn spawn_team_tasks(
+    app_state: &AppState,
+    shutdown_signal: &Arc<tokio::sync::Notify>,
+) -> (Vec<Supervisor>, Vec<JoinHandle<()>>) {
+    let settings = app_state.settings();
+    let mut supervisors = Vec::new();
+    let mut handles = Vec::new();
+    let mut workspaces = settings
+        .toggl_subscription
+        .as_ref()
+        .and_then(|subscription| subscription.workspace_id)
+        .into_iter()
+        .collect::<HashSet<_>>();
+    let members = settings.team.iter().filter_map(|member| {
+        let member_state = app_state.team.get(&member.toggl_user_id)?;
+        Some((member_state, member.workspace_id))
+    });
+    let routes = settings.workspace_chats.iter().filter_map(|route| {
+        let route_state = app_state.workspaces.get(&route.workspace_id)?;
+        Some((route_state, Some(route.workspace_id)))
+    });
+    for (scoped_state, workspace_id) in members.chain(routes) {
+        let supervisor = Supervisor::new(scoped_state.clone(), shutdown_signal.clone());
+        handles.push(supervisor.spawn("afk_status_updater", afk_status_updater));
+        if !settings.dry_run && scoped_state.settings().has_dynamic_titles() {
+            handles.push(supervisor.spawn("title_refresher", title_refresher));
+        }
+        if let (Some(workspace_id), Some(subscription)) =
+            (workspace_id, &settings.toggl_subscription)
+        {
+            if workspaces.insert(workspace_id) {
+                let subscription = TogglSubscriptionSettings {
+                    workspace_id: Some(workspace_id),
+                    ..subscription.clone()
+                };
+                handles.push(
+                    supervisor.spawn("subscription_watchdog", move |state, signal| {
+                        subscription_watchdog(state, subscription.clone(), signal)
+                    }),
+                );
+            }
+        }
+        supervisors.push(supervisor);
+    }
+    (supervisors, handles)
+}
+
+/// Runs the daemon, the local control API plus the webhook server behind a (re)started ngrok
+/// tunnel, until `shutdown` resolves.
+pub async fn run_until(settings: Settings, shutdown: impl Future<Output = ()>) -> Result<()> {
+    if settings.dry_run {
+        info!("Dry run: events are processed and logged, but nothing is published");
+    }
+    let app_state = AppState::new(settings.clone());
+    // Never stopped; these run for the daemon's lifetime.
+    let supervisor = Supervisor::new(app_state.clone(), Arc::new(tokio::sync::Notify::new()));
+    supervisor.spawn("watch_settings", |state, _| watch_settings(state));
+    supervisor.spawn("presence_loop", |state, _| presence_loop(state));
+    supervisor.spawn("transition_sinks", |state, _| transition_sinks(state));
+    tokio::spawn(reconcile_on_boot(app_state.clone()));
+    for scoped_state in app_state.team.values().chain(app_state.workspaces.values()) {
+        let supervisor =
+            Supervisor::new(scoped_state.clone(), Arc::new(tokio::sync::Notify::new()));
+        supervisor.spawn("presence_loop", |state, _| presence_loop(state));
+        supervisor.spawn("transition_sinks", |state, _| transition_sinks(state));
+        tokio::spawn(reconcile_on_boot(scoped_state.clone()));
+    }
+    tokio::spawn({
+        let app_state = app_state.clone();
+        async move { verify_bot_rights(&Client::new(), &app_state).await }
+    });
+    if let Some(file) = settings.status_file.as_ref().filter(|_| !settings.dry_run) {
+        write_status_file(&app_state, file, app_state.clock.now());
+    }
+    tokio::spawn({
+        let app_state = app_state.clone();
+        async move {
+            if let Err(err) = run_control_server(app_state).await {
+                error!("Control API error: {}", err);
+            }
+        }
+    });
+
+    #[cfg(unix)]
+    tokio::spawn({
+        let app_state = app_state.clone();
+        async move {
+            if let Err(err) = run_control_socket(app_state).await {
+                error!("Control socket error: {}", err);
+            }
+        }
+    });
+
+    // Polled across restarts so a signal arriving between them isn't lost.
+    tokio::pin!(shutdown);
+    let mut ready = false;
+    let mut backoff = Backoff::default();
+    let mut ngrok_sessions = NgrokSessions::default();
+
+    loop {
+        let started_at = Instant::now();
+        let server_handler = match start_listener(&app_state, &mut ngrok_sessions).await {
+            Ok(server_handler) => server_handler,
+            Err(err) => {
+                error!("Failed to start the tunnel: {}", err);
+                app_state
+                    .diagnostics
+                    .error("tunnel", format!("failed to start listener: {}", err));
+                let delay = tunnel_failed(&app_state, &mut backoff).await;
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => continue,
+                    _ = &mut shutdown => break,
+                }
+            }
+        };
+
+        if !ready {
+            ready = true;
+            #[cfg(unix)]
+            notify_systemd(sd_notify::NotifyState::Ready);
+        }
+
+        tokio::select! {
+            res = server_handler => {
+                match res {
+                    Ok(Ok(_)) => info!("Server exited normally."),
+                    Ok(Err(err)) => error!("Server exited with error: {}", err),
+                    Err(err) => error!("Server task panicked: {}", err),
+                }
+            }
+            _ = &mut shutdown => break,
+        }
+
+        // A listener that stayed up for a while worked; one that died right away failed again.
+        if started_at.elapsed() >= TUNNEL_STABLE_AFTER {
+            backoff.reset();
+        }
+        let delay = tunnel_failed(&app_state, &mut backoff).await;
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = &mut shutdown => break,
+        }
+    }
+
+    #[cfg(unix)]
+    notify_systemd(sd_notify::NotifyState::Stopping);
+    set_offline_title(&app_state).await;
+    Ok(())
+}
+
+/// Counts a tunnel failure, alerting `admin_alerts.chat_id` once `tunnel_retry.alert_after`
+/// of them happened in a row, and returns how long to wait before retrying.
+async fn tunnel_failed(app_state: &AppState, backoff: &mut Backoff) -> Duration {
+    let settings = app_state.settings();
+    let delay = backoff.failed(&settings.tunnel_retry);
+    info!("Retrying the tunnel in {:.1}s", delay.as_secs_f64());
+    if backoff.failures() == settings.tunnel_retry.alert_after {
+        let text = format!(
+            "amibussy: the tunnel failed {} times in a row, still retrying",
+            backoff.failures()
+        );
+        error!("{}", text);
+        if let Some(alerts) = settings.admin_alerts.as_ref().filter(|_| !settings.dry_run) {
+            send_message(&Client::new(), &settings, &alerts.chat_id, &text).await;
+        }
+    }
+    delay
+}
+
+/// Opens the tunnel the settings ask for and starts serving on it.
+async fn start_listener(
+    app_state: &AppState,
+    ngrok_sessions: &mut NgrokSessions,
+) -> Result<tokio::task::JoinHandle<Result<()>>> {
+    let settings = app_state.settings();
+    Ok(match settings.tunnel {
+        TunnelKind::Ngrok => {
+            let listener = start_ngrok_listener(&settings, ngrok_sessions).await?;
+            tokio::spawn(run_server(app_state.clone(), listener, None))
+        }
+        TunnelKind::Tailscale => {
+            let (listener, funnel) = start_tailscale_listener(&settings)?;
+            let app_state = app_state.clone();
+            tokio::spawn(async move {
+                let _funnel = funnel;
+                run_server(app_state, listener, None).await
+            })
+        }
+        TunnelKind::Direct => match start_direct_listener(&settings).await? {
+            DirectListener::Plain(listener) => {
+                tokio::spawn(run_server(app_state.clone(), listener, None))
+            }
+            DirectListener::Tls { incoming, renew_at } => {
+                tokio::spawn(run_server(app_state.clone(), incoming, renew_at))
+            }
+        },
+    })
+}
+
+/// Resolves on Ctrl+C or, on Unix, SIGTERM (what systemd and Docker send).
+async fn shutdown_requested() {
+    #[cfg(unix)]
+    {
+        use signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => tokio::select! {
+                _ = signal::ctrl_c() => info!("Received SIGINT, shutting down."),
+                _ = terminate.recv() => info!("Received SIGTERM, shutting down."),
+            },
+            Err(err) => {
+                error!("Failed to listen for SIGTERM: {}", err);
+                let _ = signal::ctrl_c().await;
+                info!("Received SIGINT, shutting down.");
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = signal::ctrl_c().await;
+        info!("Received Ctrl+C, shutting down.");
+    }
+}
+
+/// Tells systemd about startup and shutdown when running as a `Type=notify` service; does
+/// nothing otherwise.
+#[cfg(unix)]
+fn notify_systemd(state: sd_notify::NotifyState) {
+    if let Err(err) = sd_notify::notify(false, &[state]) {
+        error!("Failed to notify systemd: {}", err);
+    }
+}
+
+/// Sets `offline_title`, if configured, as the daemon goes away.
+async fn set_offline_title(state: &AppState) {
+    let settings = state.settings();
+    let Some(title) = &settings.offline_title else {
+        return;
+    };
+    if settings.dry_run {
+        info!("[DRY RUN] Not setting the offline title {:?}", title);
+        return;
+    }
+    match set_chat_title(&Client::new(), &settings, title).await {
+        Ok(()) => info!("Chat title set to {:?} on shutdown", title),
+        Err(err) => error!("Failed to set the offline title: {}", err),
+    }
+}
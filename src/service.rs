@@ -0,0 +1,328 @@
+use anyhow::Result;
+use std::path::Path;
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+use std::{path::PathBuf, process::Command};
+
+#[cfg(target_os = "linux")]
+const SYSTEMD_UNIT: &str = "amibussy.service";
+#[cfg(target_os = "macos")]
+const LAUNCHD_LABEL: &str = "com.github.m0n0x41d.amibussy";
+
+/// The command line the service runs: this binary, with the settings file it was started with.
+fn service_command(config_path: Option<&Path>) -> Result<Vec<String>> {
+    let exe = std::env::current_exe()
+        .map_err(|err| anyhow::anyhow!("can't find the amibussy binary: {}", err))?;
+    let mut command = vec![exe.display().to_string()];
+    if let Some(path) = config_path {
+        command.push("--config".to_string());
+        command.push(std::fs::canonicalize(path)?.display().to_string());
+    }
+    Ok(command)
+}
+
+#[cfg(target_os = "linux")]
+fn systemd_unit(command: &[String]) -> String {
+    let exec_start = command
+        .iter()
+        .map(|arg| format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\"")))
+        .collect::<Vec<_>>()
+        .join(" ");
+    format!(
+        r#"[Unit]
+Description=amibussy: Toggl Track status in your Telegram chat
+
+[Service]
+Type=notify
+ExecStart={}
+ExecReload=/bin/kill -HUP $MAINPID
+Restart=on-failure
+RestartSec=10
+StandardOutput=journal
+StandardError=journal
+
+[Install]
+WantedBy=default.target
+"#,
+        exec_start
+    )
+}
+
+#[cfg(target_os = "macos")]
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(target_os = "macos")]
+fn launchd_plist(command: &[String], log: &Path) -> String {
+    let arguments = command
+        .iter()
+        .map(|arg| format!("        <string>{}</string>", xml_escape(arg)))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let log = xml_escape(&log.display().to_string());
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+    <key>Label</key>
+    <string>{}</string>
+    <key>ProgramArguments</key>
+    <array>
+{}
+    </array>
+    <key>RunAtLoad</key>
+    <true/>
+    <key>KeepAlive</key>
+    <dict>
+        <key>SuccessfulExit</key>
+        <false/>
+    </dict>
+    <key>ThrottleInterval</key>
+    <integer>10</integer>
+    <key>StandardOutPath</key>
+    <string>{}</string>
+    <key>StandardErrorPath</key>
+    <string>{}</string>
+</dict>
+</plist>
+"#,
+        LAUNCHD_LABEL, arguments, log, log
+    )
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn home_path(path: &str) -> PathBuf {
+    PathBuf::from(shellexpand::tilde(path).to_string())
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn run(program: &str, args: &[&str]) -> Result<()> {
+    println!("$ {} {}", program, args.join(" "));
+    let status = Command::new(program)
+        .args(args)
+        .status()
+        .map_err(|err| anyhow::anyhow!("failed to run {}: {}", program, err))?;
+    if !status.success() {
+        anyhow::bail!("{} {} failed with {}", program, args.join(" "), status);
+    }
+    Ok(())
+}
+
+/// `amibussy install-service`: registers a user-level systemd unit (Linux), launchd agent
+/// (macOS) or Windows service that runs this binary with `config_path`, then starts it. With
+/// `print` it's only printed.
+pub fn install_service(config_path: Option<&Path>, print: bool) -> Result<()> {
+    let command = service_command(config_path)?;
+    if config_path.is_none() {
+        eprintln!(
+            "Note: no settings file, the service only gets the environment its manager provides"
+        );
+    }
+    install_platform_service(&command, print)
+}
+
+#[cfg(target_os = "linux")]
+fn install_platform_service(command: &[String], print: bool) -> Result<()> {
+    let unit = systemd_unit(command);
+    if print {
+        print!("{}", unit);
+        return Ok(());
+    }
+    let path = home_path("~/.config/systemd/user").join(SYSTEMD_UNIT);
+    write_service_file(&path, &unit)?;
+    run("systemctl", &["--user", "daemon-reload"])?;
+    run("systemctl", &["--user", "enable", "--now", SYSTEMD_UNIT])?;
+    println!(
+        "Logs: journalctl --user -u {}. To start it at boot rather than at login, run `loginctl enable-linger`.",
+        SYSTEMD_UNIT
+    );
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn install_platform_service(command: &[String], print: bool) -> Result<()> {
+    let log = home_path("~/Library/Logs/amibussy.log");
+    let plist = launchd_plist(command, &log);
+    if print {
+        print!("{}", plist);
+        return Ok(());
+    }
+    let path = home_path("~/Library/LaunchAgents").join(format!("{}.plist", LAUNCHD_LABEL));
+    let path_arg = path.display().to_string();
+    // Reinstalling: unload the old agent first, which fails harmlessly if there is none.
+    let _ = Command::new("launchctl")
+        .args(["unload", &path_arg])
+        .output();
+    write_service_file(&path, &plist)?;
+    run("launchctl", &["load", "-w", &path_arg])?;
+    println!("Logs: {}", log.display());
+    Ok(())
+}
+
+#[cfg(windows)]
+fn install_platform_service(command: &[String], print: bool) -> Result<()> {
+    windows::install(command, print)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+fn install_platform_service(_command: &[String], _print: bool) -> Result<()> {
+    anyhow::bail!("install-service supports systemd (Linux), launchd (macOS) and Windows services")
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+fn write_service_file(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, contents)
+        .map_err(|err| anyhow::anyhow!("failed to write {}: {}", path.display(), err))?;
+    println!("Wrote {}", path.display());
+    Ok(())
+}
+
+/// Running as a Windows service: `install-service` registers `amibussy ... service-run` with the
+/// service control manager, which then starts it through `run`.
+#[cfg(windows)]
+pub mod windows {
+    use crate::{config::Settings, server};
+    use anyhow::Result;
+    use std::{
+        ffi::OsString,
+        path::PathBuf,
+        sync::{Arc, OnceLock},
+        time::Duration,
+    };
+    use tracing::{error, info};
+    use windows_service::{
+        define_windows_service,
+        service::{
+            ServiceAccess, ServiceAction, ServiceActionType, ServiceControl, ServiceControlAccept,
+            ServiceErrorControl, ServiceExitCode, ServiceFailureActions, ServiceFailureResetPeriod,
+            ServiceInfo, ServiceStartType, ServiceState, ServiceStatus, ServiceType,
+        },
+        service_control_handler::{self, ServiceControlHandlerResult},
+        service_dispatcher,
+        service_manager::{ServiceManager, ServiceManagerAccess},
+    };
+
+    const SERVICE_NAME: &str = "amibussy";
+
+    /// What the service thread needs; the dispatcher gives it no way to pass them.
+    static SERVICE: OnceLock<(Settings, tokio::runtime::Handle)> = OnceLock::new();
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    pub fn install(command: &[String], print: bool) -> Result<()> {
+        let (executable, arguments) = command
+            .split_first()
+            .ok_or_else(|| anyhow::anyhow!("empty service command"))?;
+        let mut launch_arguments: Vec<OsString> = arguments.iter().map(OsString::from).collect();
+        launch_arguments.push(OsString::from("service-run"));
+        if print {
+            println!(
+                "{} {}",
+                executable,
+                launch_arguments
+                    .iter()
+                    .map(|arg| arg.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+            return Ok(());
+        }
+
+        let manager = ServiceManager::local_computer(
+            None::<&str>,
+            ServiceManagerAccess::CONNECT | ServiceManagerAccess::CREATE_SERVICE,
+        )?;
+        let service = manager.create_service(
+            &ServiceInfo {
+                name: OsString::from(SERVICE_NAME),
+                display_name: OsString::from("amibussy"),
+                service_type: ServiceType::OWN_PROCESS,
+                start_type: ServiceStartType::AutoStart,
+                error_control: ServiceErrorControl::Normal,
+                executable_path: PathBuf::from(executable),
+                launch_arguments,
+                dependencies: vec![],
+                // LocalSystem.
+                account_name: None,
+                account_password: None,
+            },
+            ServiceAccess::CHANGE_CONFIG | ServiceAccess::START,
+        )?;
+        service.set_description("Toggl Track status in your Telegram chat")?;
+        service.update_failure_actions(ServiceFailureActions {
+            reset_period: ServiceFailureResetPeriod::After(Duration::from_secs(24 * 3600)),
+            reboot_msg: None,
+            command: None,
+            actions: Some(
+                (0..3)
+                    .map(|_| ServiceAction {
+                        action_type: ServiceActionType::Restart,
+                        delay: Duration::from_secs(10),
+                    })
+                    .collect(),
+            ),
+        })?;
+        service.start::<&str>(&[])?;
+        println!("Installed and started the {} service", SERVICE_NAME);
+        Ok(())
+    }
+
+    /// Hands this process to the service control manager. Returns once the service has stopped.
+    pub fn run(settings: Settings) -> Result<()> {
+        let _ = SERVICE.set((settings, tokio::runtime::Handle::current()));
+        tokio::task::block_in_place(|| service_dispatcher::start(SERVICE_NAME, ffi_service_main))?;
+        Ok(())
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        if let Err(err) = run_service() {
+            error!("Windows service failed: {}", err);
+        }
+    }
+
+    fn run_service() -> Result<()> {
+        let (settings, runtime) = SERVICE
+            .get()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("service started without settings"))?;
+
+        let stop = Arc::new(tokio::sync::Notify::new());
+        let stop_requested = stop.clone();
+        let status_handle =
+            service_control_handler::register(SERVICE_NAME, move |control| match control {
+                ServiceControl::Stop | ServiceControl::Shutdown => {
+                    stop_requested.notify_one();
+                    ServiceControlHandlerResult::NoError
+                }
+                ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+                _ => ServiceControlHandlerResult::NotImplemented,
+            })?;
+        let status = |current_state, controls_accepted| ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state,
+            controls_accepted,
+            exit_code: ServiceExitCode::Win32(0),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        };
+
+        status_handle.set_service_status(status(
+            ServiceState::Running,
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+        ))?;
+        let result = runtime.block_on(server::run_until(settings, async move {
+            stop.notified().await;
+            info!("Stop requested by the service control manager");
+        }));
+        status_handle
+            .set_service_status(status(ServiceState::Stopped, ServiceControlAccept::empty()))?;
+        result
+    }
+}
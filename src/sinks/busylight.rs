@@ -0,0 +1,132 @@
+use super::parse_color;
+use crate::{
+    config::BusylightSettings,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+fn color_for(settings: &BusylightSettings, status: Status) -> &str {
+    match status {
+        Status::Busy | Status::ForgotTimer => &settings.busy,
+        Status::Break => &settings.on_break,
+        Status::NotWorking => &settings.not_working,
+    }
+}
+
+#[cfg(feature = "busylight")]
+mod hid {
+    use anyhow::Result;
+    use hidapi::HidApi;
+
+    const LUXAFOR_VENDOR_ID: u16 = 0x04d8;
+    const LUXAFOR_PRODUCT_ID: u16 = 0xf372;
+    const KUANDO_VENDOR_ID: u16 = 0x27bb;
+
+    fn luxafor_report([red, green, blue]: [u8; 3]) -> Vec<u8> {
+        // Report ID, "static colour", all LEDs, then RGB.
+        vec![0x00, 0x01, 0xff, red, green, blue, 0x00, 0x00, 0x00]
+    }
+
+    fn kuando_report([red, green, blue]: [u8; 3]) -> Vec<u8> {
+        let scale = |channel: u8| (u16::from(channel) * 100 / 255) as u8;
+        let mut report = [0u8; 64];
+        // Step 0: jump to itself with the colour on and no sound, i.e. a steady light.
+        report[..8].copy_from_slice(&[
+            0x10,
+            0x00,
+            scale(red),
+            scale(green),
+            scale(blue),
+            0x00,
+            0x00,
+            0x80,
+        ]);
+        report[59..62].copy_from_slice(&[0xff, 0xff, 0xff]);
+        let checksum: u16 = report[..62].iter().map(|&byte| u16::from(byte)).sum();
+        report[62..].copy_from_slice(&checksum.to_be_bytes());
+
+        let mut with_report_id = vec![0x00];
+        with_report_id.extend_from_slice(&report);
+        with_report_id
+    }
+
+    /// Sets every connected busy-light to `color`; returns how many were found.
+    pub fn set_color(color: [u8; 3]) -> Result<usize> {
+        let api = HidApi::new()?;
+        let mut lights = 0;
+        for device in api.device_list() {
+            let report = match (device.vendor_id(), device.product_id()) {
+                (LUXAFOR_VENDOR_ID, LUXAFOR_PRODUCT_ID) => luxafor_report(color),
+                (KUANDO_VENDOR_ID, _) => kuando_report(color),
+                _ => continue,
+            };
+            device.open_device(&api)?.write(&report)?;
+            lights += 1;
+        }
+        Ok(lights)
+    }
+}
+
+#[cfg(not(feature = "busylight"))]
+mod hid {
+    pub fn set_color(_color: [u8; 3]) -> anyhow::Result<usize> {
+        anyhow::bail!("amibussy was built without the `busylight` feature")
+    }
+}
+
+async fn set_color(color: [u8; 3]) -> Result<usize> {
+    tokio::task::spawn_blocking(move || hid::set_color(color)).await?
+}
+
+/// Keeps USB busy-lights showing the current status. Updates right away on a transition and
+/// refreshes every 10 seconds, since Kuando lights switch themselves off without a keep-alive.
+pub async fn busylight_updater(
+    state: AppState,
+    settings: BusylightSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let mut interval = interval(Duration::from_secs(10));
+    let mut transitions = state.transitions.subscribe();
+    let mut last_error = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = transitions.recv() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down busylight_updater");
+                break;
+            }
+        }
+
+        let status = state.decide().status;
+        let result = match parse_color(color_for(&settings, status)) {
+            Ok(color) => set_color(color).await,
+            Err(err) => Err(err),
+        };
+        match result {
+            Ok(0) => state
+                .diagnostics
+                .error("sink.busylight", "no busy-light connected"),
+            Ok(lights) => {
+                last_error = None;
+                state.diagnostics.ok(
+                    "sink.busylight",
+                    format!("{} light(s) showing {:?}", lights, status),
+                );
+            }
+            Err(err) => {
+                // The keep-alive would otherwise log the same error every 10 seconds.
+                let message = err.to_string();
+                if last_error.as_ref() != Some(&message) {
+                    error!("Failed to update busy-light: {}", message);
+                }
+                state.diagnostics.error("sink.busylight", message.clone());
+                last_error = Some(message);
+            }
+        }
+    }
+}
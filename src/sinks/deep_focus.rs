@@ -0,0 +1,161 @@
+use super::slack::{call, is_busy};
+use crate::{
+    config::{DeepFocusSettings, TelegramUserSettings},
+    state::AppState,
+};
+use anyhow::{anyhow, Result};
+use grammers_client::{Client as TelegramClient, Config, InitParams, SignInError};
+use grammers_session::{PackedChat, Session};
+use grammers_tl_types as tl;
+use reqwest::Client;
+use std::{io::Write, sync::atomic::Ordering};
+use tracing::{error, info};
+
+fn session_path(user: &TelegramUserSettings) -> String {
+    shellexpand::tilde(&user.session_file).to_string()
+}
+
+async fn connect(user: &TelegramUserSettings) -> Result<TelegramClient> {
+    let path = session_path(user);
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let client = TelegramClient::connect(Config {
+        session: Session::load_file_or_create(&path)?,
+        api_id: user.api_id,
+        api_hash: user.api_hash.clone(),
+        params: InitParams::default(),
+    })
+    .await?;
+    Ok(client)
+}
+
+fn prompt(question: &str) -> Result<String> {
+    print!("{}", question);
+    std::io::stdout().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(answer.trim().to_string())
+}
+
+/// Logs in to your own Telegram account interactively and saves the session
+/// (`amibussy telegram-login`).
+pub async fn telegram_login(user: &TelegramUserSettings) -> Result<()> {
+    let client = connect(user).await?;
+    if !client.is_authorized().await? {
+        let phone = prompt("Phone number, in international format: ")?;
+        let token = client.request_login_code(&phone).await?;
+        let code = prompt("The code Telegram sent you: ")?;
+        match client.sign_in(&token, &code).await {
+            Ok(_) => {}
+            Err(SignInError::PasswordRequired(password_token)) => {
+                let password = prompt("Two-step verification password: ")?;
+                client.check_password(password_token, password).await?;
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    client.session().save_to_file(session_path(user))?;
+    println!("Logged in; session saved to {}", session_path(user));
+    Ok(())
+}
+
+/// `chat_id` among your chats: by username, or by its Bot API ID among your dialogs.
+async fn find_chat(client: &TelegramClient, chat_id: &str) -> Result<PackedChat> {
+    if let Some(username) = chat_id.strip_prefix('@') {
+        let chat = client
+            .resolve_username(username)
+            .await?
+            .ok_or_else(|| anyhow!("no chat {}", chat_id))?;
+        return Ok(chat.pack());
+    }
+    // The Bot API marks channels and supergroups with -100 and basic groups with a minus.
+    let id = match chat_id.strip_prefix("-100") {
+        Some(id) => id.parse::<i64>()?,
+        None => chat_id.parse::<i64>()?.abs(),
+    };
+    let mut dialogs = client.iter_dialogs();
+    while let Some(dialog) = dialogs.next().await? {
+        if dialog.chat().id() == id {
+            return Ok(dialog.chat().pack());
+        }
+    }
+    Err(anyhow!("chat {} isn't among your chats", chat_id))
+}
+
+/// Mutes (or unmutes) `chat_id` for your own account, so its messages don't notify you.
+async fn set_chat_muted(user: &TelegramUserSettings, chat_id: &str, muted: bool) -> Result<()> {
+    let client = connect(user).await?;
+    if !client.is_authorized().await? {
+        anyhow::bail!("not logged in, run `amibussy telegram-login`");
+    }
+    let chat = find_chat(&client, chat_id).await?;
+    client
+        .invoke(&tl::functions::account::UpdateNotifySettings {
+            peer: tl::types::InputNotifyPeer {
+                peer: chat.to_input_peer(),
+            }
+            .into(),
+            settings: tl::types::InputPeerNotifySettings {
+                show_previews: None,
+                silent: None,
+                mute_until: Some(if muted { i32::MAX } else { 0 }),
+                sound: None,
+                stories_muted: None,
+                stories_hide_sender: None,
+                stories_sound: None,
+            }
+            .into(),
+        })
+        .await?;
+    client.session().save_to_file(session_path(user))?;
+    Ok(())
+}
+
+/// Puts the `deep_focus` measures in place when a tagged entry makes you Busy, and lifts
+/// them once it's over. The title is taken care of by `effective_title`.
+pub async fn update_deep_focus(client: &Client, state: &AppState, deep_focus: &DeepFocusSettings) {
+    let decision = state.decide();
+    let on = state.in_deep_focus(decision);
+    if state.deep_focus_on.swap(on, Ordering::Relaxed) == on {
+        return;
+    }
+    info!("[DEEP FOCUS] {}", if on { "Started" } else { "Over" });
+    let settings = state.settings();
+
+    // `slack_dnd` keeps its own snooze for as long as you're Busy.
+    let keep_snooze = settings.slack_dnd.is_some() && is_busy(decision.status);
+    if let Some(token) = deep_focus.slack_token.as_deref().filter(|_| !keep_snooze) {
+        let result = if on {
+            let minutes = deep_focus.snooze_minutes.to_string();
+            call(client, token, "dnd.setSnooze", &[("num_minutes", minutes)]).await
+        } else {
+            call(client, token, "dnd.endSnooze", &[]).await
+        };
+        match result {
+            Ok(()) => state
+                .diagnostics
+                .ok("sink.deep_focus_slack", format!("snoozed: {}", on)),
+            Err(err) => {
+                error!("Failed to update Slack DND for deep focus: {}", err);
+                state
+                    .diagnostics
+                    .error("sink.deep_focus_slack", err.to_string());
+            }
+        }
+    }
+
+    if let Some(user) = &deep_focus.telegram_user {
+        match set_chat_muted(user, &settings.chat_id, on).await {
+            Ok(()) => state
+                .diagnostics
+                .ok("sink.deep_focus_telegram", format!("chat muted: {}", on)),
+            Err(err) => {
+                error!("Failed to mute the chat for deep focus: {}", err);
+                state
+                    .diagnostics
+                    .error("sink.deep_focus_telegram", err.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use crate::{
+    config::ElgatoSettings,
+    state::{AppState, Status, StatusSource},
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// Elgato lights take the colour temperature in mireds, clamped to 143..=344 (7000K..2900K).
+fn kelvin_to_mired(kelvin: u32) -> u32 {
+    (1_000_000 / kelvin.max(1)).clamp(143, 344)
+}
+
+async fn set_lights(client: &Client, settings: &ElgatoSettings, on: bool) -> Result<()> {
+    let body = json!({
+        "numberOfLights": 1,
+        "lights": [{
+            "on": u8::from(on),
+            "brightness": settings.brightness.min(100),
+            "temperature": kelvin_to_mired(settings.temperature_kelvin),
+        }],
+    });
+    for device in &settings.devices {
+        let address = if device.contains(':') {
+            device.clone()
+        } else {
+            format!("{}:9123", device)
+        };
+        client
+            .put(format!("http://{}/elgato/lights", address))
+            .json(&body)
+            .timeout(Duration::from_secs(5))
+            .send()
+            .await?
+            .error_for_status()?;
+    }
+    Ok(())
+}
+
+fn wanted_on(state: &AppState, settings: &ElgatoSettings) -> bool {
+    let decision = state.decide();
+    let busy = matches!(decision.status, Status::Busy | Status::ForgotTimer);
+    busy && (!settings.only_in_meetings || decision.source == StatusSource::Meeting)
+}
+
+/// Switches Elgato Key/Ring Lights on while busy (or only in meetings) and off otherwise.
+///
+/// Polls the decision instead of waiting for transitions, because going from a running timer
+/// into a meeting changes what lit us up without changing the status.
+pub async fn elgato_updater(
+    state: AppState,
+    settings: ElgatoSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(5));
+    let mut lights_on = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down elgato_updater");
+                break;
+            }
+        }
+
+        let on = wanted_on(&state, &settings);
+        if lights_on == Some(on) {
+            continue;
+        }
+        match set_lights(&client, &settings, on).await {
+            Ok(()) => {
+                info!("Elgato lights on: {}", on);
+                state
+                    .diagnostics
+                    .ok("sink.elgato", format!("lights on: {}", on));
+                lights_on = Some(on);
+            }
+            Err(err) => {
+                error!("Failed to switch Elgato lights: {}", err);
+                state.diagnostics.error("sink.elgato", err.to_string());
+            }
+        }
+    }
+}
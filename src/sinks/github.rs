@@ -0,0 +1,77 @@
+use crate::{
+    config::GitHubStatusSettings,
+    history::Transition,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::sync::{atomic::Ordering, PoisonError};
+use tracing::{error, info};
+
+const CHANGE_USER_STATUS: &str = "mutation($input: ChangeUserStatusInput!) {
+  changeUserStatus(input: $input) { status { message } }
+}";
+
+/// Calls `changeUserStatus`; an input without a message or emoji clears the status.
+async fn change_user_status(client: &Client, token: &str, input: Value) -> Result<()> {
+    let response: Value = client
+        .post("https://api.github.com/graphql")
+        .bearer_auth(token)
+        .header("User-Agent", "amibussy")
+        .json(&json!({ "query": CHANGE_USER_STATUS, "variables": { "input": input } }))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if let Some(errors) = response.get("errors") {
+        anyhow::bail!("GraphQL errors: {}", errors);
+    }
+    Ok(())
+}
+
+/// Sets the GitHub profile status while a Toggl entry tagged `github.tag` runs, and clears it
+/// once that entry is over.
+pub async fn update_github_status(
+    client: &Client,
+    state: &AppState,
+    github: &GitHubStatusSettings,
+    transition: &Transition,
+) {
+    let tagged = state
+        .current_entry
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_ref()
+        .is_some_and(|entry| entry.tags.iter().any(|tag| tag == &github.tag));
+    let want_status = transition.to == Status::Busy && tagged;
+    if want_status == state.github_status_set.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let input = if want_status {
+        json!({
+            "message": github.message,
+            "emoji": github.emoji,
+            "limitedAvailability": true,
+        })
+    } else {
+        json!({})
+    };
+    match change_user_status(client, &github.token, input).await {
+        Ok(()) => {
+            info!("GitHub status set: {}", want_status);
+            state
+                .github_status_set
+                .store(want_status, Ordering::Relaxed);
+            state
+                .diagnostics
+                .ok("sink.github", format!("status set: {}", want_status));
+        }
+        Err(err) => {
+            error!("Failed to change GitHub status: {}", err);
+            state.diagnostics.error("sink.github", err.to_string());
+        }
+    }
+}
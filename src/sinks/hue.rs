@@ -0,0 +1,113 @@
+use super::parse_color;
+use crate::{
+    config::{HueLightState, HueSettings},
+    history::Transition,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::{json, Map, Value};
+use tracing::{error, info};
+
+/// Converts sRGB to the CIE xy coordinates the Hue API takes.
+fn rgb_to_xy([red, green, blue]: [u8; 3]) -> [f64; 2] {
+    let linear = |channel: u8| {
+        let value = f64::from(channel) / 255.0;
+        if value > 0.04045 {
+            ((value + 0.055) / 1.055).powf(2.4)
+        } else {
+            value / 12.92
+        }
+    };
+    let (r, g, b) = (linear(red), linear(green), linear(blue));
+    let x = r * 0.664511 + g * 0.154324 + b * 0.162028;
+    let y = r * 0.283881 + g * 0.668433 + b * 0.047685;
+    let z = r * 0.000088 + g * 0.072310 + b * 0.986039;
+    let sum = x + y + z;
+    if sum == 0.0 {
+        [0.0, 0.0]
+    } else {
+        [x / sum, y / sum]
+    }
+}
+
+fn state_body(light: &HueLightState) -> Result<Value> {
+    let mut body = Map::new();
+    if let Some(scene) = &light.scene {
+        body.insert("scene".into(), json!(scene));
+        return Ok(Value::Object(body));
+    }
+    body.insert("on".into(), json!(light.on));
+    if let Some(brightness) = light.brightness {
+        // The API takes 1..=254 rather than a percentage.
+        let bri = (u32::from(brightness.min(100)) * 254 / 100).max(1);
+        body.insert("bri".into(), json!(bri));
+    }
+    if let Some(color) = &light.color {
+        body.insert("xy".into(), json!(rgb_to_xy(parse_color(color)?)));
+    }
+    Ok(Value::Object(body))
+}
+
+async fn apply(client: &Client, hue: &HueSettings, light: &HueLightState) -> Result<()> {
+    let body = state_body(light)?;
+    let base = format!("http://{}/api/{}", hue.bridge_ip, hue.app_key);
+    let mut urls: Vec<String> = hue
+        .lights
+        .iter()
+        .map(|id| format!("{}/lights/{}/state", base, id))
+        .collect();
+    if let Some(group) = &hue.group {
+        urls.push(format!("{}/groups/{}/action", base, group));
+    }
+
+    for url in urls {
+        let response: Value = client
+            .put(&url)
+            .json(&body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        // The bridge answers 200 with a list of per-attribute results, errors included.
+        if let Some(error) = response
+            .as_array()
+            .and_then(|results| results.iter().find_map(|result| result.get("error")))
+        {
+            anyhow::bail!("Hue bridge error: {}", error);
+        }
+    }
+    Ok(())
+}
+
+/// Sets the configured Hue lights or group to the state for the status being entered.
+pub async fn update_hue(
+    client: &Client,
+    state: &AppState,
+    hue: &HueSettings,
+    transition: &Transition,
+) {
+    let light = match transition.to {
+        Status::Busy => hue.busy.as_ref(),
+        Status::ForgotTimer => hue.forgot_timer.as_ref().or(hue.busy.as_ref()),
+        Status::Break => hue.on_break.as_ref(),
+        Status::NotWorking => hue.not_working.as_ref(),
+    };
+    let Some(light) = light else {
+        return;
+    };
+
+    match apply(client, hue, light).await {
+        Ok(()) => {
+            info!("Updated Hue lights for {:?}", transition.to);
+            state
+                .diagnostics
+                .ok("sink.hue", format!("set for {:?}", transition.to));
+        }
+        Err(err) => {
+            error!("Failed to update Hue lights: {}", err);
+            state.diagnostics.error("sink.hue", err.to_string());
+        }
+    }
+}
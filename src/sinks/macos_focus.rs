@@ -0,0 +1,64 @@
+use crate::{
+    config::MacosFocusSettings,
+    history::Transition,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+
+fn is_busy(status: Status) -> bool {
+    matches!(status, Status::Busy | Status::ForgotTimer)
+}
+
+/// Runs a Shortcuts automation by name. macOS has no public API for toggling a Focus,
+/// so a "Set Focus" shortcut is the supported way to do it.
+async fn run_shortcut(name: &str) -> Result<()> {
+    let output = Command::new("shortcuts")
+        .arg("run")
+        .arg(name)
+        .output()
+        .await?;
+    if !output.status.success() {
+        anyhow::bail!(
+            "shortcuts run {:?} exited with {}: {}",
+            name,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+/// Turns the Focus on when entering Busy and off when leaving it.
+pub async fn update_macos_focus(
+    state: &AppState,
+    focus: &MacosFocusSettings,
+    transition: &Transition,
+) {
+    if is_busy(transition.from) == is_busy(transition.to) {
+        return;
+    }
+    if !cfg!(target_os = "macos") {
+        warn!("macos_focus is configured, but this isn't macOS");
+        return;
+    }
+
+    let shortcut = if is_busy(transition.to) {
+        &focus.on_shortcut
+    } else {
+        &focus.off_shortcut
+    };
+    match run_shortcut(shortcut).await {
+        Ok(()) => {
+            info!("Ran shortcut {:?}", shortcut);
+            state
+                .diagnostics
+                .ok("sink.macos_focus", format!("ran {:?}", shortcut));
+        }
+        Err(err) => {
+            error!("Failed to toggle Focus: {}", err);
+            state.diagnostics.error("sink.macos_focus", err.to_string());
+        }
+    }
+}
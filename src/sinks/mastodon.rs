@@ -0,0 +1,157 @@
+use crate::{
+    config::MastodonSettings,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use reqwest::{Client, StatusCode};
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+enum UpdateError {
+    /// Rate limited; retry no earlier than this many seconds from now.
+    RateLimited(u64),
+    Other(anyhow::Error),
+}
+
+impl<E: Into<anyhow::Error>> From<E> for UpdateError {
+    fn from(err: E) -> Self {
+        UpdateError::Other(err.into())
+    }
+}
+
+fn value_for(mastodon: &MastodonSettings, status: Status) -> &str {
+    match status {
+        Status::Busy | Status::ForgotTimer => &mastodon.busy,
+        Status::Break => &mastodon.on_break,
+        Status::NotWorking => &mastodon.not_working,
+    }
+}
+
+fn rate_limited(response: &reqwest::Response) -> Option<u64> {
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let reset = response
+        .headers()
+        .get("x-ratelimit-reset")
+        .and_then(|reset| reset.to_str().ok())
+        .and_then(|reset| chrono::DateTime::parse_from_rfc3339(reset).ok())
+        .map(|reset| (reset.timestamp() - chrono::Utc::now().timestamp()).max(0) as u64);
+    Some(reset.unwrap_or(300))
+}
+
+/// Replaces the value of the configured profile field, keeping every other field as it is.
+/// `update_credentials` takes the whole list, so the current one is fetched first.
+async fn set_profile_field(
+    client: &Client,
+    mastodon: &MastodonSettings,
+    value: &str,
+) -> Result<(), UpdateError> {
+    let base = mastodon.instance.trim_end_matches('/');
+    let response = client
+        .get(format!("{}/api/v1/accounts/verify_credentials", base))
+        .bearer_auth(&mastodon.access_token)
+        .send()
+        .await?;
+    if let Some(wait) = rate_limited(&response) {
+        return Err(UpdateError::RateLimited(wait));
+    }
+    let account: Value = response.error_for_status()?.json().await?;
+
+    let mut fields: Vec<(String, String)> = account["source"]["fields"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|field| {
+            (
+                field["name"].as_str().unwrap_or_default().to_string(),
+                field["value"].as_str().unwrap_or_default().to_string(),
+            )
+        })
+        .collect();
+    match fields
+        .iter_mut()
+        .find(|(name, _)| *name == mastodon.field_name)
+    {
+        Some((_, current)) => *current = value.to_string(),
+        None => fields.push((mastodon.field_name.clone(), value.to_string())),
+    }
+
+    let form: Vec<(String, String)> = fields
+        .iter()
+        .enumerate()
+        .flat_map(|(i, (name, value))| {
+            [
+                (format!("fields_attributes[{}][name]", i), name.clone()),
+                (format!("fields_attributes[{}][value]", i), value.clone()),
+            ]
+        })
+        .collect();
+    let response = client
+        .patch(format!("{}/api/v1/accounts/update_credentials", base))
+        .bearer_auth(&mastodon.access_token)
+        .form(&form)
+        .send()
+        .await?;
+    if let Some(wait) = rate_limited(&response) {
+        return Err(UpdateError::RateLimited(wait));
+    }
+    response.error_for_status()?;
+    Ok(())
+}
+
+/// Keeps a Mastodon profile field in sync with the status.
+///
+/// Instead of reacting to every transition, this catches up at most once per
+/// `min_interval_secs` and backs off when the instance says we're rate limited, so a
+/// flurry of start/stop events ends up as a single profile update.
+pub async fn mastodon_updater(
+    state: AppState,
+    mastodon: MastodonSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(30));
+    let mut shown: Option<String> = None;
+    let mut not_before = 0;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down mastodon_updater");
+                break;
+            }
+        }
+
+        let now = state.clock.now();
+        let value = value_for(&mastodon, state.decide().status);
+        if shown.as_deref() == Some(value) || now < not_before {
+            continue;
+        }
+
+        match set_profile_field(&client, &mastodon, value).await {
+            Ok(()) => {
+                info!("Mastodon profile field set to {:?}", value);
+                state
+                    .diagnostics
+                    .ok("sink.mastodon", format!("field set to {:?}", value));
+                shown = Some(value.to_string());
+                not_before = now + mastodon.min_interval_secs;
+            }
+            Err(UpdateError::RateLimited(wait)) => {
+                warn!("Mastodon rate limit hit, retrying in {}s", wait);
+                state
+                    .diagnostics
+                    .error("sink.mastodon", format!("rate limited for {}s", wait));
+                not_before = now + wait;
+            }
+            Err(UpdateError::Other(err)) => {
+                error!("Failed to update Mastodon profile: {}", err);
+                state.diagnostics.error("sink.mastodon", err.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,38 @@
+use crate::config::{MatrixRoomField, MatrixSettings};
+use anyhow::Result;
+use reqwest::{Client, Url};
+use serde_json::json;
+use tracing::info;
+
+/// Sets the room's name (or topic) through a `m.room.name` / `m.room.topic` state event.
+pub async fn set_room_title(client: &Client, matrix: &MatrixSettings, title: &str) -> Result<()> {
+    let (event_type, body) = match matrix.field {
+        MatrixRoomField::Name => ("m.room.name", json!({ "name": title })),
+        MatrixRoomField::Topic => ("m.room.topic", json!({ "topic": title })),
+    };
+
+    let mut url = Url::parse(&matrix.homeserver)?;
+    url.path_segments_mut()
+        .map_err(|_| anyhow::anyhow!("invalid homeserver URL {}", matrix.homeserver))?
+        .pop_if_empty()
+        .extend([
+            "_matrix",
+            "client",
+            "v3",
+            "rooms",
+            &matrix.room_id,
+            "state",
+            event_type,
+            "",
+        ]);
+
+    client
+        .put(url)
+        .bearer_auth(&matrix.access_token)
+        .json(&body)
+        .send()
+        .await?
+        .error_for_status()?;
+    info!("Successfully updated Matrix room {}", event_type);
+    Ok(())
+}
@@ -0,0 +1,70 @@
+use crate::{
+    config::{CustomStatus, MattermostSettings},
+    history::Transition,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::json;
+use tracing::{error, info};
+
+async fn set_custom_status(
+    client: &Client,
+    state: &AppState,
+    mattermost: &MattermostSettings,
+    custom: Option<&CustomStatus>,
+) -> Result<()> {
+    let url = format!(
+        "{}/api/v4/users/me/status/custom",
+        mattermost.url.trim_end_matches('/')
+    );
+    let request = match custom {
+        Some(custom) => {
+            let mut body = json!({ "emoji": custom.emoji, "text": custom.text });
+            if let Some(minutes) = mattermost.expiry_minutes {
+                let expires_at =
+                    chrono::DateTime::from_timestamp((state.clock.now() + minutes * 60) as i64, 0)
+                        .ok_or_else(|| anyhow::anyhow!("invalid expiry"))?;
+                body["duration"] = json!("date_and_time");
+                body["expires_at"] = json!(expires_at.to_rfc3339());
+            }
+            client.put(&url).json(&body)
+        }
+        None => client.delete(&url),
+    };
+
+    request
+        .bearer_auth(&mattermost.token)
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Sets (or clears) the Mattermost custom status for the status being entered.
+pub async fn update_mattermost(
+    client: &Client,
+    state: &AppState,
+    mattermost: &MattermostSettings,
+    transition: &Transition,
+) {
+    let custom = match transition.to {
+        Status::Busy | Status::ForgotTimer => mattermost.busy.as_ref(),
+        Status::Break => mattermost.on_break.as_ref(),
+        Status::NotWorking => mattermost.not_working.as_ref(),
+    };
+
+    match set_custom_status(client, state, mattermost, custom).await {
+        Ok(()) => {
+            info!("Updated Mattermost custom status");
+            state.diagnostics.ok(
+                "sink.mattermost",
+                format!("custom status set for {:?}", transition.to),
+            );
+        }
+        Err(err) => {
+            error!("Failed to update Mattermost custom status: {}", err);
+            state.diagnostics.error("sink.mattermost", err.to_string());
+        }
+    }
+}
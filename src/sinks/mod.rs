@@ -0,0 +1,27 @@
+pub mod busylight;
+pub mod deep_focus;
+pub mod elgato;
+pub mod github;
+pub mod hue;
+pub mod macos_focus;
+pub mod mastodon;
+pub mod matrix;
+pub mod mattermost;
+pub mod notifications;
+pub mod push;
+pub mod shell;
+pub mod slack;
+pub mod status_file;
+pub mod teams;
+pub mod telegram;
+pub mod webhook;
+
+/// Parses a `#rrggbb` colour.
+pub(crate) fn parse_color(color: &str) -> anyhow::Result<[u8; 3]> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 {
+        anyhow::bail!("expected a #rrggbb color, got {:?}", color);
+    }
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16);
+    Ok([channel(0)?, channel(2)?, channel(4)?])
+}
@@ -0,0 +1,126 @@
+use crate::{
+    config::{NotificationBackend, NotificationPauseSettings},
+    history::Transition,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use tracing::{error, info};
+
+fn is_busy(status: Status) -> bool {
+    matches!(status, Status::Busy | Status::ForgotTimer)
+}
+
+/// Keeps what is needed to undo a pause. KDE drops an inhibition as soon as the D-Bus
+/// connection that asked for it goes away, so that connection is held here until Break.
+#[derive(Default)]
+pub struct NotificationPause {
+    #[cfg(feature = "dbus")]
+    kde_inhibition: tokio::sync::Mutex<Option<(zbus::Connection, u32)>>,
+}
+
+#[cfg(feature = "dbus")]
+impl NotificationPause {
+    async fn set_paused(&self, backend: NotificationBackend, paused: bool) -> Result<()> {
+        match backend {
+            NotificationBackend::Dunst => {
+                let connection = zbus::Connection::session().await?;
+                let proxy = zbus::Proxy::new(
+                    &connection,
+                    "org.freedesktop.Notifications",
+                    "/org/freedesktop/Notifications",
+                    "org.dunstproject.cmd0",
+                )
+                .await?;
+                proxy.set_property("paused", paused).await?;
+            }
+            NotificationBackend::Gnome => {
+                // GNOME keeps "Do Not Disturb" in GSettings rather than behind a D-Bus method.
+                let output = tokio::process::Command::new("gsettings")
+                    .args(["set", "org.gnome.desktop.notifications", "show-banners"])
+                    .arg(if paused { "false" } else { "true" })
+                    .output()
+                    .await?;
+                if !output.status.success() {
+                    anyhow::bail!(
+                        "gsettings failed: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    );
+                }
+            }
+            NotificationBackend::Kde => {
+                let mut inhibition = self.kde_inhibition.lock().await;
+                if paused && inhibition.is_none() {
+                    let connection = zbus::Connection::session().await?;
+                    let cookie: u32 = connection
+                        .call_method(
+                            Some("org.freedesktop.Notifications"),
+                            "/org/freedesktop/Notifications",
+                            Some("org.freedesktop.Notifications"),
+                            "Inhibit",
+                            &(
+                                "amibussy",
+                                "Busy",
+                                std::collections::HashMap::<&str, zbus::zvariant::Value>::new(),
+                            ),
+                        )
+                        .await?
+                        .body()
+                        .deserialize()?;
+                    *inhibition = Some((connection, cookie));
+                } else if !paused {
+                    if let Some((connection, cookie)) = inhibition.take() {
+                        connection
+                            .call_method(
+                                Some("org.freedesktop.Notifications"),
+                                "/org/freedesktop/Notifications",
+                                Some("org.freedesktop.Notifications"),
+                                "UnInhibit",
+                                &(cookie,),
+                            )
+                            .await?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "dbus"))]
+impl NotificationPause {
+    async fn set_paused(&self, _backend: NotificationBackend, _paused: bool) -> Result<()> {
+        anyhow::bail!("amibussy was built without the `dbus` feature")
+    }
+}
+
+/// Pauses desktop notifications when entering Busy and restores them when leaving it.
+pub async fn update_notification_pause(
+    state: &AppState,
+    settings: &NotificationPauseSettings,
+    transition: &Transition,
+) {
+    if is_busy(transition.from) == is_busy(transition.to) {
+        return;
+    }
+
+    let paused = is_busy(transition.to);
+    match state
+        .notification_pause
+        .set_paused(settings.backend, paused)
+        .await
+    {
+        Ok(()) => {
+            info!("Desktop notifications paused: {}", paused);
+            state.diagnostics.ok(
+                "sink.notifications",
+                format!("{:?} notifications paused: {}", settings.backend, paused),
+            );
+        }
+        Err(err) => {
+            error!("Failed to pause desktop notifications: {}", err);
+            state
+                .diagnostics
+                .error("sink.notifications", err.to_string());
+        }
+    }
+}
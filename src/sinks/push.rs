@@ -0,0 +1,81 @@
+use crate::{
+    config::{NtfySettings, PushSettings, PushoverSettings},
+    history::Transition,
+    state::AppState,
+};
+use anyhow::Result;
+use reqwest::Client;
+use tracing::{error, info};
+
+async fn send_ntfy(client: &Client, ntfy: &NtfySettings, message: &str) -> Result<()> {
+    let mut request = client
+        .post(format!(
+            "{}/{}",
+            ntfy.server.trim_end_matches('/'),
+            ntfy.topic
+        ))
+        .header("Title", "amibussy")
+        .body(message.to_string());
+    if let Some(token) = &ntfy.token {
+        request = request.bearer_auth(token);
+    }
+    request.send().await?.error_for_status()?;
+    Ok(())
+}
+
+async fn send_pushover(client: &Client, pushover: &PushoverSettings, message: &str) -> Result<()> {
+    client
+        .post("https://api.pushover.net/1/messages.json")
+        .form(&[
+            ("token", pushover.token.as_str()),
+            ("user", pushover.user.as_str()),
+            ("title", "amibussy"),
+            ("message", message),
+        ])
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Sends a push notification for transitions into one of the `push.on` statuses.
+pub async fn send_push(
+    client: &Client,
+    state: &AppState,
+    push: &PushSettings,
+    transition: &Transition,
+) {
+    if !push.on.contains(&transition.to) {
+        return;
+    }
+    let message = push
+        .message
+        .replace("{title}", transition.to.title(&state.settings()))
+        .replace("{from}", transition.from.title(&state.settings()))
+        .replace("{cause}", &transition.cause);
+
+    if let Some(ntfy) = &push.ntfy {
+        match send_ntfy(client, ntfy, &message).await {
+            Ok(()) => {
+                info!("Sent ntfy notification");
+                state.diagnostics.ok("sink.ntfy", "notification sent");
+            }
+            Err(err) => {
+                error!("Failed to send ntfy notification: {}", err);
+                state.diagnostics.error("sink.ntfy", err.to_string());
+            }
+        }
+    }
+    if let Some(pushover) = &push.pushover {
+        match send_pushover(client, pushover, &message).await {
+            Ok(()) => {
+                info!("Sent Pushover notification");
+                state.diagnostics.ok("sink.pushover", "notification sent");
+            }
+            Err(err) => {
+                error!("Failed to send Pushover notification: {}", err);
+                state.diagnostics.error("sink.pushover", err.to_string());
+            }
+        }
+    }
+}
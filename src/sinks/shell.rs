@@ -0,0 +1,87 @@
+use crate::{
+    config::ShellHookSettings,
+    history::Transition,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::{error, info, warn};
+
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C").arg(command);
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c").arg(command);
+        shell
+    }
+}
+
+async fn run_hook(
+    state: &AppState,
+    command: &str,
+    timeout: u64,
+    transition: &Transition,
+) -> Result<()> {
+    let mut shell = shell_command(command);
+    shell
+        .env("AMIBUSSY_STATUS", transition.to.as_str())
+        .env("AMIBUSSY_FROM", transition.from.as_str())
+        .env("AMIBUSSY_TITLE", transition.to.title(&state.settings()))
+        .env("AMIBUSSY_CAUSE", &transition.cause)
+        .kill_on_drop(true);
+
+    let output = tokio::time::timeout(Duration::from_secs(timeout), shell.output())
+        .await
+        .map_err(|_| anyhow::anyhow!("timed out after {}s", timeout))??;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stdout.trim().is_empty() {
+        info!("[HOOK] {}: {}", command, stdout.trim());
+    }
+    if !stderr.trim().is_empty() {
+        warn!("[HOOK] {} (stderr): {}", command, stderr.trim());
+    }
+    if !output.status.success() {
+        anyhow::bail!("exited with {}", output.status);
+    }
+    Ok(())
+}
+
+fn hook_for(hooks: &ShellHookSettings, status: Status) -> Option<&str> {
+    match status {
+        Status::Busy => hooks.on_busy.as_deref(),
+        Status::ForgotTimer => hooks
+            .on_forgot_timer
+            .as_deref()
+            .or(hooks.on_busy.as_deref()),
+        Status::Break => hooks.on_break.as_deref(),
+        Status::NotWorking => hooks.on_not_working.as_deref(),
+    }
+}
+
+/// Runs the command configured for the status being entered, if any.
+pub async fn run_shell_hooks(state: &AppState, transition: &Transition) {
+    let Some(hooks) = &state.settings().hooks else {
+        return;
+    };
+    let Some(command) = hook_for(hooks, transition.to) else {
+        return;
+    };
+
+    match run_hook(state, command, hooks.timeout_secs, transition).await {
+        Ok(()) => state
+            .diagnostics
+            .ok("sink.shell", format!("{:?} hook ran", transition.to)),
+        Err(err) => {
+            error!("Hook {:?} failed: {}", command, err);
+            state
+                .diagnostics
+                .error("sink.shell", format!("{}: {}", command, err));
+        }
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{
+    config::SlackDndSettings,
+    history::Transition,
+    state::{AppState, Status},
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::Value;
+use tracing::{error, info};
+
+pub(crate) fn is_busy(status: Status) -> bool {
+    matches!(status, Status::Busy | Status::ForgotTimer)
+}
+
+pub(crate) async fn call(
+    client: &Client,
+    token: &str,
+    method: &str,
+    params: &[(&str, String)],
+) -> Result<()> {
+    let response: Value = client
+        .post(format!("https://slack.com/api/{}", method))
+        .bearer_auth(token)
+        .form(params)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    // Ending a snooze that already ran out is not worth an error.
+    if response["ok"] != Value::Bool(true) && response["error"] != "snooze_not_active" {
+        anyhow::bail!("{} failed: {}", method, response["error"]);
+    }
+    Ok(())
+}
+
+/// Snoozes Slack notifications when a focus block starts and ends the snooze on Break/AFK.
+pub async fn update_slack_dnd(
+    client: &Client,
+    state: &AppState,
+    dnd: &SlackDndSettings,
+    transition: &Transition,
+) {
+    if is_busy(transition.from) == is_busy(transition.to) {
+        return;
+    }
+
+    let result = if is_busy(transition.to) {
+        call(
+            client,
+            &dnd.token,
+            "dnd.setSnooze",
+            &[("num_minutes", dnd.snooze_minutes.to_string())],
+        )
+        .await
+    } else {
+        call(client, &dnd.token, "dnd.endSnooze", &[]).await
+    };
+
+    match result {
+        Ok(()) => {
+            info!("Slack snoozed: {}", is_busy(transition.to));
+            state.diagnostics.ok(
+                "sink.slack_dnd",
+                format!("snoozed: {}", is_busy(transition.to)),
+            );
+        }
+        Err(err) => {
+            error!("Failed to update Slack DND: {}", err);
+            state.diagnostics.error("sink.slack_dnd", err.to_string());
+        }
+    }
+}
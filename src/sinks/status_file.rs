@@ -0,0 +1,39 @@
+use crate::{config::StatusFileSettings, state::AppState};
+use anyhow::Result;
+use std::path::Path;
+use tracing::error;
+
+/// Renders the template with `{status}`, `{title}` and `{since}` (UNIX timestamp).
+fn render(state: &AppState, file: &StatusFileSettings, since: u64) -> String {
+    let status = state.decide().status;
+    file.template
+        .replace("{status}", status.as_str())
+        .replace("{title}", status.title(&state.settings()))
+        .replace("{since}", &since.to_string())
+}
+
+/// Writes next to the target and renames over it, so bars never read a half-written file.
+fn write_atomically(path: &Path, contents: &str) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    std::fs::write(&temporary, contents)?;
+    std::fs::rename(&temporary, path)?;
+    Ok(())
+}
+
+/// Writes the current status to the status file for waybar, polybar, i3status and friends.
+pub fn write_status_file(state: &AppState, file: &StatusFileSettings, since: u64) {
+    let path = shellexpand::tilde(&file.path).to_string();
+    match write_atomically(Path::new(&path), &render(state, file, since)) {
+        Ok(()) => state.diagnostics.ok("sink.status_file", path),
+        Err(err) => {
+            error!("Failed to write status file {}: {}", path, err);
+            state
+                .diagnostics
+                .error("sink.status_file", format!("{}: {}", path, err));
+        }
+    }
+}
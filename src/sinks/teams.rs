@@ -0,0 +1,181 @@
+use crate::{
+    config::{TeamsPresence, TeamsSettings},
+    history::Transition,
+    state::{get_unix_timestamp, AppState, Status},
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::time::Duration;
+use tracing::{error, info};
+
+const SCOPE: &str = "Presence.ReadWrite offline_access";
+
+/// Tokens from the device-code login, kept on disk so the daemon can refresh them.
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+struct TokenCache {
+    access_token: String,
+    refresh_token: String,
+    expires_at: u64,
+}
+
+fn cache_path(teams: &TeamsSettings) -> String {
+    shellexpand::tilde(&teams.token_cache).to_string()
+}
+
+fn token_url(teams: &TeamsSettings) -> String {
+    format!(
+        "https://login.microsoftonline.com/{}/oauth2/v2.0/token",
+        teams.tenant
+    )
+}
+
+fn save_tokens(teams: &TeamsSettings, response: &Value) -> Result<TokenCache> {
+    let tokens = TokenCache {
+        access_token: response["access_token"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("no access_token in {}", response))?
+            .to_string(),
+        refresh_token: response["refresh_token"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("no refresh_token in {}", response))?
+            .to_string(),
+        expires_at: get_unix_timestamp()? + response["expires_in"].as_u64().unwrap_or(3600),
+    };
+    let path = cache_path(teams);
+    if let Some(parent) = std::path::Path::new(&path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(&path, serde_json::to_vec(&tokens)?)?;
+    Ok(tokens)
+}
+
+/// Runs the OAuth device-code flow interactively and caches the tokens (`amibussy teams-login`).
+pub async fn device_code_login(teams: &TeamsSettings) -> Result<()> {
+    let client = Client::new();
+    let device: Value = client
+        .post(format!(
+            "https://login.microsoftonline.com/{}/oauth2/v2.0/devicecode",
+            teams.tenant
+        ))
+        .form(&[("client_id", teams.client_id.as_str()), ("scope", SCOPE)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    println!(
+        "{}",
+        device["message"]
+            .as_str()
+            .ok_or_else(|| anyhow::anyhow!("unexpected device code response: {}", device))?
+    );
+
+    let device_code = device["device_code"].as_str().unwrap_or_default();
+    let mut poll_every = device["interval"].as_u64().unwrap_or(5);
+    loop {
+        tokio::time::sleep(Duration::from_secs(poll_every)).await;
+        let response: Value = client
+            .post(token_url(teams))
+            .form(&[
+                ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+                ("client_id", teams.client_id.as_str()),
+                ("device_code", device_code),
+            ])
+            .send()
+            .await?
+            .json()
+            .await?;
+        match response["error"].as_str() {
+            None => {
+                save_tokens(teams, &response)?;
+                println!("Logged in; tokens saved to {}", cache_path(teams));
+                return Ok(());
+            }
+            Some("authorization_pending") => {}
+            Some("slow_down") => poll_every += 5,
+            Some(error) => anyhow::bail!(
+                "login failed: {} ({})",
+                error,
+                response["error_description"]
+            ),
+        }
+    }
+}
+
+/// A valid access token from the cache, refreshing it when it is about to expire.
+async fn access_token(client: &Client, teams: &TeamsSettings) -> Result<String> {
+    let path = cache_path(teams);
+    let cached: TokenCache = serde_json::from_slice(&std::fs::read(&path).map_err(|err| {
+        anyhow::anyhow!(
+            "no Teams tokens at {} ({}), run `amibussy teams-login`",
+            path,
+            err
+        )
+    })?)?;
+    if cached.expires_at > get_unix_timestamp()? + 60 {
+        return Ok(cached.access_token);
+    }
+
+    let response: Value = client
+        .post(token_url(teams))
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("client_id", teams.client_id.as_str()),
+            ("refresh_token", cached.refresh_token.as_str()),
+            ("scope", SCOPE),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(save_tokens(teams, &response)?.access_token)
+}
+
+async fn set_preferred_presence(
+    client: &Client,
+    teams: &TeamsSettings,
+    presence: &TeamsPresence,
+) -> Result<()> {
+    let token = access_token(client, teams).await?;
+    client
+        .post("https://graph.microsoft.com/v1.0/me/presence/setUserPreferredPresence")
+        .bearer_auth(token)
+        .json(&json!({
+            "availability": presence.availability,
+            "activity": presence.activity,
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Sets the Teams preferred presence for the status being entered.
+pub async fn update_teams(
+    client: &Client,
+    state: &AppState,
+    teams: &TeamsSettings,
+    transition: &Transition,
+) {
+    let presence = match transition.to {
+        Status::Busy | Status::ForgotTimer => &teams.busy,
+        Status::Break => &teams.on_break,
+        Status::NotWorking => &teams.not_working,
+    };
+
+    match set_preferred_presence(client, teams, presence).await {
+        Ok(()) => {
+            info!("Set Teams presence to {}", presence.availability);
+            state.diagnostics.ok(
+                "sink.teams",
+                format!("presence set to {}", presence.availability),
+            );
+        }
+        Err(err) => {
+            error!("Failed to set Teams presence: {}", err);
+            state.diagnostics.error("sink.teams", err.to_string());
+        }
+    }
+}
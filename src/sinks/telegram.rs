@@ -0,0 +1,448 @@
+use crate::{
+    config::{ChatPhotoSettings, Settings},
+    state::{AppState, Status},
+    telegram::{
+        call, call_multipart, sanitize_title, title_len, truncate, ChatMember, Message,
+        TelegramError, User, MAX_DESCRIPTION_LEN, MAX_TITLE_LEN,
+    },
+};
+use anyhow::Result;
+use reqwest::{multipart, Client};
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+};
+use tracing::{error, info, warn};
+
+/// Photo bytes read from disk, plus which status the chat photo currently shows.
+///
+/// `setChatPhoto` only accepts a fresh upload (not a `file_id`), so the best we can do is
+/// keep the files in memory and skip uploads when the photo is already the right one.
+#[derive(Debug, Default)]
+pub struct ChatPhotoCache {
+    images: Mutex<HashMap<String, Arc<Vec<u8>>>>,
+    current: Mutex<Option<String>>,
+}
+
+pub async fn set_chat_title(client: &Client, settings: &Settings, title: &str) -> Result<()> {
+    let title = checked_title(title)?;
+    let payload = json!({
+        "chat_id": settings.chat_id,
+        "title": title
+    });
+    match call::<bool>(client, &settings.bot_token, "setChatTitle", &payload).await {
+        Ok(_) | Err(TelegramError::NotModified { .. }) => {
+            info!("Successfully updated chat title");
+            Ok(())
+        }
+        Err(err) => {
+            error!("Failed to update chat title: {}", err);
+            Err(err.into())
+        }
+    }
+}
+
+/// `title` sanitized for a chat title or topic name, warning if it had to be cut.
+fn checked_title(title: &str) -> Result<String> {
+    let sanitized = sanitize_title(title);
+    if sanitized.is_empty() {
+        anyhow::bail!("the title is empty, nothing to set");
+    }
+    if title_len(title) > MAX_TITLE_LEN {
+        warn!(
+            "Title is {} characters, over Telegram's {}; cutting it to {:?}",
+            title_len(title),
+            MAX_TITLE_LEN,
+            sanitized
+        );
+    }
+    Ok(sanitized)
+}
+
+/// Sets the chat description, cut to Telegram's limit. An empty one clears it.
+pub async fn set_chat_description(
+    client: &Client,
+    settings: &Settings,
+    description: &str,
+) -> Result<()> {
+    let payload = json!({
+        "chat_id": settings.chat_id,
+        "description": truncate(description.trim(), MAX_DESCRIPTION_LEN)
+    });
+    match call::<bool>(client, &settings.bot_token, "setChatDescription", &payload).await {
+        Ok(_) | Err(TelegramError::NotModified { .. }) => {}
+        Err(err) => {
+            error!("Failed to update chat description: {}", err);
+            return Err(err.into());
+        }
+    }
+
+    info!("Successfully updated chat description");
+    Ok(())
+}
+
+/// Renames a forum topic; needs the bot to have the "Manage topics" right.
+pub async fn edit_forum_topic(
+    client: &Client,
+    settings: &Settings,
+    message_thread_id: i64,
+    name: &str,
+) -> Result<()> {
+    let name = checked_title(name)?;
+    let payload = json!({
+        "chat_id": settings.chat_id,
+        "message_thread_id": message_thread_id,
+        "name": name
+    });
+    match call::<bool>(client, &settings.bot_token, "editForumTopic", &payload).await {
+        Ok(_) | Err(TelegramError::NotModified { .. }) => {}
+        Err(err) => {
+            error!("Failed to rename forum topic: {}", err);
+            return Err(err.into());
+        }
+    }
+
+    info!("Successfully renamed forum topic {}", message_thread_id);
+    Ok(())
+}
+
+pub async fn edit_message_text(
+    client: &Client,
+    settings: &Settings,
+    message_id: i64,
+    text: &str,
+) -> Result<()> {
+    let payload = json!({
+        "chat_id": settings.chat_id,
+        "message_id": message_id,
+        "text": text
+    });
+    match call::<Message>(client, &settings.bot_token, "editMessageText", &payload).await {
+        Ok(_) | Err(TelegramError::NotModified { .. }) => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    info!("Successfully edited status message {}", message_id);
+    Ok(())
+}
+
+/// Sends `text` to the configured chat and pins it silently, returning the new message's ID.
+pub async fn send_and_pin_message(client: &Client, settings: &Settings, text: &str) -> Result<i64> {
+    let sent: Message = call(
+        client,
+        &settings.bot_token,
+        "sendMessage",
+        &json!({
+            "chat_id": settings.chat_id,
+            "text": text
+        }),
+    )
+    .await?;
+
+    call::<bool>(
+        client,
+        &settings.bot_token,
+        "pinChatMessage",
+        &json!({
+            "chat_id": settings.chat_id,
+            "message_id": sent.message_id,
+            "disable_notification": true
+        }),
+    )
+    .await?;
+
+    Ok(sent.message_id)
+}
+
+/// Sets (or with `None`, clears) a user's emoji status. Only works for users who allowed the bot to.
+pub async fn set_user_emoji_status(
+    client: &Client,
+    settings: &Settings,
+    user_id: i64,
+    custom_emoji_id: Option<&str>,
+) -> Result<()> {
+    let payload = json!({
+        "user_id": user_id,
+        "emoji_status_custom_emoji_id": custom_emoji_id.unwrap_or_default()
+    });
+    call::<bool>(client, &settings.bot_token, "setUserEmojiStatus", &payload).await?;
+
+    info!("Successfully set emoji status for user {}", user_id);
+    Ok(())
+}
+
+pub async fn send_message(client: &Client, settings: &Settings, chat_id: &str, text: &str) {
+    let payload = json!({
+        "chat_id": chat_id,
+        "text": text
+    });
+
+    match call::<Message>(client, &settings.bot_token, "sendMessage", &payload).await {
+        Ok(_) => info!("Successfully sent message to {}", chat_id),
+        Err(err) => error!("Failed to send message: {}", err),
+    }
+}
+
+/// Sends `text` with an inline keyboard to `chat_id`, returning the new message's ID.
+pub async fn send_message_with_markup(
+    client: &Client,
+    settings: &Settings,
+    chat_id: &str,
+    text: &str,
+    reply_markup: &serde_json::Value,
+) -> Result<i64> {
+    let payload = json!({
+        "chat_id": chat_id,
+        "text": text,
+        "reply_markup": reply_markup
+    });
+    let sent: Message = call(client, &settings.bot_token, "sendMessage", &payload).await?;
+    Ok(sent.message_id)
+}
+
+/// Edits the text and inline keyboard of a message the bot sent.
+pub async fn edit_message_with_markup(
+    client: &Client,
+    settings: &Settings,
+    chat_id: &str,
+    message_id: i64,
+    text: &str,
+    reply_markup: &serde_json::Value,
+) -> Result<()> {
+    let payload = json!({
+        "chat_id": chat_id,
+        "message_id": message_id,
+        "text": text,
+        "reply_markup": reply_markup
+    });
+    match call::<Message>(client, &settings.bot_token, "editMessageText", &payload).await {
+        Ok(_) | Err(TelegramError::NotModified { .. }) => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Stops the button's spinner, showing `text` to whoever pressed it.
+pub async fn answer_callback_query(
+    client: &Client,
+    settings: &Settings,
+    callback_query_id: &str,
+    text: &str,
+) -> Result<()> {
+    let payload = json!({
+        "callback_query_id": callback_query_id,
+        "text": text
+    });
+    call::<bool>(client, &settings.bot_token, "answerCallbackQuery", &payload).await?;
+    Ok(())
+}
+
+/// Replies to `message_id` in `chat_id`, also if that message was deleted meanwhile.
+pub async fn send_reply(
+    client: &Client,
+    settings: &Settings,
+    chat_id: i64,
+    message_id: i64,
+    text: &str,
+) -> Result<()> {
+    let payload = json!({
+        "chat_id": chat_id,
+        "text": text,
+        "reply_parameters": {
+            "message_id": message_id,
+            "allow_sending_without_reply": true
+        }
+    });
+    call::<Message>(client, &settings.bot_token, "sendMessage", &payload).await?;
+    Ok(())
+}
+
+pub async fn set_chat_photo(
+    client: &Client,
+    settings: &Settings,
+    photo: Vec<u8>,
+    file_name: String,
+) -> Result<()> {
+    let form = multipart::Form::new()
+        .text("chat_id", settings.chat_id.clone())
+        .part("photo", multipart::Part::bytes(photo).file_name(file_name));
+    call_multipart::<bool>(client, &settings.bot_token, "setChatPhoto", form).await?;
+
+    info!("Successfully updated chat photo");
+    Ok(())
+}
+
+/// Uploads the photo configured for `status`, unless the chat already shows it.
+pub async fn update_chat_photo(
+    client: &Client,
+    settings: &Settings,
+    photos: &ChatPhotoSettings,
+    cache: &ChatPhotoCache,
+    status: Status,
+) -> Result<()> {
+    let path = match status {
+        Status::Busy | Status::ForgotTimer => &photos.busy,
+        Status::Break => &photos.on_break,
+        Status::NotWorking => &photos.not_working,
+    };
+    let Some(path) = path else {
+        return Ok(());
+    };
+    let path = shellexpand::tilde(path).to_string();
+
+    if cache
+        .current
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .as_deref()
+        == Some(path.as_str())
+    {
+        return Ok(());
+    }
+
+    let cached = cache
+        .images
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .get(&path)
+        .cloned();
+    let photo = match cached {
+        Some(photo) => photo,
+        None => {
+            let photo = Arc::new(tokio::fs::read(&path).await?);
+            cache
+                .images
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .insert(path.clone(), photo.clone());
+            photo
+        }
+    };
+
+    let file_name = std::path::Path::new(&path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "photo.png".to_string());
+    set_chat_photo(client, settings, photo.to_vec(), file_name).await?;
+    *cache.current.lock().unwrap_or_else(PoisonError::into_inner) = Some(path);
+    Ok(())
+}
+
+/// Whether `err` is a Telegram error caused by the bot's membership or rights in the chat.
+pub fn is_permission_problem(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<TelegramError>()
+        .is_some_and(TelegramError::is_permission_problem)
+}
+
+/// What's wrong with the bot's membership or admin rights in the chat for the configured
+/// title sinks, if anything, phrased as what to fix.
+async fn bot_rights_problem(client: &Client, settings: &Settings) -> Result<Option<String>> {
+    let me: User = call(client, &settings.bot_token, "getMe", &json!({})).await?;
+    let member: ChatMember = call(
+        client,
+        &settings.bot_token,
+        "getChatMember",
+        &json!({
+            "chat_id": settings.chat_id,
+            "user_id": me.id
+        }),
+    )
+    .await?;
+
+    let needed = [
+        (
+            "Change group info",
+            "the chat title, photo and description",
+            settings.renames_chat()
+                || settings.chat_photos.is_some()
+                || settings.chat_description.is_some(),
+            member.can_change_info,
+        ),
+        (
+            "Pin messages",
+            "`status_message`",
+            settings.status_message.is_some(),
+            member.can_pin_messages,
+        ),
+        (
+            "Manage topics",
+            "`forum_topic`",
+            settings.forum_topic.is_some(),
+            member.can_manage_topics,
+        ),
+    ];
+    let missing = |all: bool| {
+        needed
+            .iter()
+            .filter(|(_, _, used, granted)| *used && (all || !granted))
+            .map(|(right, used_for, _, _)| format!("\"{}\" (for {})", right, used_for))
+            .collect::<Vec<_>>()
+    };
+
+    Ok(match member.status.as_str() {
+        "creator" => None,
+        "left" | "kicked" => Some(format!(
+            "the bot is not in chat {}; add it back and make it an admin with {}",
+            settings.chat_id,
+            missing(true).join(", ")
+        )),
+        "administrator" => {
+            let missing = missing(false);
+            (!missing.is_empty()).then(|| {
+                format!(
+                    "the bot is missing admin rights in chat {}; grant it {}",
+                    settings.chat_id,
+                    missing.join(", ")
+                )
+            })
+        }
+        _ => Some(format!(
+            "the bot is not an admin of chat {}; make it one with {}",
+            settings.chat_id,
+            missing(true).join(", ")
+        )),
+    })
+}
+
+/// Checks that the bot is in the chat with the admin rights the title sinks need, on startup
+/// and when a call fails over them. A problem is logged, shown as `telegram.rights` in
+/// `amibussy status --verbose`, and DMed to `admin_alerts.chat_id` when it's a new one.
+pub async fn verify_bot_rights(client: &Client, state: &AppState) {
+    let settings = state.settings();
+    let problem = match bot_rights_problem(client, &settings).await {
+        Ok(problem) => problem,
+        Err(err) => {
+            warn!("Couldn't check the bot's rights in the chat: {}", err);
+            state.diagnostics.error("telegram.rights", err.to_string());
+            return;
+        }
+    };
+    let previous = std::mem::replace(
+        &mut *state
+            .bot_rights_problem
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner),
+        problem.clone(),
+    );
+
+    let Some(problem) = problem else {
+        state
+            .diagnostics
+            .ok("telegram.rights", "the bot has the rights it needs");
+        return;
+    };
+    warn!("[PERMISSIONS] {}", problem);
+    state.diagnostics.error("telegram.rights", problem.clone());
+    if previous.as_ref() == Some(&problem) || settings.dry_run {
+        return;
+    }
+    if let Some(alerts) = &settings.admin_alerts {
+        send_message(
+            client,
+            &settings,
+            &alerts.chat_id,
+            &format!("amibussy: {}", problem),
+        )
+        .await;
+    }
+}
@@ -0,0 +1,153 @@
+use crate::{config::WebhookSettings, history::Transition, state::AppState};
+use anyhow::Result;
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Method};
+use sha2::Sha256;
+use std::sync::atomic::Ordering;
+use tracing::{error, info};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Escapes `value` for use inside a JSON string literal.
+fn json_escape(value: &str) -> String {
+    let quoted = serde_json::Value::from(value).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+/// Fills `{status}`, `{from}`, `{title}`, `{cause}`, `{payload_id}`, `{at}` and `{sequence}` in a
+/// body template.
+fn render_body(template: &str, state: &AppState, transition: &Transition, sequence: u64) -> String {
+    template
+        .replace("{status}", transition.to.as_str())
+        .replace("{from}", transition.from.as_str())
+        .replace(
+            "{title}",
+            &json_escape(transition.to.title(&state.settings())),
+        )
+        .replace("{cause}", &json_escape(&transition.cause))
+        .replace(
+            "{payload_id}",
+            &json_escape(transition.payload_id.as_deref().unwrap_or_default()),
+        )
+        .replace("{at}", &transition.at.to_string())
+        .replace("{sequence}", &sequence.to_string())
+}
+
+/// `sha256=` + hex HMAC-SHA256 of `{sequence}.{body}`, like GitHub's `X-Hub-Signature-256`
+/// but covering the sequence too, so it can't be swapped to replay an old body as a new one.
+fn sign(secret: &str, sequence: u64, body: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes any key size");
+    mac.update(format!("{}.{}", sequence, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+async fn call_webhook(
+    client: &Client,
+    state: &AppState,
+    webhook: &WebhookSettings,
+    transition: &Transition,
+    sequence: u64,
+) -> Result<()> {
+    let method = Method::from_bytes(webhook.method.to_uppercase().as_bytes())?;
+    let body = match &webhook.body {
+        Some(template) => render_body(template, state, transition, sequence),
+        None => serde_json::json!({
+            "status": transition.to,
+            "from": transition.from,
+            "title": transition.to.title(&state.settings()),
+            "cause": transition.cause,
+            "payload_id": transition.payload_id,
+            "at": transition.at,
+            "sequence": sequence,
+        })
+        .to_string(),
+    };
+
+    let mut request = client
+        .request(method, &webhook.url)
+        .header("Content-Type", "application/json")
+        .header("X-Amibussy-Sequence", sequence);
+    for (name, value) in &webhook.headers {
+        request = request.header(name, value);
+    }
+    if let Some(secret) = &webhook.secret {
+        request = request.header(&webhook.signature_header, sign(secret, sequence, &body));
+    }
+    request
+        .body(body)
+        .timeout(std::time::Duration::from_secs(10))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// The sequence of the next transition. It starts from the boot time in milliseconds, so it
+/// keeps growing across restarts.
+fn next_sequence(state: &AppState) -> u64 {
+    state.webhook_sequence.fetch_add(1, Ordering::Relaxed) + 1
+}
+
+/// Calls every configured outbound webhook for a transition, all with the same next
+/// `sequence`.
+pub async fn call_webhooks(client: &Client, state: &AppState, transition: &Transition) {
+    let settings = state.settings();
+    if settings.webhooks.is_empty() {
+        return;
+    }
+    let sequence = next_sequence(state);
+    for webhook in &settings.webhooks {
+        match call_webhook(client, state, webhook, transition, sequence).await {
+            Ok(()) => {
+                info!("Called webhook {}", webhook.url);
+                state.diagnostics.ok(
+                    "sink.webhook",
+                    format!("{} called for {:?}", webhook.url, transition.to),
+                );
+            }
+            Err(err) => {
+                error!("Failed to call webhook {}: {}", webhook.url, err);
+                state
+                    .diagnostics
+                    .error("sink.webhook", format!("{}: {}", webhook.url, err));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{clock::ManualClock, config::Settings};
+    use std::sync::Arc;
+
+    const NOW: u64 = 1_700_000_000;
+
+    #[test]
+    fn signs_the_sequence_with_the_body() {
+        let secret = "It's a Secret to Everybody";
+        let body = r#"{"status":"busy"}"#;
+        assert_eq!(
+            sign(secret, 7, body),
+            "sha256=02f4cdba3f631dfe9fb8757564ee8af4ca5b6cf4c76356079b9ce2ecad72df59"
+        );
+        assert_ne!(sign(secret, 8, body), sign(secret, 7, body));
+    }
+
+    #[test]
+    fn sequence_grows_across_restarts() {
+        let boot =
+            |now| AppState::with_clock(Settings::for_tests(""), Arc::new(ManualClock::new(now)));
+        let state = boot(NOW);
+        let first = next_sequence(&state);
+        let second = next_sequence(&state);
+        assert!(second > first);
+
+        // A thousand transitions, then a restart two seconds later: it still picks up above them.
+        for _ in 0..1000 {
+            next_sequence(&state);
+        }
+        let last = next_sequence(&state);
+        assert!(next_sequence(&boot(NOW + 2)) > last);
+    }
+}
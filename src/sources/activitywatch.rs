@@ -0,0 +1,207 @@
+use super::toggl::parse_timestamp;
+use crate::{
+    config::{ActivitySignal, ActivityWatchSettings},
+    presence::{emit, PresenceEvent},
+    state::AppState,
+};
+use anyhow::{anyhow, Result};
+use reqwest::Client;
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// A window event that ended longer ago than this means the watcher isn't running.
+const STALE_SECS: u64 = 120;
+
+/// The active window: its application and title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Window {
+    app: String,
+    title: String,
+}
+
+/// The window watcher's bucket: `bucket` if set, else the first `currentwindow` one.
+async fn window_bucket(client: &Client, activitywatch: &ActivityWatchSettings) -> Result<String> {
+    if let Some(bucket) = &activitywatch.bucket {
+        return Ok(bucket.clone());
+    }
+    let buckets: Value = client
+        .get(format!(
+            "{}/api/0/buckets/",
+            activitywatch.url.trim_end_matches('/')
+        ))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    currentwindow_bucket(&buckets)
+        .ok_or_else(|| anyhow!("no currentwindow bucket, is aw-watcher-window running?"))
+}
+
+/// The first `currentwindow` bucket in a `/api/0/buckets/` response.
+fn currentwindow_bucket(buckets: &Value) -> Option<String> {
+    buckets
+        .as_object()
+        .into_iter()
+        .flatten()
+        .find(|(_, bucket)| bucket["type"] == "currentwindow")
+        .map(|(id, _)| id.clone())
+}
+
+/// The window in the bucket's latest event, unless that's stale.
+async fn active_window(
+    client: &Client,
+    activitywatch: &ActivityWatchSettings,
+    bucket: &str,
+    now: u64,
+) -> Result<Option<Window>> {
+    let events: Value = client
+        .get(format!(
+            "{}/api/0/buckets/{}/events",
+            activitywatch.url.trim_end_matches('/'),
+            bucket
+        ))
+        .query(&[("limit", "1")])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let Some(event) = events.get(0) else {
+        return Ok(None);
+    };
+    let ended = event["timestamp"]
+        .as_str()
+        .and_then(parse_timestamp)
+        .map(|start| start + event["duration"].as_f64().unwrap_or(0.0) as u64)
+        .unwrap_or_default();
+    if now.saturating_sub(ended) > STALE_SECS {
+        return Ok(None);
+    }
+    Ok(Some(Window {
+        app: event["data"]["app"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+        title: event["data"]["title"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    }))
+}
+
+pub async fn activitywatch_poller(
+    state: AppState,
+    activitywatch: ActivityWatchSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(activitywatch.poll_interval_secs));
+    let mut bucket: Option<String> = None;
+    // The app and category last logged.
+    let mut logged: Option<(String, Option<String>)> = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down activitywatch_poller");
+                break;
+            }
+        }
+
+        let window = match &bucket {
+            Some(bucket) => active_window(&client, &activitywatch, bucket, state.clock.now()).await,
+            None => match window_bucket(&client, &activitywatch).await {
+                Ok(found) => {
+                    info!("[ACTIVITY] Watching the ActivityWatch bucket {}", found);
+                    let window =
+                        active_window(&client, &activitywatch, &found, state.clock.now()).await;
+                    bucket = Some(found);
+                    window
+                }
+                Err(err) => Err(err),
+            },
+        };
+        let window = match window {
+            Ok(window) => window,
+            Err(err) => {
+                error!("Failed to query ActivityWatch: {}", err);
+                state
+                    .diagnostics
+                    .error("source.activitywatch", err.to_string());
+                continue;
+            }
+        };
+
+        let category = window.as_ref().and_then(|window| {
+            activitywatch
+                .categories
+                .iter()
+                .find(|category| category.matches(&window.app, &window.title))
+        });
+        let signal = category.map_or(ActivitySignal::Neutral, |category| category.signal);
+        let name = category.map(|category| category.name.clone());
+        state.diagnostics.ok(
+            "source.activitywatch",
+            format!("active category: {}", name.as_deref().unwrap_or("none")),
+        );
+
+        if let Some(window) = &window {
+            let current = (window.app.clone(), name);
+            if logged.as_ref() != Some(&current) {
+                if let Some(history) = &state.history {
+                    let (app, category) = &current;
+                    if let Err(err) =
+                        history.record_activity(state.clock.now(), app, category.as_deref(), signal)
+                    {
+                        error!("Failed to record the active window: {}", err);
+                        state.diagnostics.error("history", err.to_string());
+                    }
+                }
+                logged = Some(current);
+            }
+        }
+
+        emit(&state, PresenceEvent::Activity { signal }, None);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn picks_the_window_watchers_bucket() {
+        let buckets = json!({
+            "aw-watcher-afk_laptop": {
+                "id": "aw-watcher-afk_laptop",
+                "type": "afkstatus",
+                "client": "aw-watcher-afk",
+                "hostname": "laptop"
+            },
+            "aw-watcher-window_laptop": {
+                "id": "aw-watcher-window_laptop",
+                "type": "currentwindow",
+                "client": "aw-watcher-window",
+                "hostname": "laptop"
+            }
+        });
+        assert_eq!(
+            currentwindow_bucket(&buckets).as_deref(),
+            Some("aw-watcher-window_laptop")
+        );
+    }
+
+    #[test]
+    fn no_bucket_without_a_window_watcher() {
+        let buckets = json!({
+            "aw-watcher-afk_laptop": { "type": "afkstatus" }
+        });
+        assert_eq!(currentwindow_bucket(&buckets), None);
+        assert_eq!(currentwindow_bucket(&json!([])), None);
+    }
+}
@@ -0,0 +1,105 @@
+use super::update_meeting_busy;
+use crate::{config::CalDavSettings, presence::MeetingSource, state::AppState};
+use anyhow::Result;
+use reqwest::Client;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// Runs a CalDAV `calendar-query` REPORT for events overlapping the next minute and
+/// reports whether any of them is opaque (busy) and not cancelled.
+async fn caldav_is_busy(client: &Client, caldav: &CalDavSettings) -> Result<bool> {
+    let now = chrono::Utc::now();
+    let format = "%Y%m%dT%H%M%SZ";
+    let body = format!(
+        r#"<?xml version="1.0" encoding="utf-8" ?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop><c:calendar-data/></d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR">
+      <c:comp-filter name="VEVENT">
+        <c:time-range start="{}" end="{}"/>
+      </c:comp-filter>
+    </c:comp-filter>
+  </c:filter>
+</c:calendar-query>"#,
+        now.format(format),
+        (now + chrono::Duration::minutes(1)).format(format)
+    );
+
+    let response = client
+        .request(reqwest::Method::from_bytes(b"REPORT")?, &caldav.url)
+        .basic_auth(&caldav.username, Some(&caldav.password))
+        .header("Depth", "1")
+        .header("Content-Type", "application/xml; charset=utf-8")
+        .body(body)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    Ok(ical_has_busy_event(&response))
+}
+
+/// Scans the iCalendar data embedded in a CalDAV multistatus response for busy VEVENTs.
+fn ical_has_busy_event(response: &str) -> bool {
+    let mut in_event = false;
+    let mut busy = true;
+
+    // Some servers escape the iCalendar CRs as `&#13;` inside the XML.
+    for line in response
+        .lines()
+        .map(|line| line.trim().trim_end_matches("&#13;"))
+    {
+        match line {
+            "BEGIN:VEVENT" => {
+                in_event = true;
+                busy = true;
+            }
+            "END:VEVENT" if in_event => {
+                if busy {
+                    return true;
+                }
+                in_event = false;
+            }
+            "TRANSP:TRANSPARENT" | "STATUS:CANCELLED" if in_event => busy = false,
+            _ => {}
+        }
+    }
+
+    false
+}
+
+pub async fn caldav_poller(
+    state: AppState,
+    caldav: CalDavSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(caldav.poll_interval_secs));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down caldav_poller");
+                break;
+            }
+        }
+
+        let busy = match caldav_is_busy(&client, &caldav).await {
+            Ok(busy) => busy,
+            Err(err) => {
+                error!("Failed to query CalDAV calendar: {}", err);
+                state.diagnostics.error("source.caldav", err.to_string());
+                continue;
+            }
+        };
+        state
+            .diagnostics
+            .ok("source.caldav", format!("busy: {}", busy));
+
+        update_meeting_busy(&state, MeetingSource::Caldav, busy);
+    }
+}
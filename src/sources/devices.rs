@@ -0,0 +1,103 @@
+use super::update_meeting_busy;
+use crate::{config::DeviceUsageSettings, presence::MeetingSource, state::AppState};
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::info;
+
+/// Whether a webcam or microphone is in use right now, if the platform can tell us.
+fn devices_in_use(settings: &DeviceUsageSettings) -> Option<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        Some(
+            (settings.camera && linux::camera_in_use())
+                || (settings.microphone && linux::microphone_in_use()),
+        )
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = settings;
+        None
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::fs;
+
+    /// Any process we can see holding a `/dev/video*` node open. Processes of other users
+    /// are invisible without root, which is fine for a desktop session.
+    pub fn camera_in_use() -> bool {
+        let Ok(processes) = fs::read_dir("/proc") else {
+            return false;
+        };
+        processes
+            .flatten()
+            .filter(|process| {
+                process
+                    .file_name()
+                    .to_str()
+                    .is_some_and(|name| name.bytes().all(|byte| byte.is_ascii_digit()))
+            })
+            .filter_map(|process| fs::read_dir(process.path().join("fd")).ok())
+            .flat_map(|fds| fds.flatten())
+            .filter_map(|fd| fs::read_link(fd.path()).ok())
+            .any(|target| target.to_string_lossy().starts_with("/dev/video"))
+    }
+
+    /// Any ALSA capture substream running, which PipeWire and PulseAudio also go through.
+    pub fn microphone_in_use() -> bool {
+        let Ok(cards) = fs::read_dir("/proc/asound") else {
+            return false;
+        };
+        cards
+            .flatten()
+            .filter(|card| card.file_name().to_string_lossy().starts_with("card"))
+            .filter_map(|card| fs::read_dir(card.path()).ok())
+            .flat_map(|pcms| pcms.flatten())
+            .filter(|pcm| {
+                let name = pcm.file_name().to_string_lossy().to_string();
+                name.starts_with("pcm") && name.ends_with('c')
+            })
+            .filter_map(|pcm| fs::read_dir(pcm.path()).ok())
+            .flat_map(|subs| subs.flatten())
+            .filter_map(|sub| fs::read_to_string(sub.path().join("status")).ok())
+            .any(|status| status.contains("state: RUNNING"))
+    }
+}
+
+/// Treats an active webcam or microphone as being in a meeting.
+pub async fn device_usage_poller(
+    state: AppState,
+    settings: DeviceUsageSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let mut interval = interval(Duration::from_secs(settings.poll_interval_secs));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down device_usage_poller");
+                break;
+            }
+        }
+
+        let check_settings = settings.clone();
+        let in_use = tokio::task::spawn_blocking(move || devices_in_use(&check_settings))
+            .await
+            .ok()
+            .flatten();
+        let Some(busy) = in_use else {
+            state.diagnostics.error(
+                "source.devices",
+                "camera/microphone detection is not supported on this platform",
+            );
+            break;
+        };
+        state
+            .diagnostics
+            .ok("source.devices", format!("in use: {}", busy));
+        update_meeting_busy(&state, MeetingSource::Devices, busy);
+    }
+}
@@ -0,0 +1,131 @@
+use super::update_meeting_busy;
+use crate::{config::GoogleCalendarSettings, presence::MeetingSource, state::AppState};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+#[derive(serde::Deserialize)]
+struct GoogleTokenResponse {
+    access_token: String,
+    expires_in: u64,
+}
+
+#[derive(serde::Deserialize)]
+struct FreeBusyResponse {
+    calendars: std::collections::HashMap<String, FreeBusyCalendar>,
+}
+
+#[derive(serde::Deserialize)]
+struct FreeBusyCalendar {
+    #[serde(default)]
+    busy: Vec<Value>,
+}
+
+async fn google_access_token(
+    client: &Client,
+    calendar: &GoogleCalendarSettings,
+) -> Result<GoogleTokenResponse> {
+    let token = client
+        .post("https://oauth2.googleapis.com/token")
+        .form(&[
+            ("client_id", calendar.client_id.as_str()),
+            ("client_secret", calendar.client_secret.as_str()),
+            ("refresh_token", calendar.refresh_token.as_str()),
+            ("grant_type", "refresh_token"),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(token)
+}
+
+/// Asks the Google Calendar free/busy API whether there's an ongoing busy event.
+async fn google_calendar_is_busy(
+    client: &Client,
+    calendar: &GoogleCalendarSettings,
+    access_token: &str,
+) -> Result<bool> {
+    let now = chrono::Utc::now();
+    let request = json!({
+        "timeMin": now.to_rfc3339(),
+        "timeMax": (now + chrono::Duration::minutes(1)).to_rfc3339(),
+        "items": [{ "id": calendar.calendar_id }],
+    });
+
+    let response: FreeBusyResponse = client
+        .post("https://www.googleapis.com/calendar/v3/freeBusy")
+        .bearer_auth(access_token)
+        .json(&request)
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    Ok(response
+        .calendars
+        .get(&calendar.calendar_id)
+        .map(|cal| !cal.busy.is_empty())
+        .unwrap_or(false))
+}
+
+pub async fn google_calendar_poller(
+    state: AppState,
+    calendar: GoogleCalendarSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(calendar.poll_interval_secs));
+    let mut access_token: Option<(String, u64)> = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down google_calendar_poller");
+                break;
+            }
+        }
+
+        let current_time = state.clock.now();
+        let token = match &access_token {
+            Some((token, expires_at)) if *expires_at > current_time + 60 => token.clone(),
+            _ => match google_access_token(&client, &calendar).await {
+                Ok(token) => {
+                    access_token =
+                        Some((token.access_token.clone(), current_time + token.expires_in));
+                    token.access_token
+                }
+                Err(err) => {
+                    error!("Failed to refresh Google OAuth token: {}", err);
+                    state.diagnostics.error(
+                        "source.google_calendar",
+                        format!("token refresh failed: {}", err),
+                    );
+                    continue;
+                }
+            },
+        };
+
+        let busy = match google_calendar_is_busy(&client, &calendar, &token).await {
+            Ok(busy) => busy,
+            Err(err) => {
+                error!("Failed to fetch Google Calendar free/busy: {}", err);
+                state
+                    .diagnostics
+                    .error("source.google_calendar", err.to_string());
+                continue;
+            }
+        };
+        state
+            .diagnostics
+            .ok("source.google_calendar", format!("busy: {}", busy));
+
+        update_meeting_busy(&state, MeetingSource::GoogleCalendar, busy);
+    }
+}
@@ -0,0 +1,80 @@
+/// Seconds since the last keyboard/mouse input on this machine, if the platform can tell us.
+pub async fn system_idle_seconds() -> Option<u64> {
+    #[cfg(target_os = "linux")]
+    {
+        // X11 first, then GNOME/Mutter's idle monitor for Wayland sessions.
+        if let Some(ms) = command_output("xprintidle", &[])
+            .await
+            .and_then(|out| out.trim().parse::<u64>().ok())
+        {
+            return Some(ms / 1000);
+        }
+
+        let out = command_output(
+            "gdbus",
+            &[
+                "call",
+                "--session",
+                "--dest",
+                "org.gnome.Mutter.IdleMonitor",
+                "--object-path",
+                "/org/gnome/Mutter/IdleMonitor/Core",
+                "--method",
+                "org.gnome.Mutter.IdleMonitor.GetIdletime",
+            ],
+        )
+        .await?;
+        // Output looks like "(uint64 12345,)"
+        let ms = out
+            .trim()
+            .trim_start_matches("(uint64 ")
+            .trim_end_matches(",)")
+            .parse::<u64>()
+            .ok()?;
+        Some(ms / 1000)
+    }
+
+    #[cfg(target_os = "macos")]
+    {
+        let out = command_output("ioreg", &["-c", "IOHIDSystem", "-d", "4"]).await?;
+        let line = out.lines().find(|line| line.contains("\"HIDIdleTime\""))?;
+        let ns = line.rsplit('=').next()?.trim().parse::<u64>().ok()?;
+        Some(ns / 1_000_000_000)
+    }
+
+    #[cfg(windows)]
+    {
+        use windows_sys::Win32::{
+            System::SystemInformation::GetTickCount,
+            UI::Input::KeyboardAndMouse::{GetLastInputInfo, LASTINPUTINFO},
+        };
+
+        let mut info = LASTINPUTINFO {
+            cbSize: std::mem::size_of::<LASTINPUTINFO>() as u32,
+            dwTime: 0,
+        };
+        if unsafe { GetLastInputInfo(&mut info) } == 0 {
+            return None;
+        }
+        let ms = unsafe { GetTickCount() }.wrapping_sub(info.dwTime);
+        Some(u64::from(ms) / 1000)
+    }
+
+    #[cfg(not(any(target_os = "linux", target_os = "macos", windows)))]
+    {
+        None
+    }
+}
+
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+async fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = tokio::process::Command::new(program)
+        .args(args)
+        .output()
+        .await
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    String::from_utf8(output.stdout).ok()
+}
@@ -0,0 +1,172 @@
+use crate::{
+    config::{TimewarriorSettings, WatsonSettings},
+    presence::{emit, PresenceEvent, Tracker, TrackerTimerEvent},
+    state::{AppState, CurrentEntry},
+};
+use anyhow::{anyhow, Result};
+use serde_json::Value;
+use std::{future::Future, sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// A local tracker's running timer: when it started and what it's for.
+type Running = (u64, CurrentEntry);
+
+/// Tags as strings, from a JSON array of them.
+fn tags(value: &Value) -> Vec<String> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|tag| tag.as_str().map(str::to_string))
+        .collect()
+}
+
+async fn timew(settings: &TimewarriorSettings, reference: &str) -> Result<String> {
+    let output = tokio::process::Command::new(&settings.command)
+        .args(["get", reference])
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "{} get {} failed: {}",
+            settings.command,
+            reference,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8(output.stdout)?)
+}
+
+/// Timewarrior's active interval, if any; its tags make the description.
+async fn timewarrior_timer(settings: &TimewarriorSettings) -> Result<Option<Running>> {
+    if timew(settings, "dom.active").await?.trim() != "1" {
+        return Ok(None);
+    }
+    let active: Value = serde_json::from_str(&timew(settings, "dom.active.json").await?)?;
+    let start = active["start"]
+        .as_str()
+        .and_then(|start| chrono::NaiveDateTime::parse_from_str(start, "%Y%m%dT%H%M%SZ").ok())
+        .and_then(|start| u64::try_from(start.and_utc().timestamp()).ok())
+        .ok_or_else(|| anyhow!("no start in the active interval: {}", active))?;
+    let tags = tags(&active["tags"]);
+    let entry = CurrentEntry {
+        description: Some(tags.join(" ")).filter(|description| !description.is_empty()),
+        tags,
+        ..CurrentEntry::default()
+    };
+    Ok(Some((start, entry)))
+}
+
+/// Watson's running frame, if any; its project makes the description. The state file is `{}`,
+/// or missing, while nothing runs.
+async fn watson_timer(settings: &WatsonSettings) -> Result<Option<Running>> {
+    let path = shellexpand::tilde(&settings.state_file).to_string();
+    let contents = match tokio::fs::read_to_string(&path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let frame: Value = serde_json::from_str(&contents)?;
+    let Some(start) = frame["start"].as_f64() else {
+        return Ok(None);
+    };
+    let entry = CurrentEntry {
+        description: frame["project"].as_str().map(str::to_string),
+        tags: tags(&frame["tags"]),
+        ..CurrentEntry::default()
+    };
+    Ok(Some((start as u64, entry)))
+}
+
+/// Reads a local tracker every `poll_interval_secs` and emits a start or stop whenever its
+/// timer changed since the last read. A timer already stopped at the first read reports
+/// nothing, so another tracker's timer isn't stopped on boot.
+async fn poll_tracker<F, Fut>(
+    state: AppState,
+    tracker: Tracker,
+    poll_interval_secs: u64,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+    read: F,
+) where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<Option<Running>>>,
+{
+    let subsystem = format!("source.{}", tracker.label().to_lowercase());
+    let mut interval = interval(Duration::from_secs(poll_interval_secs));
+    let mut last_started_at = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down the {} poller", tracker.label());
+                break;
+            }
+        }
+
+        let running = match read().await {
+            Ok(running) => running,
+            Err(err) => {
+                error!("Failed to read the {} timer: {}", tracker.label(), err);
+                state.diagnostics.error(&subsystem, err.to_string());
+                continue;
+            }
+        };
+        let started_at = running.as_ref().map(|(started_at, _)| *started_at);
+        state.diagnostics.ok(
+            &subsystem,
+            match started_at {
+                Some(started_at) => format!("running since {}", started_at),
+                None => "stopped".to_string(),
+            },
+        );
+        let first_read = last_started_at.is_none();
+        if last_started_at.replace(started_at) == Some(started_at)
+            || (first_read && started_at.is_none())
+        {
+            continue;
+        }
+
+        let (started_at, entry) = match running {
+            Some((started_at, entry)) => (Some(started_at), entry),
+            None => (None, CurrentEntry::default()),
+        };
+        let event = TrackerTimerEvent {
+            tracker,
+            started_at,
+            entry,
+        };
+        emit(&state, PresenceEvent::TrackerTimer(event), None);
+    }
+}
+
+pub async fn timewarrior_poller(
+    state: AppState,
+    timew: TimewarriorSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    poll_tracker(
+        state,
+        Tracker::Timewarrior,
+        timew.poll_interval_secs,
+        shutdown_signal,
+        || timewarrior_timer(&timew),
+    )
+    .await;
+}
+
+pub async fn watson_poller(
+    state: AppState,
+    watson: WatsonSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    poll_tracker(
+        state,
+        Tracker::Watson,
+        watson.poll_interval_secs,
+        shutdown_signal,
+        || watson_timer(&watson),
+    )
+    .await;
+}
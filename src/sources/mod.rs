@@ -0,0 +1,22 @@
+pub mod activitywatch;
+pub mod caldav;
+pub mod devices;
+pub mod google_calendar;
+pub mod idle;
+pub mod local_trackers;
+pub mod slack;
+pub mod tempo;
+pub mod toggl;
+pub mod toggl_subscription;
+pub mod wakatime;
+pub mod zoom;
+
+use crate::{
+    presence::{emit, MeetingSource, PresenceEvent},
+    state::AppState,
+};
+
+/// Reports a meeting source's busy flag; the title is republished if that changes the status.
+pub(crate) fn update_meeting_busy(state: &AppState, source: MeetingSource, busy: bool) {
+    emit(state, PresenceEvent::Meeting { source, busy }, None);
+}
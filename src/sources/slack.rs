@@ -0,0 +1,62 @@
+use super::update_meeting_busy;
+use crate::{config::SlackHuddleSettings, presence::MeetingSource, state::AppState};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::Value;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info};
+
+/// Reads `huddle_state` from the user's Slack profile; it is `in_a_huddle` while in one.
+async fn in_huddle(client: &Client, slack: &SlackHuddleSettings) -> Result<bool> {
+    let response: Value = client
+        .get("https://slack.com/api/users.profile.get")
+        .bearer_auth(&slack.token)
+        .query(&[("user", slack.user_id.as_str())])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    if response["ok"] != Value::Bool(true) {
+        anyhow::bail!("Slack API error: {}", response["error"]);
+    }
+    Ok(response["profile"]["huddle_state"].as_str() == Some("in_a_huddle"))
+}
+
+pub async fn slack_huddle_poller(
+    state: AppState,
+    slack: SlackHuddleSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(slack.poll_interval_secs));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down slack_huddle_poller");
+                break;
+            }
+        }
+
+        let busy = match in_huddle(&client, &slack).await {
+            Ok(busy) => {
+                state
+                    .diagnostics
+                    .ok("source.slack_huddle", format!("in a huddle: {}", busy));
+                busy
+            }
+            Err(err) => {
+                error!("Failed to check Slack huddle state: {}", err);
+                state
+                    .diagnostics
+                    .error("source.slack_huddle", err.to_string());
+                continue;
+            }
+        };
+
+        update_meeting_busy(&state, MeetingSource::SlackHuddle, busy);
+    }
+}
@@ -0,0 +1,88 @@
+use super::toggl::parse_timestamp;
+use crate::{
+    limits::constant_time_eq,
+    presence::{emit, PresenceEvent, Tracker, TrackerTimerEvent},
+    state::{AppState, CurrentEntry},
+};
+use axum::{
+    extract::{Json, Query, State},
+    response::{IntoResponse, Response},
+};
+use hyper::StatusCode;
+use tracing::{info, warn};
+
+#[derive(Debug, serde::Deserialize)]
+pub struct TempoQuery {
+    #[serde(default)]
+    token: Option<String>,
+}
+
+/// What a Jira Automation rule (or anything else that knows when the Tempo timer runs)
+/// sends to `/tempo`.
+#[derive(Debug, serde::Deserialize)]
+pub struct TempoEvent {
+    /// `start` or `stop`.
+    action: String,
+    /// The issue key, e.g. `PROJ-123`.
+    #[serde(default)]
+    issue: Option<String>,
+    #[serde(default)]
+    description: Option<String>,
+    /// When the timer started, RFC 3339; defaults to now.
+    #[serde(default)]
+    started_at: Option<String>,
+}
+
+/// `POST /tempo?token=...`: a Tempo timer started or stopped.
+pub async fn tempo_webhook(
+    State(state): State<AppState>,
+    Query(query): Query<TempoQuery>,
+    Json(event): Json<TempoEvent>,
+) -> Response {
+    let Some(tempo) = &state.settings().tempo else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !constant_time_eq(
+        query.token.as_deref().unwrap_or_default().as_bytes(),
+        tempo.token.as_bytes(),
+    ) {
+        warn!("Rejected a Tempo webhook with a missing or wrong token");
+        state
+            .diagnostics
+            .error("source.tempo", "rejected a request with a wrong token");
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    info!("GOT TEMPO EVENT: {} {:?}", event.action, event.issue);
+    let started_at = match event.action.as_str() {
+        "start" => Some(
+            event
+                .started_at
+                .as_deref()
+                .and_then(parse_timestamp)
+                .unwrap_or_else(|| state.clock.now()),
+        ),
+        "stop" => None,
+        _ => return (StatusCode::BAD_REQUEST, "action has to be start or stop").into_response(),
+    };
+    // Shown like a Toggl entry's description: `PROJ-123 Fix the login`.
+    let description = [event.issue, event.description]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(" ");
+    let entry = CurrentEntry {
+        description: Some(description).filter(|description| !description.is_empty()),
+        ..CurrentEntry::default()
+    };
+    state
+        .diagnostics
+        .ok("source.tempo", format!("last event {}", event.action));
+    let event = TrackerTimerEvent {
+        tracker: Tracker::Tempo,
+        started_at,
+        entry,
+    };
+    emit(&state, PresenceEvent::TrackerTimer(event), None);
+    StatusCode::OK.into_response()
+}
@@ -0,0 +1,337 @@
+use crate::{
+    presence::{emit, PresenceEvent, TogglEntryEvent},
+    replay::record_event,
+    state::{publish_if_changed, publish_title, AppState, CurrentEntry},
+};
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Json, State},
+    response::{Html, IntoResponse, Response},
+};
+use hyper::StatusCode;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::{
+    sync::{atomic::Ordering, OnceLock, PoisonError},
+    time::Duration,
+};
+use tracing::{error, info, warn};
+
+pub async fn webhook_post(State(state): State<AppState>, body: Bytes) -> Response {
+    let request_body: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("Error parsing request body: {}", err);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    info!("GOT POST REQUEST FROM TOGGL TRACK: {}", request_body);
+    if let Some(path) = &state.settings().record_events {
+        record_event(path, &request_body);
+    }
+
+    let event_id = request_body.get("event_id");
+    let event_payload = request_body.get("payload");
+
+    if let Some(event_id) = event_id {
+        state
+            .diagnostics
+            .ok("source.toggl", format!("last event {}", event_id));
+    }
+
+    if event_id.is_none() || event_payload.is_none() {
+        error!(
+            "Unknown event received. Breaking change in TogglTrack API? {:?}",
+            request_body
+        );
+        return StatusCode::UNPROCESSABLE_ENTITY.into_response();
+    }
+
+    if let Some(Value::String(s)) = event_payload {
+        if s == "ping" {
+            info!("Processing ping request validation...");
+            if let Some(validation_code) =
+                request_body.get("validation_code").and_then(|v| v.as_str())
+            {
+                let response_body = json!({ "validation_code": validation_code });
+                return (StatusCode::OK, Json(response_body)).into_response();
+            } else {
+                error!("Validation code missing in PING event");
+                return StatusCode::BAD_REQUEST.into_response();
+            }
+        }
+    }
+
+    if let Some(Value::Object(entry)) = event_payload {
+        let user_id = entry.get("user_id").and_then(|v| v.as_i64());
+        let Some(state) = state.for_toggl_user(user_id) else {
+            info!(
+                "Ignoring an event of Toggl user {:?}, who isn't on the team",
+                user_id
+            );
+            return StatusCode::OK.into_response();
+        };
+        let state = state.for_workspace(entry.get("workspace_id").and_then(|v| v.as_i64()));
+        let action = request_body
+            .get("metadata")
+            .and_then(|metadata| metadata.get("action"))
+            .and_then(|action| action.as_str())
+            .unwrap_or("updated");
+        let entry_id = entry.get("id").and_then(|v| v.as_i64());
+        let mut stop = entry
+            .get("stop")
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        // `duration` is negative while the entry is running.
+        let stopped = stop.is_some()
+            || entry
+                .get("duration")
+                .and_then(|v| v.as_i64())
+                .is_some_and(|duration| duration >= 0);
+        if action != "deleted" && stopped && stop.as_deref().and_then(parse_timestamp).is_none() {
+            // Stopped without a readable stop time: asked of the API before the event goes out,
+            // so it's still processed in the order the webhooks arrived.
+            if let Some(fetched) = fetch_stop(&state, entry_id).await {
+                stop = Some(fetched);
+            }
+        }
+        let event = TogglEntryEvent {
+            action: action.to_string(),
+            id: entry_id,
+            start: entry
+                .get("start")
+                .and_then(|v| v.as_str())
+                .map(str::to_string),
+            stop,
+            stopped,
+            entry: current_entry(entry_id, entry),
+        };
+        emit(
+            &state,
+            PresenceEvent::TogglEntry(event),
+            event_id.map(|id| id.to_string()).as_deref(),
+        );
+    }
+
+    StatusCode::OK.into_response()
+}
+
+fn current_entry(id: Option<i64>, entry: &serde_json::Map<String, Value>) -> CurrentEntry {
+    CurrentEntry {
+        id,
+        description: entry
+            .get("description")
+            .and_then(|v| v.as_str())
+            .map(str::to_string),
+        tags: entry
+            .get("tags")
+            .and_then(|v| v.as_array())
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.as_str().map(str::to_string))
+                    .collect()
+            })
+            .unwrap_or_default(),
+    }
+}
+
+pub async fn webhook_get() -> Html<&'static str> {
+    Html("<h4>Ok</h4>")
+}
+
+/// A time entry as returned by the Toggl Track API v9.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct TimeEntry {
+    pub id: i64,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub start: String,
+    #[serde(default)]
+    pub stop: Option<String>,
+    /// Seconds; negative while the entry is running.
+    pub duration: i64,
+    #[serde(default)]
+    pub tags: Option<Vec<String>>,
+    #[serde(default)]
+    pub workspace_id: Option<i64>,
+}
+
+/// Whether `entry` is one for the status of `state`, by the `workspace_chats` routing. Entries
+/// without a workspace are always yours.
+fn covers(state: &AppState, entry: &TimeEntry) -> bool {
+    entry
+        .workspace_id
+        .is_none_or(|workspace_id| state.settings().covers_workspace(workspace_id))
+}
+
+/// Fetches your own time entries started in `[start, end)` (RFC 3339 timestamps).
+pub async fn fetch_time_entries(
+    client: &Client,
+    api_token: &str,
+    start: &str,
+    end: &str,
+) -> Result<Vec<TimeEntry>> {
+    let entries = client
+        .get("https://api.track.toggl.com/api/v9/me/time_entries")
+        .basic_auth(api_token, Some("api_token"))
+        .query(&[("start_date", start), ("end_date", end)])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(entries)
+}
+
+/// Fetches the running time entry, if there is one.
+pub async fn fetch_current_entry(client: &Client, api_token: &str) -> Result<Option<TimeEntry>> {
+    let entry = client
+        .get("https://api.track.toggl.com/api/v9/me/time_entries/current")
+        .basic_auth(api_token, Some("api_token"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(entry)
+}
+
+/// Fetches one of your time entries.
+pub async fn fetch_time_entry(client: &Client, api_token: &str, id: i64) -> Result<TimeEntry> {
+    let entry = client
+        .get(format!(
+            "https://api.track.toggl.com/api/v9/me/time_entries/{}",
+            id
+        ))
+        .basic_auth(api_token, Some("api_token"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(entry)
+}
+
+/// How long the webhook's lookups of the Toggl API may take.
+const API_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The client for the webhook's lookups, shared so they reuse its connections.
+fn api_client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .timeout(API_TIMEOUT)
+            .build()
+            .unwrap_or_default()
+    })
+}
+
+/// The stop time of entry `id` as the Track API v9 has it (`/me/time_entries/{id}`), for
+/// payloads without one. Bounded by `API_TIMEOUT`, as the webhook waits for it.
+async fn fetch_stop(state: &AppState, id: Option<i64>) -> Option<String> {
+    let api_token = state.settings().toggl_api_token.clone()?;
+    let id = id?;
+    match fetch_time_entry(api_client(), &api_token, id).await {
+        Ok(entry) => entry.stop,
+        Err(err) => {
+            warn!("Failed to fetch the stop time of entry {}: {}", id, err);
+            None
+        }
+    }
+}
+
+/// Parses a Toggl RFC 3339 timestamp into UNIX seconds.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Option<u64> {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .ok()
+        .and_then(|time| u64::try_from(time.timestamp()).ok())
+}
+
+/// Seeds the timer state from Toggl: Busy since the running entry's start, or, going by when
+/// the last entry stopped, on a break or AFK.
+async fn seed_from_toggl(client: &Client, state: &AppState, api_token: &str) -> Result<()> {
+    let now = state.clock.now();
+    if let Some(entry) = fetch_current_entry(client, api_token)
+        .await?
+        .filter(|entry| covers(state, entry))
+    {
+        info!("[RECONCILE] Timer running since {}", entry.start);
+        let started_at = parse_timestamp(&entry.start).unwrap_or(now);
+        state.timer_started_at.store(started_at, Ordering::Relaxed);
+        *state
+            .current_entry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(CurrentEntry {
+            id: Some(entry.id),
+            description: entry.description,
+            tags: entry.tags.unwrap_or_default(),
+        });
+        return Ok(());
+    }
+
+    let to_rfc3339 = |timestamp: u64| {
+        chrono::DateTime::from_timestamp(timestamp as i64, 0)
+            .unwrap_or_default()
+            .to_rfc3339()
+    };
+    let entries = fetch_time_entries(
+        client,
+        api_token,
+        &to_rfc3339(now - 24 * 3600),
+        &to_rfc3339(now + 1),
+    )
+    .await?;
+    let last_stop = entries
+        .iter()
+        .filter(|entry| covers(state, entry))
+        .filter_map(|entry| entry.stop.as_deref().and_then(parse_timestamp))
+        .max();
+    let afk_after = state.settings().afk_after_secs();
+    if let Some(stopped_at) = last_stop {
+        state.timer_stopped_at.store(stopped_at, Ordering::Relaxed);
+    }
+    match last_stop {
+        Some(stopped_at) if now < stopped_at + afk_after => {
+            info!(
+                "[RECONCILE] On a break since the timer stopped at {}",
+                stopped_at
+            );
+            state.last_break_start.store(stopped_at, Ordering::Relaxed);
+        }
+        Some(stopped_at) => {
+            info!("[RECONCILE] AFK, the timer stopped at {}", stopped_at);
+            state
+                .afk_since
+                .store(stopped_at + afk_after, Ordering::Relaxed);
+        }
+        None => info!("[RECONCILE] No time tracked in the last 24 hours"),
+    }
+    Ok(())
+}
+
+/// On boot: seeds the state from Toggl and sets the title to match, rather than starting out
+/// unaware of a running timer and leaving whatever was published last (or `offline_title`)
+/// until the next webhook.
+pub async fn reconcile_on_boot(state: AppState) {
+    let Some(api_token) = state.settings().toggl_api_token.clone() else {
+        info!("[RECONCILE] No toggl_api_token, the title updates with the next Toggl event");
+        return;
+    };
+    let client = Client::new();
+    let previous = state.decide().status;
+    if let Err(err) = seed_from_toggl(&client, &state, &api_token).await {
+        error!("[RECONCILE] Failed to fetch the timer state: {}", err);
+        state
+            .diagnostics
+            .error("source.toggl", format!("reconcile failed: {}", err));
+        return;
+    }
+    if state.decide().status == previous {
+        // Nothing changed here, but the chat may still show something else from before.
+        publish_title(&client, &state).await;
+    } else {
+        publish_if_changed(&client, &state, previous, "reconcile", None).await;
+    }
+}
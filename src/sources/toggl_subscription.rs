@@ -0,0 +1,385 @@
+use crate::{
+    config::{Settings, TogglSubscriptionSettings},
+    sources::toggl::reconcile_on_boot,
+    state::AppState,
+};
+use anyhow::Result;
+use reqwest::Client;
+use serde_json::json;
+use std::{sync::Arc, time::Duration};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+const WEBHOOKS_API: &str = "https://api.track.toggl.com/webhooks/api/v1";
+
+/// A webhook subscription as returned by the Toggl Webhooks API.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Subscription {
+    pub subscription_id: i64,
+    pub url_callback: String,
+    pub enabled: bool,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub event_filters: Vec<EventFilter>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct EventFilter {
+    pub entity: String,
+    pub action: String,
+}
+
+impl Subscription {
+    /// Whether it still delivers every time entry event, which someone may have changed in
+    /// the Toggl UI.
+    fn covers_time_entries(&self) -> bool {
+        self.event_filters
+            .iter()
+            .any(|filter| filter.entity == "time_entry" && filter.action == "*")
+    }
+}
+
+fn time_entry_filters() -> Vec<EventFilter> {
+    vec![EventFilter {
+        entity: "time_entry".to_string(),
+        action: "*".to_string(),
+    }]
+}
+
+/// Where Toggl should deliver the webhooks for these settings.
+pub fn callback_url(settings: &Settings) -> String {
+    let token = settings
+        .webhook_auth
+        .as_ref()
+        .and_then(|auth| auth.path_token.as_deref());
+    match token {
+        Some(token) => format!("https://{}/webhook/{}", settings.public_domain(), token),
+        None => format!("https://{}/webhook", settings.public_domain()),
+    }
+}
+
+/// `subscription.workspace_id`, or your default workspace.
+async fn workspace_id(
+    client: &Client,
+    api_token: &str,
+    subscription: &TogglSubscriptionSettings,
+) -> Result<i64> {
+    if let Some(workspace_id) = subscription.workspace_id {
+        return Ok(workspace_id);
+    }
+    #[derive(serde::Deserialize)]
+    struct Me {
+        default_workspace_id: i64,
+    }
+    let me: Me = client
+        .get("https://api.track.toggl.com/api/v9/me")
+        .basic_auth(api_token, Some("api_token"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    Ok(me.default_workspace_id)
+}
+
+pub async fn fetch_subscriptions(
+    client: &Client,
+    api_token: &str,
+    workspace_id: i64,
+) -> Result<Vec<Subscription>> {
+    let subscriptions = client
+        .get(format!("{}/subscriptions/{}", WEBHOOKS_API, workspace_id))
+        .basic_auth(api_token, Some("api_token"))
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<Option<Vec<Subscription>>>()
+        .await?;
+    Ok(subscriptions.unwrap_or_default())
+}
+
+async fn set_subscription_enabled(
+    client: &Client,
+    api_token: &str,
+    workspace_id: i64,
+    subscription_id: i64,
+    enabled: bool,
+) -> Result<()> {
+    client
+        .patch(format!(
+            "{}/subscriptions/{}/{}",
+            WEBHOOKS_API, workspace_id, subscription_id
+        ))
+        .basic_auth(api_token, Some("api_token"))
+        .json(&json!({ "enabled": enabled }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// Replaces the subscription's callback URL, filters and description, and enables it.
+async fn update_subscription(
+    client: &Client,
+    api_token: &str,
+    workspace_id: i64,
+    subscription_id: i64,
+    url_callback: &str,
+    description: &str,
+) -> Result<()> {
+    client
+        .put(format!(
+            "{}/subscriptions/{}/{}",
+            WEBHOOKS_API, workspace_id, subscription_id
+        ))
+        .basic_auth(api_token, Some("api_token"))
+        .json(&json!({
+            "url_callback": url_callback,
+            "event_filters": time_entry_filters(),
+            "enabled": true,
+            "description": description,
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn delete_subscription(
+    client: &Client,
+    api_token: &str,
+    workspace_id: i64,
+    subscription_id: i64,
+) -> Result<()> {
+    client
+        .delete(format!(
+            "{}/subscriptions/{}/{}",
+            WEBHOOKS_API, workspace_id, subscription_id
+        ))
+        .basic_auth(api_token, Some("api_token"))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+async fn create_subscription(
+    client: &Client,
+    api_token: &str,
+    workspace_id: i64,
+    url_callback: &str,
+    description: &str,
+) -> Result<()> {
+    client
+        .post(format!("{}/subscriptions/{}", WEBHOOKS_API, workspace_id))
+        .basic_auth(api_token, Some("api_token"))
+        .json(&json!({
+            "url_callback": url_callback,
+            "event_filters": time_entry_filters(),
+            "enabled": true,
+            "description": description,
+        }))
+        .send()
+        .await?
+        .error_for_status()?;
+    Ok(())
+}
+
+/// What it takes to get from the workspace's subscriptions to exactly one enabled
+/// subscription for time entries that delivers to `url`.
+#[derive(Debug, PartialEq, Eq)]
+enum Repair {
+    Enable(i64),
+    /// Its filters were changed, or it's ours but still delivers to an old URL, e.g. from
+    /// before the tunnel's domain changed.
+    Update(i64),
+    /// A duplicate delivering to `url`, or another one of ours delivering somewhere else.
+    Delete(i64),
+    Create,
+}
+
+fn plan_repairs(subscriptions: &[Subscription], url: &str, description: &str) -> Vec<Repair> {
+    let (delivering_here, elsewhere): (Vec<_>, Vec<_>) = subscriptions
+        .iter()
+        .partition(|subscription| subscription.url_callback == url);
+    let ours_elsewhere: Vec<_> = elsewhere
+        .into_iter()
+        .filter(|subscription| subscription.description == description)
+        .collect();
+    let keep = delivering_here
+        .iter()
+        .find(|subscription| subscription.enabled)
+        .or(delivering_here.first());
+
+    let mut repairs = Vec::new();
+    let keep = match keep {
+        Some(keep) if !keep.covers_time_entries() => {
+            repairs.push(Repair::Update(keep.subscription_id));
+            Some(*keep)
+        }
+        Some(keep) if !keep.enabled => {
+            repairs.push(Repair::Enable(keep.subscription_id));
+            Some(*keep)
+        }
+        Some(keep) => Some(*keep),
+        // Moving an old one over keeps its ID and history in Toggl.
+        None => match ours_elsewhere.first() {
+            Some(old) => {
+                repairs.push(Repair::Update(old.subscription_id));
+                Some(*old)
+            }
+            None => {
+                repairs.push(Repair::Create);
+                None
+            }
+        },
+    };
+    let keep_id = keep.map(|keep| keep.subscription_id);
+    repairs.extend(
+        delivering_here
+            .iter()
+            .filter(|subscription| Some(subscription.subscription_id) != keep_id)
+            .chain(
+                ours_elsewhere
+                    .iter()
+                    .filter(|subscription| Some(subscription.subscription_id) != keep_id),
+            )
+            .map(|subscription| Repair::Delete(subscription.subscription_id)),
+    );
+    repairs
+}
+
+/// Makes sure exactly one enabled subscription delivers time entry events to
+/// `callback_url`: re-enables it if Toggl disabled it after failed deliveries, restores its
+/// filters, removes duplicates and stale ones of ours, or creates it if it's gone. Returns
+/// whether anything had to be repaired.
+pub(crate) async fn ensure_subscription(
+    client: &Client,
+    state: &AppState,
+    api_token: &str,
+    subscription: &TogglSubscriptionSettings,
+) -> Result<bool> {
+    let settings = state.settings();
+    let workspace_id = workspace_id(client, api_token, subscription).await?;
+    let url = callback_url(&settings);
+    let subscriptions = fetch_subscriptions(client, api_token, workspace_id).await?;
+    let repairs = plan_repairs(&subscriptions, &url, &subscription.description);
+    if repairs.is_empty() {
+        return Ok(false);
+    }
+    if settings.dry_run {
+        info!(
+            "[DRY RUN] Not repairing the Toggl subscriptions for {}: {:?}",
+            url, repairs
+        );
+        return Ok(false);
+    }
+
+    for repair in repairs {
+        warn!("[SUBSCRIPTION] {:?} for {}", repair, url);
+        match repair {
+            Repair::Enable(id) => {
+                set_subscription_enabled(client, api_token, workspace_id, id, true).await?
+            }
+            Repair::Update(id) => {
+                update_subscription(
+                    client,
+                    api_token,
+                    workspace_id,
+                    id,
+                    &url,
+                    &subscription.description,
+                )
+                .await?
+            }
+            Repair::Delete(id) => delete_subscription(client, api_token, workspace_id, id).await?,
+            Repair::Create => {
+                create_subscription(
+                    client,
+                    api_token,
+                    workspace_id,
+                    &url,
+                    &subscription.description,
+                )
+                .await?
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Disables the subscriptions delivering to `callback_url`, e.g. while out of office.
+/// `ensure_subscription` enables them again.
+pub(crate) async fn pause_subscription(
+    client: &Client,
+    state: &AppState,
+    api_token: &str,
+    subscription: &TogglSubscriptionSettings,
+) -> Result<()> {
+    let workspace_id = workspace_id(client, api_token, subscription).await?;
+    let url = callback_url(&state.settings());
+    for subscription in fetch_subscriptions(client, api_token, workspace_id).await? {
+        if subscription.url_callback == url && subscription.enabled {
+            set_subscription_enabled(
+                client,
+                api_token,
+                workspace_id,
+                subscription.subscription_id,
+                false,
+            )
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Checks the Toggl subscription every `check_minutes`, starting right away, and repairs it.
+/// Events missed while it was disabled are caught up on with the startup reconciliation.
+pub async fn subscription_watchdog(
+    state: AppState,
+    subscription: TogglSubscriptionSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let Some(api_token) = state.settings().toggl_api_token.clone() else {
+        error!("toggl_subscription needs toggl_api_token");
+        return;
+    };
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(subscription.check_minutes * 60));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down subscription_watchdog");
+                break;
+            }
+        }
+
+        if state
+            .out_of_office()
+            .is_some_and(|ooo| ooo.pause_subscription)
+        {
+            info!("[SUBSCRIPTION] Paused while out of office");
+            continue;
+        }
+        match ensure_subscription(&client, &state, &api_token, &subscription).await {
+            Ok(repaired) => {
+                state.diagnostics.ok(
+                    "source.toggl_subscription",
+                    format!("delivering to {}", callback_url(&state.settings())),
+                );
+                if repaired {
+                    reconcile_on_boot(state.clone()).await;
+                }
+            }
+            Err(err) => {
+                error!("Failed to check the Toggl subscription: {}", err);
+                state
+                    .diagnostics
+                    .error("source.toggl_subscription", err.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,220 @@
+use crate::{
+    config::WakaTimeSettings,
+    presence::{emit, PresenceEvent},
+    state::{day_of, AppState},
+};
+use anyhow::Result;
+use axum::{
+    body::Bytes,
+    extract::{Json, Path, State},
+    http::{
+        header::{AUTHORIZATION, CONTENT_TYPE, USER_AGENT},
+        HeaderMap, Method, Uri,
+    },
+    response::{IntoResponse, Response},
+};
+use hyper::StatusCode;
+use reqwest::Client;
+use serde_json::{json, Value};
+use std::{
+    sync::{atomic::Ordering, Arc},
+    time::Duration,
+};
+use tokio::time::interval;
+use tracing::{error, info, warn};
+
+/// How often the grace window is checked, between polls as well.
+const CHECK_SECS: u64 = 15;
+
+/// Whether the last heartbeat is within the grace window.
+fn coding(state: &AppState, wakatime: &WakaTimeSettings) -> bool {
+    let last = state.last_heartbeat_at.load(Ordering::Relaxed);
+    last != 0 && state.clock.now().saturating_sub(last) < wakatime.grace_minutes * 60
+}
+
+/// Reports whether you're coding if that changed; the title is republished if that changes
+/// the status.
+fn update_coding(state: &AppState, wakatime: &WakaTimeSettings) {
+    let busy = coding(state, wakatime);
+    if state.coding.load(Ordering::Relaxed) != busy {
+        emit(state, PresenceEvent::Coding { busy }, None);
+    }
+}
+
+/// Notes a heartbeat sent at `at`; ones from the future count as sent now.
+fn record_heartbeat(state: &AppState, wakatime: &WakaTimeSettings, at: u64) {
+    let at = at.min(state.clock.now());
+    state.last_heartbeat_at.fetch_max(at, Ordering::Relaxed);
+    update_coding(state, wakatime);
+}
+
+/// When today's last coding duration ended, if there was one.
+async fn last_activity(
+    client: &Client,
+    state: &AppState,
+    wakatime: &WakaTimeSettings,
+    api_key: &str,
+) -> Result<Option<u64>> {
+    // WakaTime's days start at midnight, whatever `day_start_hour` says.
+    let today = day_of(state.clock.now(), 0, state.settings().timezone);
+    let response: Value = client
+        .get(format!(
+            "{}/users/current/durations",
+            wakatime.api_url.trim_end_matches('/')
+        ))
+        .query(&[
+            ("date", today.to_string()),
+            ("api_key", api_key.to_string()),
+        ])
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+    let ends = response["data"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|duration| {
+            let start = duration["time"].as_f64()?;
+            Some((start + duration["duration"].as_f64().unwrap_or(0.0)) as u64)
+        });
+    Ok(ends.max())
+}
+
+/// Polls today's durations if there's an `api_key`, and drops back from coding once the grace
+/// window is over.
+pub async fn wakatime_poller(
+    state: AppState,
+    wakatime: WakaTimeSettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let client = Client::new();
+    let mut poll = interval(Duration::from_secs(wakatime.poll_interval_secs));
+    let mut check = interval(Duration::from_secs(CHECK_SECS));
+
+    loop {
+        tokio::select! {
+            _ = poll.tick(), if wakatime.api_key.is_some() => {
+                let api_key = wakatime.api_key.as_deref().unwrap_or_default();
+                match last_activity(&client, &state, &wakatime, api_key).await {
+                    Ok(Some(last)) => {
+                        state
+                            .diagnostics
+                            .ok("source.wakatime", format!("last activity at {}", last));
+                        record_heartbeat(&state, &wakatime, last);
+                    }
+                    Ok(None) => state.diagnostics.ok("source.wakatime", "no activity today"),
+                    Err(err) => {
+                        error!("Failed to fetch WakaTime durations: {}", err);
+                        state.diagnostics.error("source.wakatime", err.to_string());
+                    }
+                }
+            },
+            _ = check.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down wakatime_poller");
+                break;
+            }
+        }
+
+        update_coding(&state, &wakatime);
+    }
+}
+
+/// When the heartbeats in a body were sent: one heartbeat or, for `heartbeats.bulk`, an array.
+/// Ones without a `time` count as sent `now`.
+fn heartbeat_times(body: &[u8], now: u64) -> Vec<u64> {
+    let heartbeats = match serde_json::from_slice(body) {
+        Ok(Value::Array(heartbeats)) => heartbeats,
+        Ok(heartbeat) => vec![heartbeat],
+        Err(_) => Vec::new(),
+    };
+    heartbeats
+        .iter()
+        .map(|heartbeat| heartbeat["time"].as_f64().map_or(now, |time| time as u64))
+        .collect()
+}
+
+/// Passes a request on to `api_url` and its response back.
+async fn forward(
+    wakatime: &WakaTimeSettings,
+    method: Method,
+    path: &str,
+    uri: &Uri,
+    headers: &HeaderMap,
+    body: Bytes,
+) -> Result<Response> {
+    let mut url = format!("{}/{}", wakatime.api_url.trim_end_matches('/'), path);
+    if let Some(query) = uri.query() {
+        url.push('?');
+        url.push_str(query);
+    }
+    let mut request = Client::new().request(method, url).body(body);
+    for name in [AUTHORIZATION, CONTENT_TYPE, USER_AGENT] {
+        if let Some(value) = headers.get(&name) {
+            request = request.header(name, value.clone());
+        }
+    }
+    let response = request.send().await?;
+    let status = response.status();
+    let content_type = response.headers().get(CONTENT_TYPE).cloned();
+    let body = response.bytes().await?;
+    let mut response = (status, body).into_response();
+    if let Some(content_type) = content_type {
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+    }
+    Ok(response)
+}
+
+/// `/wakatime/*` on the control API, for the editor plugins' `api_url`: notes the heartbeats
+/// they send and, with `forward`, passes everything on to WakaTime.
+pub async fn wakatime_relay(
+    State(state): State<AppState>,
+    Path(path): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(wakatime) = state.settings().wakatime.clone() else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    let bulk = path.ends_with("/heartbeats.bulk");
+    let heartbeat = method == Method::POST && (bulk || path.ends_with("/heartbeats"));
+    let sent = if heartbeat {
+        heartbeat_times(&body, state.clock.now())
+    } else {
+        Vec::new()
+    };
+    if let Some(&at) = sent.iter().max() {
+        record_heartbeat(&state, &wakatime, at);
+        state.diagnostics.ok(
+            "source.wakatime",
+            format!("{} heartbeats relayed", sent.len()),
+        );
+    }
+
+    if wakatime.forward {
+        return match forward(&wakatime, method, &path, &uri, &headers, body).await {
+            Ok(response) => response,
+            Err(err) => {
+                warn!("Failed to forward to WakaTime: {}", err);
+                StatusCode::BAD_GATEWAY.into_response()
+            }
+        };
+    }
+    // Answered the way WakaTime would, or the plugins queue the heartbeats to send again.
+    match (heartbeat, bulk) {
+        (false, _) => StatusCode::NOT_FOUND.into_response(),
+        (true, false) => (StatusCode::CREATED, Json(json!({ "data": {} }))).into_response(),
+        (true, true) => {
+            let responses = vec![json!([{ "data": {} }, 201]); sent.len()];
+            (
+                StatusCode::ACCEPTED,
+                Json(json!({ "responses": responses })),
+            )
+                .into_response()
+        }
+    }
+}
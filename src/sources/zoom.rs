@@ -0,0 +1,116 @@
+use super::update_meeting_busy;
+use crate::{config::ZoomSettings, presence::MeetingSource, state::AppState};
+use axum::{
+    body::Bytes,
+    extract::{Json, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use hyper::StatusCode;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tracing::{info, warn};
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Requests older than this are rejected as possible replays.
+const MAX_REQUEST_AGE_SECS: u64 = 300;
+
+fn hmac_hex(secret: &str, message: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC takes any key size");
+    mac.update(message);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Checks `x-zm-signature`, which is `v0=` + HMAC-SHA256 of `v0:{timestamp}:{body}`.
+fn verify_signature(
+    state: &AppState,
+    zoom: &ZoomSettings,
+    headers: &HeaderMap,
+    body: &[u8],
+) -> bool {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    let (Some(timestamp), Some(signature)) =
+        (header("x-zm-request-timestamp"), header("x-zm-signature"))
+    else {
+        return false;
+    };
+    let Ok(sent_at) = timestamp.parse::<u64>() else {
+        return false;
+    };
+    if state.clock.now().abs_diff(sent_at) > MAX_REQUEST_AGE_SECS {
+        return false;
+    }
+
+    let Some(signature) = signature
+        .strip_prefix("v0=")
+        .and_then(|signature| hex::decode(signature).ok())
+    else {
+        return false;
+    };
+    let mut mac =
+        HmacSha256::new_from_slice(zoom.secret_token.as_bytes()).expect("HMAC takes any key size");
+    mac.update(format!("v0:{}:", timestamp).as_bytes());
+    mac.update(body);
+    mac.verify_slice(&signature).is_ok()
+}
+
+/// `POST /zoom`: Zoom's "meeting started/ended" event subscriptions.
+pub async fn zoom_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(zoom) = &state.settings().zoom else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    if !verify_signature(&state, zoom, &headers, &body) {
+        warn!("Rejected Zoom webhook with a missing or invalid signature");
+        state.diagnostics.error(
+            "source.zoom",
+            "rejected a request with an invalid signature",
+        );
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let request_body: Value = match serde_json::from_slice(&body) {
+        Ok(value) => value,
+        Err(err) => {
+            warn!("Error parsing Zoom request body: {}", err);
+            return StatusCode::BAD_REQUEST.into_response();
+        }
+    };
+
+    let event = request_body["event"].as_str().unwrap_or_default();
+    let object = &request_body["payload"]["object"];
+    info!("GOT ZOOM EVENT: {}", event);
+
+    let busy = match event {
+        "endpoint.url_validation" => {
+            let Some(plain_token) = request_body["payload"]["plainToken"].as_str() else {
+                return StatusCode::BAD_REQUEST.into_response();
+            };
+            let encrypted_token = hmac_hex(&zoom.secret_token, plain_token.as_bytes());
+            return (
+                StatusCode::OK,
+                Json(json!({ "plainToken": plain_token, "encryptedToken": encrypted_token })),
+            )
+                .into_response();
+        }
+        "meeting.started" => true,
+        "meeting.ended" => false,
+        _ => return StatusCode::OK.into_response(),
+    };
+
+    if let Some(user_id) = &zoom.user_id {
+        if object["host_id"].as_str() != Some(user_id.as_str()) {
+            return StatusCode::OK.into_response();
+        }
+    }
+
+    state
+        .diagnostics
+        .ok("source.zoom", format!("last event {}", event));
+    update_meeting_busy(&state, MeetingSource::Zoom, busy);
+    StatusCode::OK.into_response()
+}
@@ -0,0 +1,1701 @@
+use crate::{
+    bot::update_status_panel,
+    clock::{local_time, timestamp_of, Clock, SystemClock},
+    config::{ActivitySignal, EndOfDaySettings, LongEntrySettings, Settings},
+    diagnostics::Diagnostics,
+    error::Error,
+    history::{History, Transition},
+    i18n::Text,
+    limits::RateLimiter,
+    out_of_office::{self, sync_out_of_office, OutOfOffice},
+    presence::Envelope,
+    sinks::{
+        deep_focus::update_deep_focus,
+        matrix::set_room_title,
+        notifications::NotificationPause,
+        telegram::{
+            edit_forum_topic, edit_message_text, is_permission_problem, send_and_pin_message,
+            send_message, set_chat_description, set_chat_title, set_user_emoji_status,
+            update_chat_photo, verify_bot_rights, ChatPhotoCache,
+        },
+    },
+    sources::idle::system_idle_seconds,
+};
+use chrono_tz::Tz;
+use reqwest::Client;
+use std::{
+    collections::BTreeMap,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, PoisonError, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{broadcast, mpsc},
+    time::interval,
+};
+use tracing::{error, info, warn};
+
+/// Everything that can have an opinion about the effective status.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusSource {
+    /// A status pinned by hand.
+    Override,
+    /// An ongoing busy event in one of the calendars, a Zoom meeting or Slack huddle, or the
+    /// camera/microphone in use.
+    Meeting,
+    /// The Toggl timer: running or on break.
+    Timer,
+    /// WakaTime heartbeats within the grace window: you're coding, timer or not.
+    Coding,
+    /// The ActivityWatch category of the active window: busy or slacking.
+    Activity,
+    /// Nothing going on: you're not working.
+    Idle,
+    /// Outside `work_hours`. Always consulted first, whatever the precedence says.
+    Schedule,
+    /// Out of office. Consulted before even the schedule.
+    OutOfOffice,
+}
+
+pub fn default_precedence() -> Vec<StatusSource> {
+    vec![
+        StatusSource::Override,
+        StatusSource::Meeting,
+        StatusSource::Timer,
+        StatusSource::Coding,
+        StatusSource::Idle,
+    ]
+}
+
+#[derive(Clone)]
+pub struct AppState {
+    /// Swapped as a whole on reload; see `settings()`.
+    shared_settings: Arc<RwLock<Arc<Settings>>>,
+    /// Notified after the settings were replaced, so long-running tasks restart with them.
+    pub settings_reloaded: Arc<tokio::sync::Notify>,
+    pub last_break_start: Arc<AtomicU64>,
+    pub timer_started_at: Arc<AtomicU64>,
+    /// When the timer last stopped, for `afk_tiers`; 0 while it runs or if unknown.
+    pub timer_stopped_at: Arc<AtomicU64>,
+    pub long_entry_flagged: Arc<AtomicBool>,
+    pub google_calendar_busy: Arc<AtomicBool>,
+    pub caldav_busy: Arc<AtomicBool>,
+    /// The webcam or microphone is in use.
+    pub devices_busy: Arc<AtomicBool>,
+    pub zoom_busy: Arc<AtomicBool>,
+    pub slack_huddle_busy: Arc<AtomicBool>,
+    /// When the last WakaTime heartbeat was sent, 0 if none yet.
+    pub last_heartbeat_at: Arc<AtomicU64>,
+    /// A heartbeat came within `wakatime.grace_minutes`, as of the last check.
+    pub coding: Arc<AtomicBool>,
+    /// What the ActivityWatch category of the active window says, as of the last poll.
+    pub activity: Arc<Mutex<ActivitySignal>>,
+    pub daily_totals: Arc<Mutex<DailyTotals>>,
+    /// When the break turned into AFK, 0 if not AFK.
+    pub afk_since: Arc<AtomicU64>,
+    /// Past `end_of_day` without a timer: Not Working until the next day or timer.
+    pub done_for_today: Arc<AtomicBool>,
+    /// Out of office as set over the control API; see `out_of_office()`.
+    pub runtime_out_of_office: Arc<Mutex<Option<OutOfOffice>>>,
+    /// Whether `sync_out_of_office` last saw us out of office.
+    pub(crate) out_of_office_active: Arc<AtomicBool>,
+    /// While `amibussy pause` has frozen the sinks, the status they were left showing.
+    pub sinks_frozen_at: Arc<Mutex<Option<Status>>>,
+    pub status_override: Arc<Mutex<Option<Status>>>,
+    /// When the current status was entered (or the daemon started).
+    pub status_since: Arc<AtomicU64>,
+    /// When the override runs out, 0 if it doesn't.
+    pub override_until: Arc<AtomicU64>,
+    pub diagnostics: Arc<Diagnostics>,
+    pub clock: Arc<dyn Clock>,
+    pub chat_photos: Arc<ChatPhotoCache>,
+    pub status_message_id: Arc<Mutex<Option<i64>>>,
+    pub status_panel_message_id: Arc<Mutex<Option<i64>>>,
+    pub history: Option<Arc<History>>,
+    /// Every transition, for live subscribers such as `/events`.
+    pub transitions: broadcast::Sender<Transition>,
+    /// The last `sequence` sent to the outbound webhooks. Starts from the clock in
+    /// milliseconds, so it keeps increasing across restarts.
+    pub webhook_sequence: Arc<AtomicU64>,
+    pub notification_pause: Arc<NotificationPause>,
+    /// The running Toggl entry, as last seen in a webhook.
+    pub current_entry: Arc<Mutex<Option<CurrentEntry>>>,
+    pub github_status_set: Arc<AtomicBool>,
+    /// Whether the `deep_focus` measures are in place.
+    pub deep_focus_on: Arc<AtomicBool>,
+    /// The last problem `verify_bot_rights` found, so it's only DMed once.
+    pub bot_rights_problem: Arc<Mutex<Option<String>>>,
+    /// Per-client request budget for the public webhook route.
+    pub webhook_limiter: Arc<RateLimiter>,
+    /// The title last published to the title sinks, for `title_refresher`.
+    published_title: Arc<Mutex<Option<String>>>,
+    /// A transition held back by `hysteresis`.
+    pending_transition: Arc<Mutex<Option<PendingTransition>>>,
+    /// Teammates' statuses by Toggl user ID, each a state of its own; empty unless `team` is
+    /// set. Fixed at startup: reloads update their settings but don't add or remove them.
+    pub team: Arc<BTreeMap<i64, AppState>>,
+    /// The statuses of `workspace_chats` by workspace ID, fixed at startup like `team`.
+    pub workspaces: Arc<BTreeMap<i64, AppState>>,
+    /// Events from the sources, for `presence_loop`.
+    pub(crate) presence_events: mpsc::UnboundedSender<Envelope>,
+    /// Behind an async mutex, so it's still there when `presence_loop` is restarted after a
+    /// panic.
+    pub(crate) presence_events_receiver: Arc<tokio::sync::Mutex<mpsc::UnboundedReceiver<Envelope>>>,
+}
+
+/// A status change that is only announced once it has lasted long enough.
+struct PendingTransition {
+    /// The last announced status.
+    from: Status,
+    to: Status,
+    task: tokio::task::AbortHandle,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CurrentEntry {
+    /// Toggl's ID of the entry, to tell edits of it from edits of older entries.
+    pub id: Option<i64>,
+    pub description: Option<String>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Status {
+    Busy,
+    /// Busy, but the timer has been running for so long it was probably forgotten.
+    ForgotTimer,
+    Break,
+    NotWorking,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Busy => "busy",
+            Status::ForgotTimer => "forgot_timer",
+            Status::Break => "break",
+            Status::NotWorking => "not_working",
+        }
+    }
+
+    pub fn title(self, settings: &Settings) -> &str {
+        match self {
+            Status::Busy => &settings.busy_chat_status,
+            Status::ForgotTimer => settings
+                .long_entry
+                .as_ref()
+                .and_then(|long_entry| long_entry.forgot_timer_status.as_deref())
+                .unwrap_or(&settings.busy_chat_status),
+            Status::Break => &settings.break_chat_status,
+            Status::NotWorking => &settings.not_working_status,
+        }
+    }
+}
+
+impl FromStr for Status {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "busy" => Ok(Status::Busy),
+            "forgot_timer" => Ok(Status::ForgotTimer),
+            "break" => Ok(Status::Break),
+            "not_working" => Ok(Status::NotWorking),
+            other => Err(anyhow::anyhow!("unknown status {:?}", other)),
+        }
+    }
+}
+
+/// The outcome of running the precedence rules: which status, and which source decided it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decision {
+    pub status: Status,
+    pub source: StatusSource,
+}
+
+/// Busy and break time accumulated for the current day.
+#[derive(Debug, Clone, Default)]
+pub struct DailyTotals {
+    pub day: Option<chrono::NaiveDate>,
+    pub busy_secs: u64,
+    pub break_secs: u64,
+    pub afk_secs: u64,
+    /// Number of busy blocks (stopped timers) recorded today.
+    pub entries: u32,
+    pub longest_busy_secs: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum IntervalKind {
+    Busy,
+    Break,
+    Afk,
+}
+
+impl DailyTotals {
+    /// Resets the totals if `day` is newer than the one being accumulated, returning the finished day.
+    pub fn roll_over(&mut self, day: chrono::NaiveDate) -> Option<DailyTotals> {
+        match self.day {
+            Some(current) if current >= day => None,
+            Some(_) => {
+                let finished = self.clone();
+                *self = DailyTotals {
+                    day: Some(day),
+                    ..Default::default()
+                };
+                Some(finished)
+            }
+            None => {
+                self.day = Some(day);
+                None
+            }
+        }
+    }
+
+    /// Adds `[start, end)` to the totals, counting only the parts that fall on the current day.
+    pub fn record(
+        &mut self,
+        kind: IntervalKind,
+        start: u64,
+        end: u64,
+        day_start_hour: u32,
+        timezone: Option<Tz>,
+    ) {
+        let mut today_secs = 0;
+        for (day, from, to) in split_at_day_start(start, end, day_start_hour, timezone) {
+            self.roll_over(day);
+            if Some(day) != self.day {
+                continue;
+            }
+            today_secs += to - from;
+            match kind {
+                IntervalKind::Busy => self.busy_secs += to - from,
+                IntervalKind::Break => self.break_secs += to - from,
+                IntervalKind::Afk => self.afk_secs += to - from,
+            }
+        }
+
+        if let IntervalKind::Busy = kind {
+            self.entries += 1;
+            self.longest_busy_secs = self.longest_busy_secs.max(today_secs);
+        }
+    }
+}
+
+/// The local "day" a timestamp belongs to, with days starting at `day_start_hour`.
+pub fn day_of(timestamp: u64, day_start_hour: u32, timezone: Option<Tz>) -> chrono::NaiveDate {
+    (local_time(timezone, timestamp) - chrono::Duration::hours(i64::from(day_start_hour))).date()
+}
+
+/// UNIX timestamp at which `day` starts locally.
+pub fn day_start_timestamp(
+    day: chrono::NaiveDate,
+    day_start_hour: u32,
+    timezone: Option<Tz>,
+) -> Option<u64> {
+    timestamp_of(timezone, day.and_hms_opt(day_start_hour, 0, 0)?)
+}
+
+/// Splits `[start, end)` into per-day pieces at each local day start.
+pub fn split_at_day_start(
+    start: u64,
+    end: u64,
+    day_start_hour: u32,
+    timezone: Option<Tz>,
+) -> Vec<(chrono::NaiveDate, u64, u64)> {
+    let mut pieces = Vec::new();
+    let mut from = start;
+
+    while from < end {
+        let day = day_of(from, day_start_hour, timezone);
+        let next_day_start = day
+            .succ_opt()
+            .and_then(|next| day_start_timestamp(next, day_start_hour, timezone))
+            .unwrap_or(end);
+        let to = next_day_start.clamp(from + 1, end);
+        pieces.push((day, from, to));
+        from = to;
+    }
+
+    pieces
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TogglStatus {
+    Running,
+    OnBreak,
+    Afk,
+}
+
+impl AppState {
+    pub fn new(settings: Settings) -> Self {
+        Self::with_clock(settings, Arc::new(SystemClock::default()))
+    }
+
+    /// Like `new`, but with an injected clock, e.g. a `ManualClock` in tests.
+    pub fn with_clock(settings: Settings, clock: Arc<dyn Clock>) -> Self {
+        let status_message_id = settings
+            .status_message
+            .as_ref()
+            .and_then(|message| message.message_id);
+        let status_panel_message_id = settings
+            .status_panel
+            .as_ref()
+            .and_then(|panel| panel.message_id);
+        let history_db = shellexpand::tilde(&settings.history_db).to_string();
+        let history = match History::open(&history_db) {
+            Ok(history) => Some(Arc::new(history)),
+            Err(err) => {
+                error!(
+                    "Failed to open status history at {}, not recording transitions: {}",
+                    history_db, err
+                );
+                None
+            }
+        };
+        let (presence_events, presence_events_receiver) = mpsc::unbounded_channel();
+        let team = settings
+            .team
+            .iter()
+            .map(|member| {
+                let member_state = AppState::with_clock(settings.for_member(member), clock.clone());
+                (member.toggl_user_id, member_state)
+            })
+            .collect();
+        let workspaces = settings
+            .workspace_chats
+            .iter()
+            .map(|route| {
+                let route_state =
+                    AppState::with_clock(settings.for_workspace(route), clock.clone());
+                (route.workspace_id, route_state)
+            })
+            .collect();
+        AppState {
+            shared_settings: Arc::new(RwLock::new(Arc::new(settings))),
+            settings_reloaded: Arc::new(tokio::sync::Notify::new()),
+            last_break_start: Arc::new(AtomicU64::new(0)),
+            timer_started_at: Arc::new(AtomicU64::new(0)),
+            timer_stopped_at: Arc::new(AtomicU64::new(0)),
+            long_entry_flagged: Arc::new(AtomicBool::new(false)),
+            google_calendar_busy: Arc::new(AtomicBool::new(false)),
+            caldav_busy: Arc::new(AtomicBool::new(false)),
+            devices_busy: Arc::new(AtomicBool::new(false)),
+            zoom_busy: Arc::new(AtomicBool::new(false)),
+            slack_huddle_busy: Arc::new(AtomicBool::new(false)),
+            last_heartbeat_at: Arc::new(AtomicU64::new(0)),
+            coding: Arc::new(AtomicBool::new(false)),
+            activity: Arc::new(Mutex::new(ActivitySignal::Neutral)),
+            daily_totals: Arc::new(Mutex::new(DailyTotals::default())),
+            afk_since: Arc::new(AtomicU64::new(0)),
+            done_for_today: Arc::new(AtomicBool::new(false)),
+            runtime_out_of_office: Arc::new(Mutex::new(None)),
+            out_of_office_active: Arc::new(AtomicBool::new(false)),
+            sinks_frozen_at: Arc::new(Mutex::new(None)),
+            status_override: Arc::new(Mutex::new(None)),
+            override_until: Arc::new(AtomicU64::new(0)),
+            status_since: Arc::new(AtomicU64::new(clock.now())),
+            webhook_sequence: Arc::new(AtomicU64::new(clock.now() * 1000)),
+            diagnostics: Arc::new(Diagnostics::default()),
+            clock,
+            chat_photos: Arc::new(ChatPhotoCache::default()),
+            status_message_id: Arc::new(Mutex::new(status_message_id)),
+            status_panel_message_id: Arc::new(Mutex::new(status_panel_message_id)),
+            history,
+            transitions: broadcast::channel(64).0,
+            notification_pause: Arc::new(NotificationPause::default()),
+            current_entry: Arc::new(Mutex::new(None)),
+            github_status_set: Arc::new(AtomicBool::new(false)),
+            deep_focus_on: Arc::new(AtomicBool::new(false)),
+            bot_rights_problem: Arc::new(Mutex::new(None)),
+            webhook_limiter: Arc::new(RateLimiter::default()),
+            published_title: Arc::new(Mutex::new(None)),
+            pending_transition: Arc::new(Mutex::new(None)),
+            team: Arc::new(team),
+            workspaces: Arc::new(workspaces),
+            presence_events,
+            presence_events_receiver: Arc::new(tokio::sync::Mutex::new(presence_events_receiver)),
+        }
+    }
+
+    pub fn toggl_status(&self) -> TogglStatus {
+        if self.timer_started_at.load(Ordering::Relaxed) != 0 {
+            TogglStatus::Running
+        } else if self.last_break_start.load(Ordering::Relaxed) != 0 {
+            TogglStatus::OnBreak
+        } else {
+            TogglStatus::Afk
+        }
+    }
+
+    /// The current settings. Hold on to the returned `Arc` rather than calling this repeatedly
+    /// if a consistent view matters, since a reload may swap them at any time.
+    pub fn settings(&self) -> Arc<Settings> {
+        self.shared_settings
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Replaces the settings and tells long-running tasks to restart with them. A `--dry-run`
+    /// carries over.
+    pub fn replace_settings(&self, mut settings: Settings) {
+        if self.settings().forced_dry_run {
+            settings.force_dry_run();
+        }
+        for member in &settings.team {
+            match self.team.get(&member.toggl_user_id) {
+                Some(member_state) => member_state.replace_settings(settings.for_member(member)),
+                None => warn!(
+                    "New team member {} only joins after a restart",
+                    member.toggl_user_id
+                ),
+            }
+        }
+        for route in &settings.workspace_chats {
+            match self.workspaces.get(&route.workspace_id) {
+                Some(route_state) => route_state.replace_settings(settings.for_workspace(route)),
+                None => warn!(
+                    "Workspace {} is only routed to its chat after a restart",
+                    route.workspace_id
+                ),
+            }
+        }
+        *self
+            .shared_settings
+            .write()
+            .unwrap_or_else(PoisonError::into_inner) = Arc::new(settings);
+        self.settings_reloaded.notify_waiters();
+    }
+
+    /// The state whose status a Toggl event of `user_id` is about: yours, a teammate's, or
+    /// `None` for someone else in the workspace. Without `team` everything is yours.
+    pub fn for_toggl_user(&self, user_id: Option<i64>) -> Option<AppState> {
+        let Some(user_id) = user_id.filter(|_| !self.team.is_empty()) else {
+            return Some(self.clone());
+        };
+        if self.settings().toggl_user_id == Some(user_id) {
+            return Some(self.clone());
+        }
+        self.team.get(&user_id).cloned()
+    }
+
+    /// The state whose status an event in `workspace_id` is about: the one `workspace_chats`
+    /// routes it to, or this one.
+    pub fn for_workspace(&self, workspace_id: Option<i64>) -> AppState {
+        workspace_id
+            .and_then(|workspace_id| self.workspaces.get(&workspace_id))
+            .unwrap_or(self)
+            .clone()
+    }
+
+    pub fn sinks_paused(&self) -> bool {
+        self.sinks_frozen_at
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_some()
+    }
+
+    /// The status the sinks were last told about: the decided one, unless `hysteresis` is
+    /// still holding a change to it back.
+    pub fn announced_status(&self) -> Status {
+        match &*self
+            .pending_transition
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+        {
+            Some(transition) => transition.from,
+            None => self.decide().status,
+        }
+    }
+
+    /// Pins (or with `None`, unpins) a status by hand, optionally until a UNIX timestamp.
+    pub fn set_override(&self, status: Option<Status>, until: Option<u64>) {
+        *self
+            .status_override
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = status;
+        self.override_until
+            .store(until.unwrap_or_default(), Ordering::Relaxed);
+    }
+
+    /// What a single source thinks the status should be, if it has an opinion at all.
+    pub fn opinion(&self, source: StatusSource) -> Option<Status> {
+        match source {
+            StatusSource::Override => *self
+                .status_override
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner),
+            StatusSource::Meeting => (self.google_calendar_busy.load(Ordering::Relaxed)
+                || self.caldav_busy.load(Ordering::Relaxed)
+                || self.devices_busy.load(Ordering::Relaxed)
+                || self.zoom_busy.load(Ordering::Relaxed)
+                || self.slack_huddle_busy.load(Ordering::Relaxed))
+            .then_some(Status::Busy),
+            StatusSource::Timer => match self.toggl_status() {
+                TogglStatus::Running if self.long_entry_flagged.load(Ordering::Relaxed) => {
+                    Some(Status::ForgotTimer)
+                }
+                TogglStatus::Running => Some(Status::Busy),
+                TogglStatus::OnBreak => Some(Status::Break),
+                TogglStatus::Afk => None,
+            },
+            StatusSource::Coding => self.coding.load(Ordering::Relaxed).then_some(Status::Busy),
+            StatusSource::Activity => {
+                match *self.activity.lock().unwrap_or_else(PoisonError::into_inner) {
+                    ActivitySignal::Busy => Some(Status::Busy),
+                    ActivitySignal::Slacking => Some(Status::Break),
+                    ActivitySignal::Neutral => None,
+                }
+            }
+            StatusSource::Idle => Some(Status::NotWorking),
+            StatusSource::OutOfOffice => self.out_of_office().map(|_| Status::NotWorking),
+            StatusSource::Schedule => (self.off_hours()
+                || self.done_for_today.load(Ordering::Relaxed))
+            .then_some(Status::NotWorking),
+        }
+    }
+
+    /// Whether it's currently outside the configured `work_hours`.
+    pub fn off_hours(&self) -> bool {
+        let Some(work_hours) = &self.settings().work_hours else {
+            return false;
+        };
+        !work_hours.contains(local_time(self.settings().timezone, self.clock.now()))
+    }
+
+    /// Out of office as set over the control API or, failing that, in the settings, unless
+    /// it's over.
+    pub fn out_of_office(&self) -> Option<OutOfOffice> {
+        let now = self.clock.now();
+        self.runtime_out_of_office
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+            .filter(|ooo| now < ooo.until)
+            .or_else(|| out_of_office::from_settings(&self.settings(), now))
+    }
+
+    /// Out of office first, then `decide_in_office`.
+    pub fn decide(&self) -> Decision {
+        if self.out_of_office().is_some() {
+            return Decision {
+                status: Status::NotWorking,
+                source: StatusSource::OutOfOffice,
+            };
+        }
+        self.decide_in_office()
+    }
+
+    /// The schedule (work hours and the end of the day) first, then the configured precedence.
+    pub fn decide_in_office(&self) -> Decision {
+        if self.off_hours() || self.done_for_today.load(Ordering::Relaxed) {
+            return Decision {
+                status: Status::NotWorking,
+                source: StatusSource::Schedule,
+            };
+        }
+        self.decide_by_precedence()
+    }
+
+    /// Walks the configured precedence and returns the first opinion.
+    fn decide_by_precedence(&self) -> Decision {
+        self.settings()
+            .precedence
+            .iter()
+            .find_map(|&source| {
+                self.opinion(source)
+                    .map(|status| Decision { status, source })
+            })
+            .unwrap_or(Decision {
+                status: Status::NotWorking,
+                source: StatusSource::Idle,
+            })
+    }
+
+    /// The chat title we should be showing right now.
+    pub fn effective_title(&self) -> String {
+        let decision = self.decide();
+        let settings = self.settings();
+        if let Some(ooo) = self
+            .out_of_office()
+            .filter(|_| decision.source == StatusSource::OutOfOffice)
+        {
+            return self.render_title(&ooo.title);
+        }
+        if let Some(end_of_day) = settings
+            .end_of_day
+            .as_ref()
+            .filter(|_| self.done_for_today.load(Ordering::Relaxed) && !self.off_hours())
+        {
+            let title = end_of_day
+                .title
+                .as_deref()
+                .unwrap_or(settings.text(Text::EndOfDayTitle));
+            return self.render_title(title);
+        }
+        if let Some(deep_focus) = settings
+            .deep_focus
+            .as_ref()
+            .filter(|_| self.in_deep_focus(decision))
+        {
+            let title = deep_focus
+                .title
+                .as_deref()
+                .unwrap_or(settings.text(Text::DeepFocusTitle));
+            return self.render_title(title);
+        }
+        if let Some(rule) = self.entry_title(decision) {
+            return self.render_title(&settings.entry_titles[rule].title);
+        }
+        match self.afk_tier(decision) {
+            Some(tier) => self.render_title(&settings.afk_tiers[tier].title),
+            None => self.render_title(decision.status.title(&settings)),
+        }
+    }
+
+    /// Whether you're Busy with an entry tagged `deep_focus.tag`.
+    pub fn in_deep_focus(&self, decision: Decision) -> bool {
+        let Some(deep_focus) = &self.settings().deep_focus else {
+            return false;
+        };
+        decision.status == Status::Busy
+            && self
+                .current_entry
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .as_ref()
+                .is_some_and(|entry| entry.tags.contains(&deep_focus.tag))
+    }
+
+    /// The first of the `entry_titles` matching the running entry's description, while the
+    /// timer has you Busy.
+    pub fn entry_title(&self, decision: Decision) -> Option<usize> {
+        if decision.status != Status::Busy || decision.source != StatusSource::Timer {
+            return None;
+        }
+        let current = self
+            .current_entry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let description = current.as_ref()?.description.as_deref()?;
+        self.settings()
+            .entry_titles
+            .iter()
+            .position(|rule| rule.pattern.is_match(description))
+    }
+
+    /// The last of the `afk_tiers` reached since the timer stopped whose status is the one
+    /// decided by the timer going quiet, if any.
+    pub fn afk_tier(&self, decision: Decision) -> Option<usize> {
+        let stopped_at = self.timer_stopped_at.load(Ordering::Relaxed);
+        if stopped_at == 0 || !matches!(decision.source, StatusSource::Timer | StatusSource::Idle) {
+            return None;
+        }
+        let stopped_secs = self.clock.now().saturating_sub(stopped_at);
+        self.settings().afk_tiers.iter().rposition(|tier| {
+            tier.status == decision.status && stopped_secs >= tier.after_minutes * 60
+        })
+    }
+
+    /// Fills in `{elapsed}` (time in the current status), `{back_at}` (local HH:MM at which
+    /// the current break turns into Not Working) and `{emoji}` (the `theme`'s for the status)
+    /// in a status title.
+    fn render_title(&self, template: &str) -> String {
+        if !template.contains('{') {
+            return template.to_string();
+        }
+        let settings = self.settings();
+        let now = self.clock.now();
+        let elapsed = now.saturating_sub(self.status_since.load(Ordering::Relaxed));
+        let break_start = self.last_break_start.load(Ordering::Relaxed);
+        let back_at = if break_start == 0 {
+            String::new()
+        } else {
+            local_time(settings.timezone, break_start + settings.afk_after_secs())
+                .format("%H:%M")
+                .to_string()
+        };
+        let emoji = settings
+            .loaded_theme
+            .as_ref()
+            .map_or("", |theme| theme.emoji(self.decide().status));
+        template
+            .replace("{elapsed}", &format_elapsed(elapsed))
+            .replace("{back_at}", &back_at)
+            .replace("{emoji}", emoji)
+    }
+
+    /// Fills in a `chat_description` (or `auto_reply`) template: `{title}`, `{entry}`,
+    /// `{started}` and `{since}` on top of what `render_title` does.
+    pub fn render_template(&self, template: &str) -> String {
+        let settings = self.settings();
+        let hh_mm = |timestamp| {
+            local_time(settings.timezone, timestamp)
+                .format("%H:%M")
+                .to_string()
+        };
+        let started_at = self.timer_started_at.load(Ordering::Relaxed);
+        let started = if started_at == 0 {
+            String::new()
+        } else {
+            hh_mm(started_at)
+        };
+        let entry = self
+            .current_entry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_ref()
+            .and_then(|entry| entry.description.clone())
+            .unwrap_or_default();
+        self.render_title(template)
+            .replace("{title}", &self.effective_title())
+            .replace("{since}", &hh_mm(self.status_since.load(Ordering::Relaxed)))
+            .replace("{started}", &started)
+            .replace("{entry}", &entry)
+    }
+}
+
+/// `47m` or `2h 05m`.
+fn format_elapsed(secs: u64) -> String {
+    if secs < 3600 {
+        format!("{}m", secs / 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+    }
+}
+
+/// `publish_if_changed` for changes that may keep the status but give it another title, like
+/// calling it a day while already Not Working: then only the title is published.
+pub async fn publish_change(client: &Client, state: &AppState, previous: Status, cause: &str) {
+    if state.decide().status == previous {
+        publish_title(client, state).await;
+    } else {
+        publish_if_changed(client, state, previous, cause, None).await;
+    }
+}
+
+/// Records the transition away from `previous` (if the status changed) and publishes the
+/// effective title if it differs from the previous one, logging how it was decided.
+///
+/// With `hysteresis`, a change is held back until the new status has lasted long enough;
+/// if it changes back in the meantime, nothing is announced at all.
+pub async fn publish_if_changed(
+    client: &Client,
+    state: &AppState,
+    previous: Status,
+    cause: &str,
+    payload_id: Option<&str>,
+) {
+    if let Some(previous) = hold_back(client, state, previous, cause, payload_id) {
+        announce(client, state, previous, cause, payload_id).await;
+    }
+}
+
+/// Applies `hysteresis`: cancels a pending transition that didn't last and, if the new status
+/// has a minimum duration, schedules announcing it. Returns the status to announce the
+/// change from now, or `None` if it's held back.
+fn hold_back(
+    client: &Client,
+    state: &AppState,
+    previous: Status,
+    cause: &str,
+    payload_id: Option<&str>,
+) -> Option<Status> {
+    let status = state.decide().status;
+    let mut previous = previous;
+    let mut pending = state
+        .pending_transition
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    if pending
+        .as_ref()
+        .is_some_and(|transition| transition.to == status)
+    {
+        return None;
+    }
+    if let Some(transition) = pending.take() {
+        transition.task.abort();
+        info!(
+            "[HYSTERESIS] {:?} didn't last, not announcing it",
+            transition.to
+        );
+        previous = transition.from;
+    }
+
+    let hold_secs = state
+        .settings()
+        .hysteresis
+        .as_ref()
+        .map_or(0, |hysteresis| hysteresis.min_secs(status));
+    if status == previous || hold_secs == 0 {
+        return Some(previous);
+    }
+    info!(
+        "[HYSTERESIS] Announcing {:?} if it lasts {}s",
+        status, hold_secs
+    );
+    let task = tokio::spawn({
+        let (client, state) = (client.clone(), state.clone());
+        let (cause, payload_id) = (cause.to_string(), payload_id.map(str::to_string));
+        async move {
+            tokio::time::sleep(Duration::from_secs(hold_secs)).await;
+            let transition = state
+                .pending_transition
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .take();
+            if let Some(transition) = transition {
+                announce(
+                    &client,
+                    &state,
+                    transition.from,
+                    &cause,
+                    payload_id.as_deref(),
+                )
+                .await;
+            }
+        }
+    });
+    *pending = Some(PendingTransition {
+        from: previous,
+        to: status,
+        task: task.abort_handle(),
+    });
+    None
+}
+
+/// `publish_if_changed` without the hysteresis.
+async fn announce(
+    client: &Client,
+    state: &AppState,
+    previous: Status,
+    cause: &str,
+    payload_id: Option<&str>,
+) {
+    let decision = state.decide();
+    if decision.status != previous {
+        // The transition sinks pick it up from `state.transitions`.
+        record_transition(state, previous, decision.status, cause, payload_id);
+    }
+    // The status may stay while the title changes, e.g. with `entry_titles`.
+    let published = state
+        .published_title
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if previous.title(&state.settings()) != decision.status.title(&state.settings())
+        || published.is_some_and(|published| published != state.effective_title())
+    {
+        publish_title(client, state).await;
+    }
+}
+
+/// Publishes the effective title to Telegram, Matrix and the other title sinks, logging how
+/// the status was decided.
+pub async fn publish_title(client: &Client, state: &AppState) {
+    let decision = state.decide();
+    let dry_run = state.settings().dry_run;
+    info!(
+        "[DECISION] {:?} decided by {:?}. Opinions: {:?}",
+        decision.status,
+        decision.source,
+        state
+            .settings()
+            .precedence
+            .iter()
+            .map(|&source| (source, state.opinion(source)))
+            .collect::<Vec<_>>()
+    );
+    if dry_run {
+        info!(
+            "[DRY RUN] Not setting the title to {:?}",
+            state.effective_title()
+        );
+        return;
+    }
+    if state.sinks_paused() {
+        info!(
+            "[PAUSED] Not setting the title to {:?}",
+            state.effective_title()
+        );
+        return;
+    }
+    *state
+        .published_title
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner) = Some(state.effective_title());
+    // Set when a Telegram call fails over the bot's rights, to find out which are missing.
+    let mut check_rights = false;
+    if state.settings().status_message.is_some() {
+        match publish_status_message(client, state).await {
+            Ok(()) => state.diagnostics.ok(
+                "sink.telegram_message",
+                format!("status message set to {:?}", decision.status),
+            ),
+            Err(err) => {
+                error!("Failed to update status message: {}", err);
+                check_rights |= is_permission_problem(&err);
+                state
+                    .diagnostics
+                    .error("sink.telegram_message", err.to_string());
+            }
+        }
+    }
+
+    if let Some(topic) = &state.settings().forum_topic {
+        match edit_forum_topic(
+            client,
+            &state.settings(),
+            topic.message_thread_id,
+            &state.effective_title(),
+        )
+        .await
+        {
+            Ok(()) => state.diagnostics.ok(
+                "sink.telegram_topic",
+                format!("topic renamed to {:?}", decision.status),
+            ),
+            Err(err) => {
+                check_rights |= is_permission_problem(&err);
+                state
+                    .diagnostics
+                    .error("sink.telegram_topic", err.to_string());
+            }
+        }
+    }
+
+    if state.settings().renames_chat() {
+        match set_chat_title(client, &state.settings(), &state.effective_title()).await {
+            Ok(()) => state.diagnostics.ok(
+                "sink.telegram",
+                format!("title set to {:?}", decision.status),
+            ),
+            Err(err) => {
+                check_rights |= is_permission_problem(&err);
+                state.diagnostics.error("sink.telegram", err.to_string());
+            }
+        }
+    }
+
+    if let Some(matrix) = &state.settings().matrix {
+        match set_room_title(client, matrix, &state.effective_title()).await {
+            Ok(()) => state
+                .diagnostics
+                .ok("sink.matrix", format!("room set to {:?}", decision.status)),
+            Err(err) => {
+                error!("Failed to update Matrix room: {}", err);
+                state.diagnostics.error("sink.matrix", err.to_string());
+            }
+        }
+    }
+
+    if let Some(emoji) = &state.settings().emoji_status {
+        let emoji_id = match decision.status {
+            Status::Busy | Status::ForgotTimer => emoji.busy.as_deref(),
+            Status::Break => emoji.on_break.as_deref(),
+            Status::NotWorking => emoji.not_working.as_deref(),
+        };
+        match set_user_emoji_status(client, &state.settings(), emoji.user_id, emoji_id).await {
+            Ok(()) => state.diagnostics.ok(
+                "sink.telegram_emoji",
+                format!("emoji status set to {:?}", decision.status),
+            ),
+            Err(err) => {
+                error!("Failed to set emoji status: {}", err);
+                state
+                    .diagnostics
+                    .error("sink.telegram_emoji", err.to_string());
+            }
+        }
+    }
+
+    if let Some(photos) = &state.settings().chat_photos {
+        if let Err(err) = update_chat_photo(
+            client,
+            &state.settings(),
+            photos,
+            &state.chat_photos,
+            decision.status,
+        )
+        .await
+        {
+            error!("Failed to update chat photo: {}", err);
+            check_rights |= is_permission_problem(&err);
+            state
+                .diagnostics
+                .error("sink.telegram_photo", err.to_string());
+        }
+    }
+
+    if let Some(descriptions) = &state.settings().chat_description {
+        let template = match decision.status {
+            Status::Busy | Status::ForgotTimer => descriptions.busy.as_deref(),
+            Status::Break => descriptions.on_break.as_deref(),
+            Status::NotWorking => descriptions.not_working.as_deref(),
+        };
+        let description = state.render_template(template.unwrap_or_default());
+        match set_chat_description(client, &state.settings(), &description).await {
+            Ok(()) => state.diagnostics.ok(
+                "sink.telegram_description",
+                format!("description set for {:?}", decision.status),
+            ),
+            Err(err) => {
+                check_rights |= is_permission_problem(&err);
+                state
+                    .diagnostics
+                    .error("sink.telegram_description", err.to_string());
+            }
+        }
+    }
+
+    if let Some(deep_focus) = &state.settings().deep_focus {
+        update_deep_focus(client, state, deep_focus).await;
+    }
+
+    if state.settings().status_panel.is_some() {
+        match update_status_panel(client, state).await {
+            Ok(()) => state.diagnostics.ok(
+                "sink.status_panel",
+                format!("panel shows {:?}", decision.status),
+            ),
+            Err(err) => {
+                error!("Failed to update the status panel: {}", err);
+                state
+                    .diagnostics
+                    .error("sink.status_panel", err.to_string());
+            }
+        }
+    }
+
+    if check_rights {
+        verify_bot_rights(client, state).await;
+    }
+}
+
+fn record_transition(
+    state: &AppState,
+    from: Status,
+    to: Status,
+    cause: &str,
+    payload_id: Option<&str>,
+) {
+    let transition = Transition {
+        at: state.clock.now(),
+        from,
+        to,
+        cause: cause.to_string(),
+        payload_id: payload_id.map(str::to_string),
+    };
+    state.status_since.store(transition.at, Ordering::Relaxed);
+    if let Some(history) = &state.history {
+        if let Err(err) = history.record(&transition) {
+            error!("Failed to record status transition: {}", err);
+            state.diagnostics.error("history", err.to_string());
+        }
+    }
+    // No subscribers is fine.
+    let _ = state.transitions.send(transition);
+}
+
+/// Edits the pinned status message, sending and pinning one first if we don't have it yet.
+async fn publish_status_message(client: &Client, state: &AppState) -> anyhow::Result<()> {
+    let Some(settings) = &state.settings().status_message else {
+        return Ok(());
+    };
+
+    let since = local_time(state.settings().timezone, state.clock.now())
+        .format("%H:%M")
+        .to_string();
+    let text = settings
+        .template
+        .as_deref()
+        .unwrap_or(state.settings().text(Text::StatusMessage))
+        .replace("{title}", &state.effective_title())
+        .replace("{since}", &since);
+
+    let message_id = *state
+        .status_message_id
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner);
+    match message_id {
+        Some(message_id) => edit_message_text(client, &state.settings(), message_id, &text).await,
+        None => {
+            let message_id = send_and_pin_message(client, &state.settings(), &text).await?;
+            info!(
+                "Pinned a new status message. Set status_message.message_id: {} to keep using it after restarts.",
+                message_id
+            );
+            *state
+                .status_message_id
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner) = Some(message_id);
+            Ok(())
+        }
+    }
+}
+
+/// Seconds since the UNIX epoch, or `Error::Clock` if the system clock is set before it.
+pub fn get_unix_timestamp() -> Result<u64, Error> {
+    Ok(SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs())
+}
+
+/// Republishes titles using `{elapsed}` or `{back_at}` every `title_refresh_minutes` while
+/// the status stays the same, whenever the rendered title has changed since.
+pub async fn title_refresher(state: AppState, shutdown_signal: Arc<tokio::sync::Notify>) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(
+        state.settings().title_refresh_minutes * 60,
+    ));
+    // The first tick is immediate; the title was just published anyway.
+    interval.tick().await;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down title_refresher");
+                break;
+            }
+        }
+
+        let title = state.effective_title();
+        let published = state
+            .published_title
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        if published.is_none() || published.as_deref() == Some(title.as_str()) {
+            continue;
+        }
+        info!("[TITLE REFRESH] {:?}", title);
+        publish_title(&client, &state).await;
+    }
+}
+
+pub async fn afk_status_updater(state: AppState, shutdown_signal: Arc<tokio::sync::Notify>) {
+    let settings = state.settings();
+    let mut interval = interval(Duration::from_secs(settings.afk_check_secs));
+    let client = Client::new();
+    let mut was_off_hours = state.off_hours();
+    let mut afk_tier = state.afk_tier(state.decide());
+    let mut end_of_day_checked = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down afk_status_updater");
+                break;
+            }
+        }
+
+        // Rather than time out every break against a clock that can't tell.
+        if let Err(err) = state.clock.try_now() {
+            error!("Skipping the AFK check: {}", err);
+            continue;
+        }
+
+        sync_out_of_office(&client, &state).await;
+
+        let off_hours = state.off_hours();
+        if off_hours != was_off_hours {
+            was_off_hours = off_hours;
+            // The status decided just before the boundary was crossed.
+            let previous = if off_hours {
+                state.decide_by_precedence().status
+            } else {
+                Status::NotWorking
+            };
+            info!(
+                "[SCHEDULE] {}",
+                if off_hours {
+                    "Work hours are over"
+                } else {
+                    "Work hours have started"
+                }
+            );
+            // Out of office, the schedule changes nothing.
+            if state.out_of_office().is_none() {
+                publish_if_changed(&client, &state, previous, "schedule", None).await;
+            }
+        }
+
+        if let Some(long_entry) = settings.long_entry.as_ref().filter(|_| !off_hours) {
+            check_long_running_entry(&client, &state, long_entry).await;
+        }
+
+        let today = day_of(
+            state.clock.now(),
+            settings.day_start_hour,
+            settings.timezone,
+        );
+        let finished = state
+            .daily_totals
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .roll_over(today);
+        if let Some(finished) = finished {
+            info!(
+                "[DAY ROLLOVER] {:?}: busy {} min, break {} min",
+                finished.day,
+                finished.busy_secs / 60,
+                finished.break_secs / 60
+            );
+            if state.done_for_today.load(Ordering::Relaxed) {
+                set_done_for_today(&client, &state, false).await;
+            }
+        }
+
+        if let Some(end_of_day) = &settings.end_of_day {
+            let now = local_time(settings.timezone, state.clock.now());
+            let due = end_of_day.time().is_some_and(|at| now.time() >= at);
+            if due && end_of_day_checked != Some(today) {
+                end_of_day_checked = Some(today);
+                check_end_of_day(&client, &state, end_of_day).await;
+            }
+        }
+
+        let override_until = state.override_until.load(Ordering::Relaxed);
+        if override_until != 0 && state.clock.now() >= override_until {
+            info!("[OVERRIDE] Expired");
+            let previous = state.decide().status;
+            state.set_override(None, None);
+            publish_if_changed(&client, &state, previous, "override_expired", None).await;
+        }
+
+        let tier = state.afk_tier(state.decide());
+        if tier != afk_tier {
+            afk_tier = tier;
+            if let Some(tier) = tier {
+                info!(
+                    "[AFK TIER] {} minutes since the timer stopped",
+                    settings.afk_tiers[tier].after_minutes
+                );
+                publish_title(&client, &state).await;
+            }
+        }
+
+        let last_break = state.last_break_start.load(Ordering::Relaxed);
+        if last_break == 0 {
+            continue;
+        }
+
+        let current_time = state.clock.now();
+        let afk_after = settings.afk_after_secs();
+        if current_time > last_break + afk_after {
+            if settings.idle_detection {
+                let idle = system_idle_seconds().await;
+                match idle {
+                    Some(idle) => state
+                        .diagnostics
+                        .ok("source.idle", format!("last input {}s ago", idle)),
+                    None => state
+                        .diagnostics
+                        .error("source.idle", "idle time is unavailable on this system"),
+                }
+                if let Some(idle) = idle {
+                    if idle < afk_after {
+                        info!(
+                            "Toggl timer is stopped, but last input was {}s ago. Holding off AFK...",
+                            idle
+                        );
+                        continue;
+                    }
+                }
+            }
+
+            info!(
+                "[SETTING NOT_WORKING] Break lasted longer than {} minutes",
+                afk_after / 60
+            );
+            let previous = state.decide().status;
+            state
+                .daily_totals
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .record(
+                    IntervalKind::Break,
+                    last_break,
+                    current_time,
+                    settings.day_start_hour,
+                    settings.timezone,
+                );
+            state.last_break_start.store(0, Ordering::Relaxed);
+            state.afk_since.store(current_time, Ordering::Relaxed);
+
+            publish_if_changed(&client, &state, previous, "afk_timeout", None).await;
+            afk_tier = state.afk_tier(state.decide());
+        }
+    }
+}
+
+/// At `end_of_day.at`: calls it a day if no timer is running, or nudges you to stop it.
+async fn check_end_of_day(client: &Client, state: &AppState, end_of_day: &EndOfDaySettings) {
+    let settings = state.settings();
+    let started_at = state.timer_started_at.load(Ordering::Relaxed);
+    if started_at == 0 {
+        info!("[END OF DAY] No timer running, done for today");
+        set_done_for_today(client, state, true).await;
+        return;
+    }
+
+    let text = end_of_day
+        .overwork_message
+        .as_deref()
+        .unwrap_or(settings.text(Text::OverworkMessage))
+        .replace("{at}", &end_of_day.at)
+        .replace(
+            "{elapsed}",
+            &format_elapsed(state.clock.now().saturating_sub(started_at)),
+        );
+    warn!("[END OF DAY] The timer is still running");
+    let chat_id = end_of_day
+        .chat_id
+        .as_ref()
+        .or(settings.admin_alerts.as_ref().map(|alerts| &alerts.chat_id));
+    if let Some(chat_id) = chat_id.filter(|_| !settings.dry_run) {
+        send_message(client, &settings, chat_id, &text).await;
+    }
+}
+
+/// Enters or leaves "done for today". The status stays Not Working when it was already, so
+/// the title is published either way.
+async fn set_done_for_today(client: &Client, state: &AppState, done: bool) {
+    let previous = state.decide().status;
+    state.done_for_today.store(done, Ordering::Relaxed);
+    let cause = if done { "end_of_day" } else { "day_rollover" };
+    publish_change(client, state, previous, cause).await;
+}
+
+/// Flags a timer that has been running longer than `max_hours`, once per entry.
+async fn check_long_running_entry(
+    client: &Client,
+    state: &AppState,
+    long_entry: &LongEntrySettings,
+) {
+    let started_at = state.timer_started_at.load(Ordering::Relaxed);
+    if started_at == 0 || state.long_entry_flagged.load(Ordering::Relaxed) {
+        return;
+    }
+
+    let current_time = state.clock.now();
+    if current_time < started_at + long_entry.max_hours * 3600 {
+        return;
+    }
+
+    let previous = state.decide().status;
+    state.long_entry_flagged.store(true, Ordering::Relaxed);
+    warn!(
+        "[LONG ENTRY] Timer has been running for more than {} hours. Forgot to stop it?",
+        long_entry.max_hours
+    );
+
+    if let Some(alert_chat_id) = long_entry
+        .alert_chat_id
+        .as_ref()
+        .filter(|_| !state.settings().dry_run)
+    {
+        let text = long_entry_alert(&state.settings(), long_entry.max_hours);
+        send_message(client, &state.settings(), alert_chat_id, &text).await;
+    }
+
+    publish_if_changed(client, state, previous, "long_entry", None).await;
+}
+
+/// The message to `long_entry.alert_chat_id`, in the settings' language.
+fn long_entry_alert(settings: &Settings, max_hours: u64) -> String {
+    settings
+        .text(Text::LongEntryAlert)
+        .replace("{hours}", &max_hours.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::ManualClock;
+
+    /// 2023-11-15 03:55 UTC, five minutes before the day starts with `day_start_hour = 4`.
+    const NOW: u64 = 1_700_020_500;
+
+    fn state_at(clock: &Arc<ManualClock>, toml: &str) -> AppState {
+        let toml = format!("dry_run = true\ntimezone = \"UTC\"\n{}", toml);
+        AppState::with_clock(Settings::for_tests(&toml), clock.clone())
+    }
+
+    /// Moves the state's clock and tokio's along by `secs`, then lets the background tasks run.
+    async fn advance(clock: &ManualClock, secs: u64) {
+        // Lets spawned tasks start their sleeps before the time moves.
+        tokio::task::yield_now().await;
+        clock.advance(secs);
+        tokio::time::advance(Duration::from_secs(secs)).await;
+        tokio::task::yield_now().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn break_turns_into_afk_after_minutes_till_afk() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let state = state_at(&clock, "afk_check_secs = 60");
+        state.last_break_start.store(NOW, Ordering::Relaxed);
+        state.timer_stopped_at.store(NOW, Ordering::Relaxed);
+        let shutdown_signal = Arc::new(tokio::sync::Notify::new());
+        let updater = tokio::spawn(afk_status_updater(state.clone(), shutdown_signal.clone()));
+        tokio::task::yield_now().await;
+
+        for _ in 0..10 {
+            advance(&clock, 60).await;
+        }
+        assert_eq!(state.decide().status, Status::Break);
+        assert_eq!(state.afk_since.load(Ordering::Relaxed), 0);
+
+        advance(&clock, 60).await;
+        assert_eq!(
+            state.decide(),
+            Decision {
+                status: Status::NotWorking,
+                source: StatusSource::Idle,
+            }
+        );
+        assert_eq!(state.afk_since.load(Ordering::Relaxed), NOW + 660);
+        assert_eq!(state.last_break_start.load(Ordering::Relaxed), 0);
+
+        shutdown_signal.notify_one();
+        updater.await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn break_across_the_day_start_counts_towards_the_new_day() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let state = state_at(&clock, "afk_check_secs = 60\nday_start_hour = 4");
+        state.last_break_start.store(NOW, Ordering::Relaxed);
+        state.timer_stopped_at.store(NOW, Ordering::Relaxed);
+        let shutdown_signal = Arc::new(tokio::sync::Notify::new());
+        let updater = tokio::spawn(afk_status_updater(state.clone(), shutdown_signal.clone()));
+        tokio::task::yield_now().await;
+
+        for _ in 0..11 {
+            advance(&clock, 60).await;
+        }
+        let totals = state
+            .daily_totals
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone();
+        assert_eq!(totals.day, chrono::NaiveDate::from_ymd_opt(2023, 11, 15));
+        // 03:55 to 04:06, of which the five minutes before 04:00 were yesterday's.
+        assert_eq!(totals.break_secs, 6 * 60);
+
+        shutdown_signal.notify_one();
+        updater.await.unwrap();
+    }
+
+    #[test]
+    fn intervals_split_at_the_day_start() {
+        let timezone = Some(chrono_tz::UTC);
+        let pieces = split_at_day_start(NOW, NOW + 660, 4, timezone);
+        assert_eq!(
+            pieces,
+            vec![
+                (
+                    chrono::NaiveDate::from_ymd_opt(2023, 11, 14).unwrap(),
+                    NOW,
+                    NOW + 300
+                ),
+                (
+                    chrono::NaiveDate::from_ymd_opt(2023, 11, 15).unwrap(),
+                    NOW + 300,
+                    NOW + 660
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn long_entry_alert_follows_the_locale() {
+        let alert = |toml| long_entry_alert(&Settings::for_tests(toml), 8);
+        assert_eq!(
+            alert(""),
+            "Your Toggl timer has been running for more than 8 hours. Did you forget to stop it?"
+        );
+        assert_eq!(
+            alert("locale = \"ru\""),
+            "Таймер Toggl идёт уже больше 8 ч. Может, забыли его остановить?"
+        );
+        assert_eq!(
+            alert("locale = \"de\""),
+            "Dein Toggl-Timer läuft seit mehr als 8 Stunden. Vergessen, ihn zu stoppen?"
+        );
+        // `messages` comes before the locale.
+        assert_eq!(
+            alert("locale = \"de\"\n[messages]\nlong_entry_alert = \"{hours}h, really?\""),
+            "8h, really?"
+        );
+    }
+
+    #[test]
+    fn reloaded_settings_keep_a_forced_dry_run() {
+        let mut settings = Settings::for_tests("");
+        settings.force_dry_run();
+        let state = AppState::with_clock(settings, Arc::new(ManualClock::new(NOW)));
+        state.replace_settings(Settings::for_tests("dry_run = false"));
+        assert!(state.settings().dry_run);
+
+        let state = AppState::with_clock(Settings::for_tests(""), Arc::new(ManualClock::new(NOW)));
+        state.replace_settings(Settings::for_tests("dry_run = true"));
+        state.replace_settings(Settings::for_tests(""));
+        assert!(!state.settings().dry_run);
+    }
+
+    #[test]
+    fn precedence_picks_the_first_opinion() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let state = state_at(&clock, "");
+        assert_eq!(
+            state.decide(),
+            Decision {
+                status: Status::NotWorking,
+                source: StatusSource::Idle,
+            }
+        );
+
+        state.last_break_start.store(NOW, Ordering::Relaxed);
+        assert_eq!(state.decide().source, StatusSource::Timer);
+        assert_eq!(state.decide().status, Status::Break);
+
+        // A meeting comes before the timer by default, and an override before both.
+        state.zoom_busy.store(true, Ordering::Relaxed);
+        assert_eq!(
+            state.decide(),
+            Decision {
+                status: Status::Busy,
+                source: StatusSource::Meeting,
+            }
+        );
+        state.set_override(Some(Status::NotWorking), None);
+        assert_eq!(
+            state.decide(),
+            Decision {
+                status: Status::NotWorking,
+                source: StatusSource::Override,
+            }
+        );
+    }
+
+    #[test]
+    fn precedence_follows_the_settings() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let state = state_at(&clock, "precedence = [\"timer\", \"meeting\", \"idle\"]");
+        state.last_break_start.store(NOW, Ordering::Relaxed);
+        state.zoom_busy.store(true, Ordering::Relaxed);
+        assert_eq!(
+            state.decide_by_precedence(),
+            Decision {
+                status: Status::Break,
+                source: StatusSource::Timer,
+            }
+        );
+    }
+
+    #[test]
+    fn schedule_comes_before_the_precedence() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let state = state_at(&clock, "");
+        state.set_override(Some(Status::Busy), None);
+        state.done_for_today.store(true, Ordering::Relaxed);
+        assert_eq!(
+            state.decide(),
+            Decision {
+                status: Status::NotWorking,
+                source: StatusSource::Schedule,
+            }
+        );
+        assert_eq!(state.decide_by_precedence().source, StatusSource::Override);
+    }
+
+    const TITLES: &str = r#"
+busy_chat_status = "Busy"
+break_chat_status = "On a break"
+not_working_status = "Away"
+"#;
+
+    #[test]
+    fn effective_title_follows_the_decision() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let toml = format!(
+            "{}\n[[entry_titles]]\npattern = \"^Review\"\ntitle = \"Reviewing for {{elapsed}}\"",
+            TITLES
+        );
+        let state = state_at(&clock, &toml);
+        assert_eq!(state.effective_title(), "Away");
+
+        state.timer_started_at.store(NOW, Ordering::Relaxed);
+        assert_eq!(state.effective_title(), "Busy");
+
+        *state
+            .current_entry
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = Some(CurrentEntry {
+            description: Some("Review the PR".to_string()),
+            ..CurrentEntry::default()
+        });
+        state.status_since.store(NOW - 3900, Ordering::Relaxed);
+        assert_eq!(state.effective_title(), "Reviewing for 1h 05m");
+
+        // Only the timer's Busy gets an entry title.
+        state.set_override(Some(Status::Break), None);
+        assert_eq!(state.effective_title(), "On a break");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hysteresis_drops_a_change_that_doesnt_last() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let state = state_at(&clock, "[hysteresis]\nbreak_secs = 60");
+        let client = Client::new();
+        let mut transitions = state.transitions.subscribe();
+
+        state.last_break_start.store(NOW, Ordering::Relaxed);
+        publish_if_changed(&client, &state, Status::Busy, "toggl_stop", None).await;
+        assert_eq!(state.decide().status, Status::Break);
+        assert_eq!(state.announced_status(), Status::Busy);
+
+        advance(&clock, 30).await;
+        state.timer_started_at.store(NOW + 30, Ordering::Relaxed);
+        state.last_break_start.store(0, Ordering::Relaxed);
+        publish_if_changed(&client, &state, Status::Break, "toggl_start", None).await;
+        assert!(state
+            .pending_transition
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .is_none());
+
+        advance(&clock, 60).await;
+        assert_eq!(state.announced_status(), Status::Busy);
+        assert!(transitions.try_recv().is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn hysteresis_announces_a_change_that_lasts() {
+        let clock = Arc::new(ManualClock::new(NOW));
+        let state = state_at(&clock, "[hysteresis]\nbreak_secs = 60");
+        let client = Client::new();
+        let mut transitions = state.transitions.subscribe();
+
+        state.last_break_start.store(NOW, Ordering::Relaxed);
+        publish_if_changed(&client, &state, Status::Busy, "toggl_stop", None).await;
+        advance(&clock, 59).await;
+        assert!(transitions.try_recv().is_err());
+
+        advance(&clock, 1).await;
+        let transition = transitions.try_recv().unwrap();
+        assert_eq!(
+            (transition.from, transition.to, transition.cause.as_str()),
+            (Status::Busy, Status::Break, "toggl_stop")
+        );
+        assert_eq!(state.announced_status(), Status::Break);
+    }
+}
@@ -0,0 +1,154 @@
+use crate::{
+    clock::local_time,
+    config::DailySummarySettings,
+    i18n::Text,
+    sinks::telegram::send_message,
+    sources::toggl::fetch_time_entries,
+    state::{day_of, day_start_timestamp, AppState, DailyTotals},
+};
+use anyhow::Result;
+use reqwest::Client;
+use std::{
+    sync::{Arc, PoisonError},
+    time::Duration,
+};
+use tokio::time::interval;
+use tracing::{error, info};
+
+pub(crate) fn format_duration(secs: u64) -> String {
+    format!("{}h {:02}m", secs / 3600, secs % 3600 / 60)
+}
+
+/// Overrides the locally recorded busy figures with what Toggl itself has for the day,
+/// which also covers entries whose webhooks we missed.
+async fn totals_from_toggl(
+    client: &Client,
+    state: &AppState,
+    api_token: &str,
+    totals: &mut DailyTotals,
+) -> Result<()> {
+    let Some(day) = totals.day else {
+        return Ok(());
+    };
+    let day_start_hour = state.settings().day_start_hour;
+    let timezone = state.settings().timezone;
+    let to_rfc3339 = |timestamp: Option<u64>| {
+        timestamp
+            .and_then(|timestamp| chrono::DateTime::from_timestamp(timestamp as i64, 0))
+            .map(|time| time.to_rfc3339())
+            .ok_or_else(|| anyhow::anyhow!("invalid day boundary for {}", day))
+    };
+    let start = to_rfc3339(day_start_timestamp(day, day_start_hour, timezone))?;
+    let end = to_rfc3339(
+        day.succ_opt()
+            .and_then(|next| day_start_timestamp(next, day_start_hour, timezone)),
+    )?;
+
+    let entries = fetch_time_entries(client, api_token, &start, &end).await?;
+    let now = state.clock.now() as i64;
+    let durations = entries.iter().map(|entry| {
+        if entry.duration >= 0 {
+            entry.duration as u64
+        } else {
+            // Running entries report -start_timestamp as their duration.
+            (now + entry.duration).max(0) as u64
+        }
+    });
+
+    totals.busy_secs = durations.clone().sum();
+    totals.longest_busy_secs = durations.max().unwrap_or_default();
+    totals.entries = entries.len() as u32;
+    Ok(())
+}
+
+pub async fn build_summary(client: &Client, state: &AppState) -> String {
+    let mut totals = state
+        .daily_totals
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+        .clone();
+    if let Some(api_token) = &state.settings().toggl_api_token {
+        if let Err(err) = totals_from_toggl(client, state, api_token, &mut totals).await {
+            error!(
+                "Failed to fetch today's entries from Toggl, using local totals: {}",
+                err
+            );
+        }
+    }
+
+    let status_changes = totals
+        .day
+        .and_then(|day| {
+            let settings = state.settings();
+            let (day_start_hour, timezone) = (settings.day_start_hour, settings.timezone);
+            let start = day_start_timestamp(day, day_start_hour, timezone)?;
+            let end = day
+                .succ_opt()
+                .and_then(|next| day_start_timestamp(next, day_start_hour, timezone))?;
+            state.history.as_ref()?.between(start, end).ok()
+        })
+        .map(|transitions| transitions.len())
+        .unwrap_or_default();
+
+    let settings = state.settings();
+    settings
+        .text(Text::Summary)
+        .replace(
+            "{day}",
+            &totals
+                .day
+                .map(|day| day.to_string())
+                .unwrap_or_else(|| settings.text(Text::Today).to_string()),
+        )
+        .replace("{tracked}", &format_duration(totals.busy_secs))
+        .replace("{entries}", &totals.entries.to_string())
+        .replace("{longest}", &format_duration(totals.longest_busy_secs))
+        .replace("{breaks}", &format_duration(totals.break_secs))
+        .replace("{afk}", &format_duration(totals.afk_secs))
+        .replace("{changes}", &status_changes.to_string())
+}
+
+/// Posts the daily summary once a day, as soon as the local time passes `summary.at`.
+pub async fn daily_summary_poster(
+    state: AppState,
+    summary: DailySummarySettings,
+    shutdown_signal: Arc<tokio::sync::Notify>,
+) {
+    let at = match chrono::NaiveTime::parse_from_str(&summary.at, "%H:%M") {
+        Ok(at) => at,
+        Err(err) => {
+            error!("Invalid daily_summary.at {:?}: {}", summary.at, err);
+            return;
+        }
+    };
+    let client = Client::new();
+    let chat_id = summary
+        .chat_id
+        .clone()
+        .unwrap_or_else(|| state.settings().chat_id.clone());
+    let mut interval = interval(Duration::from_secs(30));
+    let mut last_posted = None;
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Shutting down daily_summary_poster");
+                break;
+            }
+        }
+
+        let now = state.clock.now();
+        let settings = state.settings();
+        let local_now = local_time(settings.timezone, now);
+        let today = day_of(now, settings.day_start_hour, settings.timezone);
+        if local_now.time() < at || last_posted == Some(today) {
+            continue;
+        }
+
+        let text = build_summary(&client, &state).await;
+        info!("[DAILY SUMMARY] {}", text);
+        send_message(&client, &state.settings(), &chat_id, &text).await;
+        last_posted = Some(today);
+    }
+}
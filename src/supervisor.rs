@@ -0,0 +1,112 @@
+use crate::state::AppState;
+use std::{
+    any::Any,
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{sync::Notify, task::JoinHandle};
+use tracing::{error, info};
+
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// A task that ran this long before panicking gets restarted right away again.
+const STABLE_AFTER: Duration = Duration::from_secs(300);
+
+/// Restarts background tasks that panic (e.g. on an `unwrap()`) instead of letting them die
+/// silently, with a growing delay if they keep panicking. The tasks stop on `shutdown_signal`;
+/// one that returns is done.
+#[derive(Clone)]
+pub struct Supervisor {
+    state: AppState,
+    shutdown_signal: Arc<Notify>,
+    stopping: Arc<AtomicBool>,
+    /// Wakes tasks waiting to be restarted when stopping.
+    stopped: Arc<Notify>,
+}
+
+impl Supervisor {
+    pub fn new(state: AppState, shutdown_signal: Arc<Notify>) -> Self {
+        Supervisor {
+            state,
+            shutdown_signal,
+            stopping: Arc::new(AtomicBool::new(false)),
+            stopped: Arc::new(Notify::new()),
+        }
+    }
+
+    /// Spawns `task(state, shutdown_signal)`, and spawns it again whenever it panics.
+    pub fn spawn<F, Fut>(&self, name: &'static str, task: F) -> JoinHandle<()>
+    where
+        F: Fn(AppState, Arc<Notify>) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            let subsystem = format!("task.{}", name);
+            let mut delay = MIN_RESTART_DELAY;
+            loop {
+                let started_at = Instant::now();
+                let run = tokio::spawn(task(
+                    supervisor.state.clone(),
+                    supervisor.shutdown_signal.clone(),
+                ));
+                let message = match run.await {
+                    Ok(()) => return,
+                    Err(err) if err.is_cancelled() => return,
+                    Err(err) => panic_message(err.into_panic()),
+                };
+                if started_at.elapsed() >= STABLE_AFTER {
+                    delay = MIN_RESTART_DELAY;
+                }
+                error!(
+                    "{} panicked: {}. Restarting it in {}s",
+                    name,
+                    message,
+                    delay.as_secs()
+                );
+                supervisor
+                    .state
+                    .diagnostics
+                    .error(&subsystem, format!("panicked: {}", message));
+
+                let stopped = supervisor.stopped.notified();
+                tokio::pin!(stopped);
+                stopped.as_mut().enable();
+                if supervisor.stopping.load(Ordering::Relaxed) {
+                    return;
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(delay) => {}
+                    _ = stopped => return,
+                }
+                delay = (delay * 2).min(MAX_RESTART_DELAY);
+                info!("Restarting {}", name);
+                supervisor
+                    .state
+                    .diagnostics
+                    .ok(&subsystem, "restarted after a panic");
+            }
+        })
+    }
+
+    /// Tells the tasks to stop, and not to restart those waiting to be.
+    pub fn stop(&self) {
+        self.stopping.store(true, Ordering::Relaxed);
+        self.stopped.notify_waiters();
+        self.shutdown_signal.notify_waiters();
+    }
+}
+
+fn panic_message(panic: Box<dyn Any + Send>) -> String {
+    match panic.downcast::<String>() {
+        Ok(message) => *message,
+        Err(panic) => match panic.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "unknown panic".to_string(),
+        },
+    }
+}
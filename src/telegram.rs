@@ -0,0 +1,364 @@
+use reqwest::{multipart, Client, StatusCode};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::time::Duration;
+use tracing::warn;
+
+/// The longest chat title or forum topic name Telegram takes, in UTF-16 code units like it
+/// counts them.
+pub const MAX_TITLE_LEN: usize = 128;
+
+/// The longest chat description Telegram takes.
+pub const MAX_DESCRIPTION_LEN: usize = 255;
+
+/// Rate limits up to this long are waited out and retried once; longer ones are errors.
+const MAX_RETRY_WAIT: Duration = Duration::from_secs(10);
+
+/// The envelope every Bot API method answers with, successful or not.
+#[derive(Debug, Deserialize)]
+struct ApiResponse<T> {
+    ok: bool,
+    result: Option<T>,
+    description: Option<String>,
+    error_code: Option<u16>,
+    #[serde(default)]
+    parameters: ResponseParameters,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ResponseParameters {
+    retry_after: Option<u64>,
+    migrate_to_chat_id: Option<i64>,
+}
+
+/// The parts of a `Message` we use.
+#[derive(Debug, Deserialize)]
+pub struct Message {
+    pub message_id: i64,
+    #[serde(default)]
+    pub chat: Option<Chat>,
+    /// Missing for messages sent on behalf of a channel or the group itself.
+    #[serde(default)]
+    pub from: Option<User>,
+    #[serde(default)]
+    pub text: Option<String>,
+    #[serde(default)]
+    pub reply_to_message: Option<Box<Message>>,
+}
+
+/// The parts of a `Chat` we use.
+#[derive(Debug, Deserialize)]
+pub struct Chat {
+    pub id: i64,
+    /// `private`, `group`, `supergroup` or `channel`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+impl Chat {
+    /// Whether this is the chat `chat_id` (a numeric ID or an `@username`) refers to.
+    pub fn is(&self, chat_id: &str) -> bool {
+        chat_id == self.id.to_string()
+            || self
+                .username
+                .as_ref()
+                .is_some_and(|username| chat_id.eq_ignore_ascii_case(&format!("@{}", username)))
+    }
+}
+
+/// The parts of an `Update` we use.
+#[derive(Debug, Deserialize)]
+pub struct Update {
+    pub update_id: i64,
+    #[serde(default)]
+    pub message: Option<Message>,
+    #[serde(default)]
+    pub callback_query: Option<CallbackQuery>,
+}
+
+/// A press of an inline keyboard button.
+#[derive(Debug, Deserialize)]
+pub struct CallbackQuery {
+    pub id: String,
+    pub from: User,
+    #[serde(default)]
+    pub data: Option<String>,
+}
+
+/// The parts of a `User` we use.
+#[derive(Debug, Deserialize)]
+pub struct User {
+    pub id: i64,
+    #[serde(default)]
+    pub is_bot: bool,
+    #[serde(default)]
+    pub username: Option<String>,
+}
+
+/// A `ChatMember` of any kind, flattened. The rights are only there for administrators.
+#[derive(Debug, Deserialize)]
+pub struct ChatMember {
+    /// `creator`, `administrator`, `member`, `restricted`, `left` or `kicked`.
+    pub status: String,
+    #[serde(default)]
+    pub can_change_info: bool,
+    #[serde(default)]
+    pub can_pin_messages: bool,
+    #[serde(default)]
+    pub can_manage_topics: bool,
+}
+
+/// A failed Bot API call, with what to do about it where Telegram tells us enough.
+#[derive(Debug, thiserror::Error)]
+pub enum TelegramError {
+    #[error("{method}: the bot token was rejected, check `bot_token`")]
+    Unauthorized { method: &'static str },
+    #[error("{method}: chat not found, check `chat_id` and that the bot is in the chat")]
+    ChatNotFound { method: &'static str },
+    #[error("{method}: the bot was removed from the chat ({description}), add it back")]
+    BotKicked {
+        method: &'static str,
+        description: String,
+    },
+    #[error("{method}: the bot lacks the admin rights for this ({description})")]
+    NotEnoughRights {
+        method: &'static str,
+        description: String,
+    },
+    #[error("{method}: the text is too long for Telegram ({description})")]
+    TooLong {
+        method: &'static str,
+        description: String,
+    },
+    #[error("{method}: the group was upgraded to a supergroup, set `chat_id` to {chat_id}")]
+    ChatMigrated { method: &'static str, chat_id: i64 },
+    #[error("{method}: rate limited by Telegram, retry after {retry_after}s")]
+    RetryAfter {
+        method: &'static str,
+        retry_after: u64,
+    },
+    /// The chat already shows this, e.g. `setChatTitle` with the current title.
+    #[error("{method}: nothing to change")]
+    NotModified { method: &'static str },
+    #[error("{method} failed with {code}: {description}")]
+    Api {
+        method: &'static str,
+        code: u16,
+        description: String,
+    },
+    #[error("{method} request failed: {source}")]
+    Http {
+        method: &'static str,
+        source: reqwest::Error,
+    },
+}
+
+impl TelegramError {
+    /// Whether the bot's membership or admin rights in the chat are the problem.
+    pub fn is_permission_problem(&self) -> bool {
+        matches!(
+            self,
+            TelegramError::BotKicked { .. }
+                | TelegramError::NotEnoughRights { .. }
+                | TelegramError::ChatNotFound { .. }
+        )
+    }
+
+    fn from_response<T>(
+        method: &'static str,
+        status: StatusCode,
+        response: ApiResponse<T>,
+    ) -> Self {
+        let code = response.error_code.unwrap_or(status.as_u16());
+        let description = response.description.unwrap_or_default();
+        let lowercase = description.to_lowercase();
+        if let Some(retry_after) = response.parameters.retry_after {
+            return TelegramError::RetryAfter {
+                method,
+                retry_after,
+            };
+        }
+        if let Some(chat_id) = response.parameters.migrate_to_chat_id {
+            return TelegramError::ChatMigrated { method, chat_id };
+        }
+        match code {
+            401 => TelegramError::Unauthorized { method },
+            403 if lowercase.contains("kicked")
+                || lowercase.contains("not a member")
+                || lowercase.contains("chat was deleted") =>
+            {
+                TelegramError::BotKicked {
+                    method,
+                    description,
+                }
+            }
+            400 if lowercase.contains("chat not found") => TelegramError::ChatNotFound { method },
+            400 | 403
+                if lowercase.contains("not enough rights")
+                    || lowercase.contains("admin_required") =>
+            {
+                TelegramError::NotEnoughRights {
+                    method,
+                    description,
+                }
+            }
+            400 if lowercase.contains("too long") || lowercase.contains("too_long") => {
+                TelegramError::TooLong {
+                    method,
+                    description,
+                }
+            }
+            400 if lowercase.contains("not modified") || lowercase.contains("not_modified") => {
+                TelegramError::NotModified { method }
+            }
+            _ => TelegramError::Api {
+                method,
+                code,
+                description,
+            },
+        }
+    }
+
+    fn http(method: &'static str, err: reqwest::Error) -> Self {
+        // The URL has the bot token in it.
+        TelegramError::Http {
+            method,
+            source: err.without_url(),
+        }
+    }
+}
+
+/// Length of `title` as Telegram counts it.
+pub fn title_len(title: &str) -> usize {
+    title.encode_utf16().count()
+}
+
+/// `title` as Telegram takes it: on one line, without control characters, and cut to
+/// `MAX_TITLE_LEN` with an ellipsis. An empty title stays empty for the caller to skip.
+pub fn sanitize_title(title: &str) -> String {
+    let title = title
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect::<String>()
+        .trim()
+        .to_string();
+    truncate(&title, MAX_TITLE_LEN)
+}
+
+/// `text` cut to `max_len` UTF-16 code units with an ellipsis, if it's longer.
+pub fn truncate(text: &str, max_len: usize) -> String {
+    if title_len(text) <= max_len {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut len = 1; // the ellipsis
+    for c in text.chars() {
+        len += c.len_utf16();
+        if len > max_len {
+            break;
+        }
+        truncated.push(c);
+    }
+    format!("{}…", truncated.trim_end())
+}
+
+/// Calls `method` with a JSON `payload`, waiting out and retrying a short rate limit once.
+pub async fn call<T: DeserializeOwned>(
+    client: &Client,
+    token: &str,
+    method: &'static str,
+    payload: &impl Serialize,
+) -> Result<T, TelegramError> {
+    let url = format!("https://api.telegram.org/bot{}/{}", token, method);
+    let mut retried = false;
+    loop {
+        let response = client
+            .post(&url)
+            .json(payload)
+            .send()
+            .await
+            .map_err(|err| TelegramError::http(method, err))?;
+        match parse(method, response).await {
+            Err(TelegramError::RetryAfter { retry_after, .. })
+                if !retried && Duration::from_secs(retry_after) <= MAX_RETRY_WAIT =>
+            {
+                warn!("{}: rate limited, retrying in {}s", method, retry_after);
+                tokio::time::sleep(Duration::from_secs(retry_after)).await;
+                retried = true;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Calls `method` with a multipart `form`, for uploads. Not retried, as the form is consumed.
+pub async fn call_multipart<T: DeserializeOwned>(
+    client: &Client,
+    token: &str,
+    method: &'static str,
+    form: multipart::Form,
+) -> Result<T, TelegramError> {
+    let url = format!("https://api.telegram.org/bot{}/{}", token, method);
+    let response = client
+        .post(&url)
+        .multipart(form)
+        .send()
+        .await
+        .map_err(|err| TelegramError::http(method, err))?;
+    parse(method, response).await
+}
+
+async fn parse<T: DeserializeOwned>(
+    method: &'static str,
+    response: reqwest::Response,
+) -> Result<T, TelegramError> {
+    let status = response.status();
+    let response: ApiResponse<T> = response
+        .json()
+        .await
+        .map_err(|err| TelegramError::http(method, err))?;
+    match response {
+        ApiResponse {
+            ok: true,
+            result: Some(result),
+            ..
+        } => Ok(result),
+        response => Err(TelegramError::from_response(method, status, response)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized_titles_are_one_trimmed_line() {
+        assert_eq!(sanitize_title("\tBusy\nfor now\u{7} "), "Busy for now");
+        assert_eq!(sanitize_title(" \n"), "");
+    }
+
+    #[test]
+    fn long_titles_are_cut_with_an_ellipsis() {
+        let title = sanitize_title(&"a".repeat(200));
+        assert_eq!(title_len(&title), MAX_TITLE_LEN);
+        assert_eq!(title, format!("{}…", "a".repeat(MAX_TITLE_LEN - 1)));
+        assert_eq!(
+            sanitize_title(&"a".repeat(MAX_TITLE_LEN)),
+            "a".repeat(MAX_TITLE_LEN)
+        );
+    }
+
+    #[test]
+    fn titles_are_cut_by_utf16_length_between_characters() {
+        // Each tomato is two UTF-16 code units, so 63 of them and the ellipsis fit.
+        let title = sanitize_title(&"🍅".repeat(100));
+        assert_eq!(title, format!("{}…", "🍅".repeat(63)));
+        assert_eq!(title_len(&title), 127);
+    }
+
+    #[test]
+    fn truncation_drops_the_trailing_space() {
+        assert_eq!(truncate("Deep work now", 6), "Deep…");
+    }
+}
@@ -0,0 +1,158 @@
+use crate::state::Status;
+use config::{Config, File};
+use std::{collections::HashMap, path::PathBuf};
+
+/// Titles and emojis for the statuses, picked by name with `theme`.
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct Theme {
+    pub busy: ThemeStatus,
+    #[serde(rename = "break")]
+    pub on_break: ThemeStatus,
+    pub not_working: ThemeStatus,
+}
+
+#[derive(Debug, Clone, serde::Deserialize)]
+pub struct ThemeStatus {
+    /// Goes in front of the title, and into `{emoji}`.
+    #[serde(default)]
+    pub emoji: String,
+    pub title: String,
+}
+
+impl ThemeStatus {
+    fn new(emoji: &str, title: &str) -> Self {
+        ThemeStatus {
+            emoji: emoji.to_string(),
+            title: title.to_string(),
+        }
+    }
+
+    /// The chat title: the emoji, if any, and the title.
+    pub fn full_title(&self) -> String {
+        if self.emoji.is_empty() {
+            self.title.clone()
+        } else {
+            format!("{} {}", self.emoji, self.title)
+        }
+    }
+}
+
+impl Theme {
+    pub fn emoji(&self, status: Status) -> &str {
+        match status {
+            Status::Busy | Status::ForgotTimer => &self.busy.emoji,
+            Status::Break => &self.on_break.emoji,
+            Status::NotWorking => &self.not_working.emoji,
+        }
+    }
+}
+
+/// The themes that come with amibussy.
+pub const BUILTIN_THEMES: [&str; 3] = ["traffic-light", "minimal", "cats"];
+
+fn builtin(name: &str) -> Option<Theme> {
+    let theme = |busy, on_break, not_working| Theme {
+        busy,
+        on_break,
+        not_working,
+    };
+    match name {
+        "traffic-light" => Some(theme(
+            ThemeStatus::new("🔴", "Busy"),
+            ThemeStatus::new("🟡", "On a break"),
+            ThemeStatus::new("⚫", "Off"),
+        )),
+        "minimal" => Some(theme(
+            ThemeStatus::new("", "Busy"),
+            ThemeStatus::new("", "Break"),
+            ThemeStatus::new("", "Away"),
+        )),
+        "cats" => Some(theme(
+            ThemeStatus::new("😼", "Hunting bugs"),
+            ThemeStatus::new("😺", "Stretching"),
+            ThemeStatus::new("😴", "Napping"),
+        )),
+        _ => None,
+    }
+}
+
+/// The theme called `name`: from `themes_file` if it has one by that name, else a built-in one.
+pub fn load_theme(name: &str, themes_file: Option<&str>) -> Result<Theme, String> {
+    if let Some(path) = themes_file {
+        let path = PathBuf::from(shellexpand::tilde(path).to_string());
+        let themes: HashMap<String, Theme> = Config::builder()
+            .add_source(File::from(path.as_path()))
+            .build()
+            .and_then(Config::try_deserialize)
+            .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+        // `config` may hand the names back lowercased.
+        let theme = themes
+            .into_iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name));
+        if let Some((_, theme)) = theme {
+            return Ok(theme);
+        }
+    }
+    builtin(name).ok_or_else(|| {
+        format!(
+            "unknown theme {:?}, expected one of {} or one from themes_file",
+            name,
+            BUILTIN_THEMES.join(", ")
+        )
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Settings;
+
+    #[test]
+    fn builtin_themes_render_the_emoji_before_the_title() {
+        for name in BUILTIN_THEMES {
+            assert!(load_theme(name, None).is_ok(), "{}", name);
+        }
+        let cats = load_theme("cats", None).unwrap();
+        assert_eq!(cats.busy.full_title(), "😼 Hunting bugs");
+        assert_eq!(cats.emoji(Status::ForgotTimer), "😼");
+        assert_eq!(cats.emoji(Status::NotWorking), "😴");
+        // Without an emoji the title stands alone.
+        let minimal = load_theme("minimal", None).unwrap();
+        assert_eq!(minimal.on_break.full_title(), "Break");
+    }
+
+    #[test]
+    fn unknown_themes_list_the_builtin_ones() {
+        let err = load_theme("disco", None).unwrap_err();
+        assert!(err.contains("traffic-light, minimal, cats"), "{}", err);
+    }
+
+    #[test]
+    fn themes_file_comes_before_the_builtin_themes() {
+        let path =
+            std::env::temp_dir().join(format!("amibussy-themes-{}.yaml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"Cats:
+  busy: {emoji: "🐈", title: Busy}
+  break: {title: Break}
+  not_working: {title: Off}
+"#,
+        )
+        .unwrap();
+        let theme = load_theme("cats", path.to_str());
+        std::fs::remove_file(&path).unwrap();
+        let theme = theme.unwrap();
+        assert_eq!(theme.busy.full_title(), "🐈 Busy");
+        assert_eq!(theme.on_break.full_title(), "Break");
+    }
+
+    #[test]
+    fn theme_fills_only_the_titles_that_arent_set() {
+        let settings =
+            Settings::for_tests("theme = \"traffic-light\"\nbusy_chat_status = \"Deep work\"");
+        assert_eq!(settings.busy_chat_status, "Deep work");
+        assert_eq!(settings.break_chat_status, "🟡 On a break");
+        assert_eq!(settings.not_working_status, "⚫ Off");
+    }
+}
@@ -0,0 +1,91 @@
+use anyhow::Result;
+use std::{
+    io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+use tokio::{
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+};
+use tokio_rustls::{rustls::ServerConfig, server::TlsStream, TlsAcceptor};
+use tokio_stream::Stream;
+use tracing::{debug, warn};
+
+/// How long a client gets to finish the TLS handshake.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A server config for the PEM certificate chain and private key.
+pub fn load_tls_config(cert_pem: &[u8], key_pem: &[u8]) -> Result<Arc<ServerConfig>> {
+    let certs = rustls_pemfile::certs(&mut &*cert_pem)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| anyhow::anyhow!("invalid certificate: {}", err))?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificate in the PEM");
+    }
+    let key = rustls_pemfile::private_key(&mut &*key_pem)
+        .map_err(|err| anyhow::anyhow!("invalid private key: {}", err))?
+        .ok_or_else(|| anyhow::anyhow!("no private key in the PEM"))?;
+
+    let mut config = ServerConfig::builder_with_provider(Arc::new(
+        tokio_rustls::rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()
+    .map_err(|err| anyhow::anyhow!("TLS setup failed: {}", err))?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .map_err(|err| anyhow::anyhow!("certificate and key don't go together: {}", err))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(Arc::new(config))
+}
+
+/// Connections from a TCP listener after their TLS handshake. Handshakes run concurrently,
+/// so a slow client doesn't hold up the others.
+pub struct TlsIncoming {
+    connections: mpsc::Receiver<TlsStream<TcpStream>>,
+}
+
+impl TlsIncoming {
+    /// Accepts on `listener` until the `TlsIncoming` is dropped.
+    pub fn new(listener: TcpListener, config: Arc<ServerConfig>) -> Self {
+        let acceptor = TlsAcceptor::from(config);
+        let (sender, connections) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let (stream, peer) = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok(accepted) => accepted,
+                        Err(err) => {
+                            warn!("Failed to accept a connection: {}", err);
+                            tokio::time::sleep(Duration::from_millis(100)).await;
+                            continue;
+                        }
+                    },
+                    _ = sender.closed() => break,
+                };
+                let acceptor = acceptor.clone();
+                let sender = sender.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                        Ok(Ok(stream)) => {
+                            let _ = sender.send(stream).await;
+                        }
+                        Ok(Err(err)) => debug!("TLS handshake with {} failed: {}", peer, err),
+                        Err(_) => debug!("TLS handshake with {} timed out", peer),
+                    }
+                });
+            }
+        });
+        TlsIncoming { connections }
+    }
+}
+
+impl Stream for TlsIncoming {
+    type Item = io::Result<TlsStream<TcpStream>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.connections.poll_recv(cx).map(|stream| stream.map(Ok))
+    }
+}
@@ -0,0 +1,273 @@
+use crate::{
+    acme::obtain_certificate,
+    config::{Settings, TunnelKind, TunnelRetrySettings},
+    sources::toggl_subscription::callback_url,
+    state::{get_unix_timestamp, AppState},
+    tls::{load_tls_config, TlsIncoming},
+};
+use anyhow::Result;
+use ngrok::{
+    config::{OauthOptions, TunnelBuilder},
+    tunnel::HttpTunnel,
+    Session,
+};
+use reqwest::{Client, StatusCode as ReqwesStatusCode};
+use std::{
+    collections::hash_map::RandomState, hash::BuildHasher, net::SocketAddr, process::Stdio,
+    sync::Arc, time::Duration,
+};
+use tokio::{
+    net::TcpListener,
+    process::{Child, Command},
+    time::interval,
+};
+use tokio_stream::wrappers::TcpListenerStream;
+use tracing::{error, info};
+
+/// The ngrok session, kept across listener restarts: connecting sessions is what ngrok
+/// rate-limits, while a session reconnects by itself.
+#[derive(Default)]
+pub struct NgrokSessions {
+    current: Option<(String, Session)>,
+}
+
+impl NgrokSessions {
+    /// The session for `authtoken`, connecting one if there's none yet or the token changed.
+    async fn get(&mut self, authtoken: &str) -> Result<Session> {
+        if let Some((token, session)) = &self.current {
+            if token == authtoken {
+                return Ok(session.clone());
+            }
+        }
+        let session = Session::builder().authtoken(authtoken).connect().await?;
+        self.current = Some((authtoken.to_string(), session.clone()));
+        Ok(session)
+    }
+}
+
+pub async fn start_ngrok_listener(
+    settings: &Settings,
+    sessions: &mut NgrokSessions,
+) -> Result<HttpTunnel> {
+    let session = sessions.get(&settings.ngrok_authtoken).await?;
+
+    let mut endpoint = session.http_endpoint();
+    endpoint = endpoint.domain(&settings.ngrok_domain);
+    if let Some(options) = &settings.ngrok_endpoint {
+        for cidr in &options.allow_cidrs {
+            endpoint = endpoint.allow_cidr(cidr);
+        }
+        for cidr in &options.deny_cidrs {
+            endpoint = endpoint.deny_cidr(cidr);
+        }
+        if let Some(basic_auth) = &options.basic_auth {
+            endpoint = endpoint.basic_auth(&basic_auth.username, &basic_auth.password);
+        }
+        if let Some(oauth) = &options.oauth {
+            let mut oauth_options = OauthOptions::new(&oauth.provider);
+            for email in &oauth.allow_emails {
+                oauth_options = oauth_options.allow_email(email);
+            }
+            for domain in &oauth.allow_domains {
+                oauth_options = oauth_options.allow_domain(domain);
+            }
+            endpoint = endpoint.oauth(oauth_options);
+        }
+        if let Some(ratio) = options.circuit_breaker {
+            endpoint = endpoint.circuit_breaker(ratio);
+        }
+    }
+
+    let listener = match endpoint.listen().await {
+        Ok(listener) => listener,
+        Err(err) => {
+            // The session may be what's broken; start over with a new one next time.
+            sessions.current = None;
+            return Err(anyhow::anyhow!(
+                "failed to open the ngrok endpoint: {}",
+                err
+            ));
+        }
+    };
+
+    info!(
+        "Ngrok tunnel started to listen on: {}",
+        &format!("https://{}/webhook", settings.ngrok_domain)
+    );
+
+    Ok(listener)
+}
+
+/// Listens on `tailscale.port` locally and runs `tailscale funnel` to expose it. Funnel stops
+/// when the returned child is dropped.
+pub fn start_tailscale_listener(settings: &Settings) -> Result<(TcpListenerStream, Child)> {
+    let tailscale = settings
+        .tailscale
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("tunnel: tailscale needs a `tailscale` section"))?;
+    let addr = SocketAddr::from(([127, 0, 0, 1], tailscale.port));
+    let incoming = std::net::TcpListener::bind(addr)
+        .and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            TcpListener::from_std(listener)
+        })
+        .map(TcpListenerStream::new)
+        .map_err(|err| anyhow::anyhow!("failed to listen on {}: {}", addr, err))?;
+    let funnel = Command::new(&tailscale.binary)
+        .args(["funnel", &tailscale.port.to_string()])
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|err| anyhow::anyhow!("failed to run {} funnel: {}", tailscale.binary, err))?;
+
+    info!(
+        "Tailscale Funnel started to forward {} to {}",
+        &format!("https://{}/webhook", tailscale.domain),
+        addr
+    );
+
+    Ok((incoming, funnel))
+}
+
+/// Exponential backoff with jitter between tunnel restarts, so that a rate-limited ngrok
+/// account isn't hit again every few seconds.
+#[derive(Debug, Default)]
+pub struct Backoff {
+    failures: u32,
+}
+
+impl Backoff {
+    /// Counts a failure and returns how long to wait before the next attempt: `initial_secs`,
+    /// doubling up to `max_secs`, cut by a random amount of up to half.
+    pub fn failed(&mut self, retry: &TunnelRetrySettings) -> Duration {
+        self.failures += 1;
+        let doublings = (self.failures - 1).min(16);
+        let millis = retry
+            .initial_secs
+            .saturating_mul(1 << doublings)
+            .min(retry.max_secs)
+            * 1000;
+        let jitter = RandomState::new().hash_one(self.failures) % (millis / 2 + 1);
+        Duration::from_millis(millis - jitter)
+    }
+
+    /// Consecutive failures so far.
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    pub fn reset(&mut self) {
+        self.failures = 0;
+    }
+}
+
+/// What `tunnel: direct` listens with.
+pub enum DirectListener {
+    /// Plain HTTP, for behind a reverse proxy that terminates TLS.
+    Plain(TcpListenerStream),
+    Tls {
+        incoming: TlsIncoming,
+        /// When an ACME certificate is due for renewal.
+        renew_at: Option<u64>,
+    },
+}
+
+/// Listens on `direct.listen`, with TLS from `direct.tls` or `direct.acme` if either is set.
+pub async fn start_direct_listener(settings: &Settings) -> Result<DirectListener> {
+    let direct = settings
+        .direct
+        .as_ref()
+        .ok_or_else(|| anyhow::anyhow!("tunnel: direct needs a `direct` section"))?;
+    let read = |path: &str| {
+        let path = shellexpand::tilde(path).to_string();
+        std::fs::read(&path).map_err(|err| anyhow::anyhow!("failed to read {}: {}", path, err))
+    };
+    let tls = match (&direct.tls, &direct.acme) {
+        (Some(files), _) => Some((
+            load_tls_config(&read(&files.cert)?, &read(&files.key)?)?,
+            None,
+        )),
+        (None, Some(acme)) => {
+            let certificate =
+                obtain_certificate(&direct.domain, acme, get_unix_timestamp()?).await?;
+            let config = load_tls_config(
+                certificate.cert_pem.as_bytes(),
+                certificate.key_pem.as_bytes(),
+            )?;
+            Some((config, Some(certificate.renew_at)))
+        }
+        (None, None) => None,
+    };
+
+    let addr: SocketAddr = direct
+        .listen
+        .parse()
+        .map_err(|err| anyhow::anyhow!("invalid direct.listen: {}", err))?;
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|err| anyhow::anyhow!("failed to listen on {}: {}", addr, err))?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    info!(
+        "Listening on {} for {}",
+        addr,
+        &format!("{}://{}/webhook", scheme, direct.domain)
+    );
+
+    Ok(match tls {
+        Some((config, renew_at)) => DirectListener::Tls {
+            incoming: TlsIncoming::new(listener, config),
+            renew_at,
+        },
+        None => DirectListener::Plain(TcpListenerStream::new(listener)),
+    })
+}
+
+pub async fn ngrok_healthcheck(state: AppState, shutdown_signal: Arc<tokio::sync::Notify>) {
+    let client = Client::new();
+    let mut interval = interval(Duration::from_secs(15));
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {},
+            _ = shutdown_signal.notified() => {
+                info!("Tearing down ngrok_healthcheck...");
+                break;
+            }
+        }
+
+        // The callback URL, so that `webhook_auth` lets the probe through.
+        let settings = state.settings();
+        let url = callback_url(&settings);
+        let mut request = client.get(&url);
+        if let Some((header, value)) = settings
+            .webhook_auth
+            .as_ref()
+            .and_then(|auth| auth.header.as_deref().zip(auth.header_value.as_deref()))
+        {
+            request = request.header(header, value);
+        }
+        // Behind `ngrok_endpoint` restrictions the edge may turn the probe away itself; that it
+        // answered at all shows the tunnel is up.
+        let restricted = settings.tunnel == TunnelKind::Ngrok
+            && settings
+                .ngrok_endpoint
+                .as_ref()
+                .is_some_and(|endpoint| endpoint.restricts_access());
+        let reachable = match request.send().await {
+            Ok(response) if restricted => !response.status().is_server_error(),
+            Ok(response) => response.status() == ReqwesStatusCode::OK,
+            Err(_) => false,
+        };
+        if !reachable {
+            error!("Tunnel seems to be down. Restarting listener...");
+            state
+                .diagnostics
+                .error("tunnel", format!("{} is unreachable", url));
+            shutdown_signal.notify_one();
+            break;
+        }
+        state
+            .diagnostics
+            .ok("tunnel", format!("{} is reachable", url));
+    }
+}
@@ -0,0 +1,48 @@
+//! The library on its own, the way another binary would use it: settings from a file, a state
+//! and its decision, without the daemon around them.
+
+use amibussy::{
+    clock::ManualClock,
+    config::Settings,
+    state::{AppState, Status, StatusSource},
+};
+use std::sync::{atomic::Ordering, Arc};
+
+const NOW: u64 = 1_700_000_000;
+
+const SETTINGS: &str = r#"
+bot_token = "test"
+chat_id = "-1001"
+minutes_till_afk = 10
+history_db = ":memory:"
+dry_run = true
+timezone = "UTC"
+busy_chat_status = "Busy"
+break_chat_status = "On a break"
+not_working_status = "Away"
+ngrok_authtoken = "test"
+ngrok_domain = "test.ngrok-free.app"
+"#;
+
+fn load_settings() -> Settings {
+    let path = std::env::temp_dir().join(format!("amibussy-library-{}.toml", std::process::id()));
+    std::fs::write(&path, SETTINGS).unwrap();
+    let settings = Settings::from_config(Some(&path));
+    std::fs::remove_file(&path).unwrap();
+    settings.unwrap()
+}
+
+#[test]
+fn decides_the_status_outside_the_daemon() {
+    let state = AppState::with_clock(load_settings(), Arc::new(ManualClock::new(NOW)));
+    assert_eq!(state.decide().status, Status::NotWorking);
+    assert_eq!(state.effective_title(), "Away");
+
+    state.timer_started_at.store(NOW - 60, Ordering::Relaxed);
+    let decision = state.decide();
+    assert_eq!(
+        (decision.status, decision.source),
+        (Status::Busy, StatusSource::Timer)
+    );
+    assert_eq!(state.effective_title(), "Busy");
+}